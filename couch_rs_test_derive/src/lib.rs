@@ -0,0 +1,151 @@
+//! Derive macros for [couch_rs_test](https://docs.rs/couch_rs_test); use them through the `derive`
+//! feature of that crate rather than depending on this crate directly.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{ext::IdentExt, spanned::Spanned, Data, DeriveInput, Expr, Fields};
+
+/// Generates builder-style constructors and a `seed` method for a typed fixture document, removing the
+/// per-entity boilerplate of typed test suites.
+///
+/// For a struct with named fields, the derive adds these inherent methods:
+///
+/// * `fixture()`, creating a document with every field set to its default: the expression given with
+///   `#[fixture(default = ...)]`, or else `Default::default()`. String literals are converted with
+///   `Into`, so that they can initialize `String` fields.
+/// * `with_<field>(value)` for every field, replacing the field by `value.into()`. Leading underscores
+///   are dropped from the method name, so `_id` is set with `with_id`.
+/// * `seed(repo)`, writing the document to the database of a `TestRepo` like `TestRepo::with_data` and
+///   returning it with the `_id` and `_rev` assigned by CouchDB.
+///
+/// `seed` requires the struct to implement `TypedCouchDocument`, for example through the
+/// `CouchDocument` derive of couch_rs. With an empty `_id` by default, CouchDB assigns a fresh id to
+/// each seeded document.
+///
+/// ```rust
+/// use couch_rs::{document::TypedCouchDocument, types::document::DocumentId, CouchDocument};
+/// use couch_rs_test::{TestFixture, TestRepo};
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize, CouchDocument, TestFixture)]
+/// struct User {
+///     #[serde(skip_serializing_if = "String::is_empty")]
+///     _id: DocumentId,
+///     #[serde(skip_serializing_if = "String::is_empty")]
+///     _rev: String,
+///     #[fixture(default = "alice")]
+///     name: String,
+///     #[fixture(default = 30)]
+///     age: u32,
+///     admin: bool,
+/// }
+///
+/// # async fn example(repo: &TestRepo) -> Result<(), couch_rs::error::CouchError> {
+/// let admin = User::fixture().with_name("root").with_admin(true).seed(repo).await?;
+/// assert!(!admin._rev.is_empty());
+/// # Ok(())
+/// # }
+/// ```
+#[proc_macro_derive(TestFixture, attributes(fixture))]
+pub fn derive_test_fixture(input: TokenStream) -> TokenStream {
+    let ast = syn::parse_macro_input!(input as DeriveInput);
+    impl_test_fixture(&ast)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn impl_test_fixture(ast: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &ast.ident;
+    let fields = match &ast.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new(
+                    ast.span(),
+                    "TestFixture can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new(
+                ast.span(),
+                "TestFixture can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut defaults = vec![];
+    let mut setters = vec![];
+    for field in fields.iter() {
+        // named fields always have an ident
+        let ident = field.ident.as_ref().expect("named field");
+        let ty = &field.ty;
+
+        let default = match fixture_default(field)? {
+            Some(
+                expr @ Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(_),
+                    ..
+                }),
+            ) => quote! { ::core::convert::Into::into(#expr) },
+            Some(expr) => quote! { #expr },
+            None => quote! { ::core::default::Default::default() },
+        };
+        defaults.push(quote! { #ident: #default });
+
+        let field_name = ident.unraw().to_string();
+        let setter = format_ident!("with_{}", field_name.trim_start_matches('_'));
+        let doc = format!("Set `{}` of this fixture to `value`.", field_name);
+        setters.push(quote! {
+            #[doc = #doc]
+            pub fn #setter(mut self, value: impl ::core::convert::Into<#ty>) -> Self {
+                self.#ident = value.into();
+                self
+            }
+        });
+    }
+
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let fixture_doc = format!(
+        "Create a `{}` fixture with every field set to its default.",
+        name
+    );
+    Ok(quote! {
+        impl #impl_generics #name #ty_generics #where_clause {
+            #[doc = #fixture_doc]
+            pub fn fixture() -> Self {
+                #name {
+                    #(#defaults,)*
+                }
+            }
+
+            #(#setters)*
+
+            /// Write this fixture to the database of `repo`, returning it with the `_id` and `_rev`
+            /// assigned by CouchDB.
+            pub async fn seed(
+                self,
+                repo: &::couch_rs_test::TestRepo,
+            ) -> ::core::result::Result<Self, ::couch_rs_test::__private::CouchError> {
+                ::couch_rs_test::__private::seed(repo, self).await
+            }
+        }
+    })
+}
+
+// reads the expression of a `#[fixture(default = ...)]` attribute on `field`
+fn fixture_default(field: &syn::Field) -> syn::Result<Option<Expr>> {
+    let mut default = None;
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("fixture")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("default") {
+                default = Some(meta.value()?.parse::<Expr>()?);
+                Ok(())
+            } else {
+                Err(meta.error("unsupported fixture attribute; expected `default = ...`"))
+            }
+        })?;
+    }
+    Ok(default)
+}
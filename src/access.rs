@@ -0,0 +1,165 @@
+use std::ops::BitOr;
+
+use couch_rs::error::CouchError;
+use serde_json::{json, Value};
+
+use crate::{
+    compat::{Method, StatusCode},
+    naming::encode_db_name,
+    TestRepo,
+};
+
+/// Operations checked by [TestRepo::assert_access]. `Access::Read | Access::Write` checks both.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    /// Reading documents, checked by listing `_all_docs`.
+    Read,
+    /// Writing documents, checked by creating a probe document.
+    Write,
+    /// Both reading and writing.
+    ReadWrite,
+}
+
+impl Access {
+    fn includes(&self, other: Access) -> bool {
+        *self == other || *self == Access::ReadWrite
+    }
+}
+
+impl BitOr for Access {
+    type Output = Access;
+
+    fn bitor(self, other: Access) -> Access {
+        match self == other {
+            true => self,
+            false => Access::ReadWrite,
+        }
+    }
+}
+
+/// The outcome [TestRepo::assert_access] expects for each checked operation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessOutcome {
+    /// The operation succeeds.
+    Allow,
+    /// The operation is refused with status 401 or 403.
+    Deny,
+}
+
+impl TestRepo {
+    /// Asserts that the user `username`, authenticating with `password`, is allowed or denied, as given
+    /// by `expected`, each operation of `access` on the database associated with this instance. This
+    /// keeps tests of security objects and validation functions concise.
+    ///
+    /// Reading is checked by listing `_all_docs` and writing by creating a probe document, which is
+    /// deleted again with the credentials of this instance when the write succeeds. A write refused by a
+    /// `validate_doc_update` function counts as denied.
+    ///
+    /// ```rust
+    /// # use couch_rs_test::{Access, AccessOutcome};
+    /// # async fn example(repo: &couch_rs_test::TestRepo) {
+    /// repo.assert_access("reporter", "secret", Access::Read, AccessOutcome::Allow)
+    ///     .await;
+    /// repo.assert_access("reporter", "secret", Access::Write, AccessOutcome::Deny)
+    ///     .await;
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if an operation has another outcome than `expected`, or fails for another reason than
+    /// lacking permission.
+    pub async fn assert_access(
+        &self,
+        username: &str,
+        password: &str,
+        access: Access,
+        expected: AccessOutcome,
+    ) {
+        for operation in [Access::Read, Access::Write] {
+            if !access.includes(operation) {
+                continue;
+            }
+
+            let outcome = self
+                .try_as(username, password, operation)
+                .await
+                .unwrap_or_else(|e| {
+                    panic!(
+                        "Failed to check {:?} access of {} to {}: {}",
+                        operation,
+                        username,
+                        self.db.name(),
+                        e
+                    )
+                });
+            if outcome != expected {
+                panic!(
+                    "Expected {:?} access of {} to database {} to be {}, but it was {}",
+                    operation,
+                    username,
+                    self.db.name(),
+                    describe(expected),
+                    describe(outcome)
+                );
+            }
+        }
+    }
+
+    // attempts the representative operation for `operation` as the given user
+    async fn try_as(
+        &self,
+        username: &str,
+        password: &str,
+        operation: Access,
+    ) -> Result<AccessOutcome, CouchError> {
+        let client = self.cfg.client_as(&self.cfg.uri, username, password)?;
+        let db_name = encode_db_name(&self.cfg.db_name);
+
+        let response = match operation {
+            Access::Write => {
+                client
+                    .req(Method::POST, &db_name, None)
+                    .body(json!({ "couch_rs_test_access_probe": username }).to_string())
+                    .send()
+                    .await?
+            }
+            _ => {
+                client
+                    .req(Method::GET, &format!("{}/_all_docs", db_name), None)
+                    .query(&[("limit", "1")])
+                    .send()
+                    .await?
+            }
+        };
+
+        match response.status() {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Ok(AccessOutcome::Deny),
+            status if status.is_success() => {
+                if operation == Access::Write {
+                    let created: Value = response.json().await?;
+                    if let (Some(id), Some(rev)) = (created["id"].as_str(), created["rev"].as_str())
+                    {
+                        self.db.remove(&json!({ "_id": id, "_rev": rev })).await;
+                    }
+                }
+                Ok(AccessOutcome::Allow)
+            }
+            status => Err(CouchError::new(
+                format!(
+                    "unexpected response {}: {}",
+                    status,
+                    response.text().await.unwrap_or_default()
+                ),
+                status,
+            )),
+        }
+    }
+}
+
+fn describe(outcome: AccessOutcome) -> &'static str {
+    match outcome {
+        AccessOutcome::Allow => "allowed",
+        AccessOutcome::Deny => "denied",
+    }
+}
@@ -0,0 +1,216 @@
+use std::{future::Future, sync::Mutex};
+
+use couch_rs::{error::CouchError, types::find::FindQuery};
+use serde_json::Value;
+
+use crate::{compat::StatusCode, selector, store::DocStore, TestRepo, TestRepoConfig};
+
+/// The operations repository code under test needs from a test database: create, seed, query and
+/// destroy.
+///
+/// [TestRepo] implements this trait against a real CouchDB server, and [MemoryBackend] keeps documents
+/// in memory. Writing test setup against `TestBackend` lets the same tests run as fast unit tests
+/// without any CouchDB server, and as integration tests against a real one.
+///
+/// ```rust
+/// # use serde_json::json;
+/// use couch_rs_test::{MemoryBackend, TestBackend, TestRepoConfig};
+///
+/// async fn count_admins<B: TestBackend>(backend: &B) -> usize {
+///     backend
+///         .find(&json!({"role": "admin"}))
+///         .await
+///         .expect("query failed")
+///         .len()
+/// }
+///
+/// # async fn example() -> Result<(), couch_rs::error::CouchError> {
+/// let cfg = TestRepoConfig::new("http://localhost:5984", "admin", "password", "users");
+/// let backend = MemoryBackend::create(cfg).await?;
+/// backend.seed(&mut [json!({"role": "admin"}), json!({"role": "user"})]).await?;
+/// assert_eq!(count_admins(&backend).await, 1);
+/// backend.destroy().await;
+/// # Ok(())
+/// # }
+/// ```
+pub trait TestBackend: Sized + Send + Sync {
+    /// Creates a new, empty test database for `config`.
+    fn create(config: TestRepoConfig) -> impl Future<Output = Result<Self, CouchError>> + Send;
+
+    /// Writes `docs` to the database like [TestRepo::with_data], setting `_id` and `_rev` on each
+    /// document written, and returns the number of documents processed.
+    fn seed(&self, docs: &mut [Value]) -> impl Future<Output = Result<usize, CouchError>> + Send;
+
+    /// Reads the document with the given id, returning `None` if it does not exist.
+    fn get(&self, id: &str) -> impl Future<Output = Result<Option<Value>, CouchError>> + Send;
+
+    /// Returns all documents except design documents, in id order.
+    fn all_docs(&self) -> impl Future<Output = Result<Vec<Value>, CouchError>> + Send;
+
+    /// Returns all documents matching a Mango `selector`, in id order.
+    fn find(&self, selector: &Value)
+        -> impl Future<Output = Result<Vec<Value>, CouchError>> + Send;
+
+    /// Destroys the database.
+    fn destroy(self) -> impl Future<Output = ()> + Send {
+        async move { drop(self) }
+    }
+}
+
+impl TestBackend for TestRepo {
+    async fn create(config: TestRepoConfig) -> Result<TestRepo, CouchError> {
+        TestRepo::new(config)
+            .await
+            .map_err(|e| CouchError::new(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR))
+    }
+
+    // in a shared database, documents are written to and read from the namespace of this instance,
+    // and handed back with the ids they were given
+    async fn seed(&self, docs: &mut [Value]) -> Result<usize, CouchError> {
+        let seeded = self.with_data(docs).await?;
+        for doc in docs.iter_mut() {
+            self.strip_namespace(doc);
+        }
+        Ok(seeded)
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<Value>, CouchError> {
+        match self.db.get_raw(&self.namespaced_id(id)).await {
+            Ok(mut doc) => {
+                self.strip_namespace(&mut doc);
+                Ok(Some(doc))
+            }
+            Err(e) if e.is_not_found() => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn all_docs(&self) -> Result<Vec<Value>, CouchError> {
+        let mut docs = self.db.get_all_raw().await?.rows;
+        // couch_rs happens to leave design documents out of the rows; the trait promises it regardless
+        docs.retain(|doc| {
+            !doc["_id"]
+                .as_str()
+                .is_some_and(|id| id.starts_with("_design/"))
+        });
+        if let Some(namespace) = self.namespace() {
+            docs.retain(|doc| {
+                doc["_id"]
+                    .as_str()
+                    .is_some_and(|id| id.starts_with(namespace))
+            });
+        }
+        for doc in docs.iter_mut() {
+            self.strip_namespace(doc);
+        }
+        Ok(docs)
+    }
+
+    async fn find(&self, selector: &Value) -> Result<Vec<Value>, CouchError> {
+        let selector = self.namespaced_selector(selector.clone());
+        let query = FindQuery::new(selector).limit(u64::from(u32::MAX));
+        let mut docs = self.db.find_raw(&query).await?.rows;
+        for doc in docs.iter_mut() {
+            self.strip_namespace(doc);
+        }
+        docs.sort_by(|a, b| a["_id"].as_str().cmp(&b["_id"].as_str()));
+        Ok(docs)
+    }
+}
+
+/// A [TestBackend] keeping documents in memory, for unit tests of repository code without a CouchDB
+/// server. Revisions are tracked so that conflicting writes fail as they would on CouchDB; queries
+/// support a subset of Mango selectors covering equality, comparisons, `$in`, `$exists` and the
+/// combination operators.
+#[derive(Debug)]
+pub struct MemoryBackend {
+    name: String,
+    docs: Mutex<DocStore>,
+}
+
+impl MemoryBackend {
+    /// The name of the emulated database, as configured.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl TestBackend for MemoryBackend {
+    async fn create(config: TestRepoConfig) -> Result<MemoryBackend, CouchError> {
+        Ok(MemoryBackend {
+            name: config.db_name,
+            docs: Mutex::new(DocStore::default()),
+        })
+    }
+
+    async fn seed(&self, docs: &mut [Value]) -> Result<usize, CouchError> {
+        let mut store = self.docs.lock().unwrap_or_else(|e| e.into_inner());
+        for doc in docs.iter_mut() {
+            // failed writes are skipped, as failed documents of a bulk write are on CouchDB
+            let _ = store.write(doc);
+        }
+        Ok(docs.len())
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<Value>, CouchError> {
+        Ok(self
+            .docs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(id)
+            .cloned())
+    }
+
+    async fn all_docs(&self) -> Result<Vec<Value>, CouchError> {
+        self.find(&Value::Object(Default::default())).await
+    }
+
+    async fn find(&self, selector: &Value) -> Result<Vec<Value>, CouchError> {
+        Ok(self
+            .docs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .live()
+            .filter(|(id, _)| !id.starts_with("_design/"))
+            .map(|(_, doc)| doc)
+            .filter(|doc| selector::matches(doc, selector))
+            .cloned()
+            .collect())
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    // the ids `backend` lists after seeding a design document and two regular documents
+    async fn seeded_ids<B: TestBackend>(config: TestRepoConfig) -> Vec<String> {
+        let backend = B::create(config).await.unwrap();
+        let mut docs = [
+            json!({"_id": "_design/app", "views": {}}),
+            json!({"_id": "alice", "role": "admin"}),
+            json!({"_id": "bob", "role": "user"}),
+        ];
+        backend.seed(&mut docs).await.unwrap();
+        let ids = backend
+            .all_docs()
+            .await
+            .unwrap()
+            .iter()
+            .map(|doc| doc["_id"].as_str().unwrap().to_string())
+            .collect();
+        backend.destroy().await;
+        ids
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn all_docs_leaves_out_design_documents_on_both_backends() {
+        let config = TestRepoConfig::new("", "", "", "backend").with_mock();
+        let in_memory = seeded_ids::<MemoryBackend>(config.clone()).await;
+        let mocked = seeded_ids::<TestRepo>(config).await;
+        assert_eq!(in_memory, ["alice", "bob"]);
+        assert_eq!(mocked, in_memory);
+    }
+}
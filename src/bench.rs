@@ -0,0 +1,407 @@
+//! Latency measurements of queries against a seeded test database.
+
+use std::{
+    error::Error,
+    fmt,
+    fs::OpenOptions,
+    future::Future,
+    io::Write,
+    time::{Duration, Instant},
+};
+
+use couch_rs::error::CouchError;
+use futures_util::future::try_join_all;
+use serde_json::{json, Value};
+
+use crate::{compat::Method, naming::encode_db_name, TestRepo};
+
+/// Environment variable naming a file that every [BenchReport] is appended to, as one JSON object per
+/// line, so that query performance can be tracked across runs, for example per pull request in CI.
+const OUTPUT_ENV_VAR: &str = "COUCH_TEST_BENCH_OUTPUT";
+
+/// Latency percentiles of a query measured by [TestRepo::bench_query].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BenchReport {
+    /// Name of the benchmark.
+    pub name: String,
+    /// Number of documents in the database when the benchmark ran.
+    pub docs: u64,
+    /// Number of timed runs of the query.
+    pub iterations: usize,
+    /// Fastest run.
+    pub min: Duration,
+    /// Median run.
+    pub p50: Duration,
+    /// 90th percentile.
+    pub p90: Duration,
+    /// 99th percentile.
+    pub p99: Duration,
+    /// Slowest run.
+    pub max: Duration,
+    /// Arithmetic mean of all runs.
+    pub mean: Duration,
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({} runs, {} docs): min {:?}, p50 {:?}, p90 {:?}, p99 {:?}, max {:?}, mean {:?}",
+            self.name,
+            self.iterations,
+            self.docs,
+            self.min,
+            self.p50,
+            self.p90,
+            self.p99,
+            self.max,
+            self.mean
+        )
+    }
+}
+
+impl TestRepo {
+    /// Prepares the database associated with this instance for a measured section: queries every view
+    /// and Mango index of its design documents once, so that CouchDB builds any index that is out of
+    /// date, and opens as many concurrent connections as the configured
+    /// [max concurrency](crate::TestRepoConfig::with_max_concurrency), so that the client's connection
+    /// pool is filled. Without this, the first iterations of a performance test measure index builds
+    /// and connection setup rather than the code under test.
+    ///
+    /// ```rust
+    /// # async fn example(repo: &couch_rs_test::TestRepo) -> Result<(), couch_rs::error::CouchError> {
+    /// repo.warm_up().await?;
+    /// // measured section
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn warm_up(&self) -> Result<(), CouchError> {
+        let db_name = encode_db_name(&self.cfg.db_name);
+        for name in self.design_doc_names().await? {
+            let ddoc = self
+                .get_json(&format!("{}/_design/{}", db_name, encode_db_name(&name)))
+                .await?;
+            let views = ddoc["views"].as_object().cloned().unwrap_or_default();
+            for (view, definition) in views.iter() {
+                match ddoc["language"].as_str() {
+                    Some("query") => self.warm_up_index(&name, view, definition).await?,
+                    _ => {
+                        self.get_json(&format!(
+                            "{}/_design/{}/_view/{}?limit=0",
+                            db_name,
+                            encode_db_name(&name),
+                            encode_db_name(view)
+                        ))
+                        .await?;
+                    }
+                }
+            }
+        }
+
+        let connections = (0..self.cfg.max_concurrency.max(1)).map(|_| self.get_json(&db_name));
+        try_join_all(connections).await?;
+        log::debug!("Warmed up database {}", self.cfg.db_name);
+        Ok(())
+    }
+
+    // queries the Mango index `index` of the design document `ddoc`, which builds it; text indexes and
+    // indexes without fields are skipped
+    async fn warm_up_index(
+        &self,
+        ddoc: &str,
+        index: &str,
+        definition: &Value,
+    ) -> Result<(), CouchError> {
+        let field = definition["options"]["def"]["fields"]
+            .as_array()
+            .and_then(|fields| fields.first())
+            .and_then(|field| match field {
+                Value::String(name) => Some(name.clone()),
+                Value::Object(sort) => sort.keys().next().cloned(),
+                _ => None,
+            });
+        let field = match field {
+            Some(field) if definition["map"].get("fields").is_some() => field,
+            _ => return Ok(()),
+        };
+
+        let query = json!({
+            "selector": {field: {"$exists": true}},
+            "use_index": [ddoc, index],
+            "limit": 1,
+        });
+        self.client
+            .req(
+                Method::POST,
+                &format!("{}/_find", encode_db_name(&self.cfg.db_name)),
+                None,
+            )
+            .body(query.to_string())
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Runs `query` `iterations` times in sequence against the database associated with this instance,
+    /// after one untimed warm-up run, and reports the latency percentiles of the timed runs. The report
+    /// is logged, and appended to the file named by the `COUCH_TEST_BENCH_OUTPUT` environment variable
+    /// when it is set.
+    ///
+    /// ```rust
+    /// # use couch_rs::types::find::FindQuery;
+    /// # use serde_json::json;
+    /// # async fn example(repo: &couch_rs_test::TestRepo) {
+    /// let report = repo
+    ///     .bench_query("orders by customer", 100, || async {
+    ///         repo.db.find_raw(&FindQuery::new(json!({"customer": "alice"}))).await
+    ///     })
+    ///     .await
+    ///     .unwrap();
+    /// assert!(report.p99 < std::time::Duration::from_millis(50));
+    /// # }
+    /// ```
+    ///
+    /// The first error returned by `query` ends the benchmark and is returned.
+    pub async fn bench_query<F, Fut, T, E>(
+        &self,
+        name: &str,
+        iterations: usize,
+        mut query: F,
+    ) -> Result<BenchReport, Box<dyn Error>>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: Into<Box<dyn Error>>,
+    {
+        let docs = self.client.get_info(&self.cfg.db_name).await?.doc_count;
+
+        query().await.map_err(Into::into)?;
+        let mut runs = Vec::with_capacity(iterations);
+        for _ in 0..iterations {
+            let start = Instant::now();
+            query().await.map_err(Into::into)?;
+            runs.push(start.elapsed());
+        }
+
+        let report = report(name, docs, runs);
+        log::info!("Benchmark {}", report);
+        if let Ok(path) = std::env::var(OUTPUT_ENV_VAR) {
+            if let Err(e) = append_report(&path, &report) {
+                log::error!("Failed to write benchmark {} to {}: {}", name, path, e);
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// Registers `query` as a benchmark named `name` with `criterion`, running it on `runtime`, so queries
+/// against a test database can be measured by an existing criterion suite. Errors returned by `query`
+/// abort the benchmark.
+///
+/// ```rust,no_run
+/// # use couch_rs::types::find::FindQuery;
+/// # use serde_json::json;
+/// # fn example(c: &mut criterion::Criterion, runtime: &tokio::runtime::Runtime, repo: &couch_rs_test::TestRepo) {
+/// couch_rs_test::bench::criterion_bench(c, runtime, "orders by customer", || async {
+///     repo.db.find_raw(&FindQuery::new(json!({"customer": "alice"}))).await
+/// });
+/// # }
+/// ```
+///
+/// Only available with the `criterion` feature.
+#[cfg(feature = "criterion")]
+pub fn criterion_bench<F, Fut, T, E>(
+    criterion: &mut criterion::Criterion,
+    runtime: &tokio::runtime::Runtime,
+    name: &str,
+    query: F,
+) where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: fmt::Display,
+{
+    criterion.bench_function(name, |b| {
+        b.to_async(runtime).iter(|| async {
+            if let Err(e) = query().await {
+                panic!("Benchmarked query failed: {}", e);
+            }
+        })
+    });
+}
+
+/// A seeded test database for criterion benchmarks, set up once and reused across all iterations so
+/// that benchmarks measure the queries under test rather than the creation, seeding and destruction of
+/// databases.
+///
+/// Setup is synchronous: the instance owns the tokio runtime it creates the database on and that its
+/// benchmarks run on, so it can be built at the top of a plain criterion benchmark function. The database
+/// is destroyed when the instance is dropped, after the benchmarks and outside of any measurement.
+///
+/// ```rust,no_run
+/// # use couch_rs::types::find::FindQuery;
+/// # use serde_json::json;
+/// use couch_rs_test::{bench::BenchRepo, TestRepoConfig};
+///
+/// fn orders(c: &mut criterion::Criterion) {
+///     let cfg = TestRepoConfig::new("http://localhost:5984", "admin", "password", "orders");
+///     let repo = BenchRepo::new(cfg).expect("failed to create benchmark database");
+///     repo.with_data(&mut [json!({"customer": "alice"}), json!({"customer": "bob"})])
+///         .unwrap();
+///
+///     repo.bench(c, "orders by customer", |repo| async move {
+///         repo.db.find_raw(&FindQuery::new(json!({"customer": "alice"}))).await
+///     });
+/// }
+/// ```
+///
+/// The methods of this type block the calling thread, so they must not be called from within an async
+/// runtime.
+///
+/// Only available with the `criterion` feature.
+#[cfg(feature = "criterion")]
+pub struct BenchRepo {
+    // taken when dropped, so the repo is destroyed before the runtime shuts down
+    repo: Option<TestRepo>,
+    runtime: tokio::runtime::Runtime,
+}
+
+#[cfg(feature = "criterion")]
+impl BenchRepo {
+    /// Creates the benchmark database like [TestRepo::new], blocking until it has been created.
+    pub fn new(cfg: crate::TestRepoConfig) -> Result<BenchRepo, Box<dyn Error>> {
+        // the drop watcher of the repo needs a worker thread of its own
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()?;
+        let repo = runtime.block_on(TestRepo::new(cfg))?;
+        Ok(BenchRepo {
+            repo: Some(repo),
+            runtime,
+        })
+    }
+
+    /// The wrapped repo, for seeding it or running setup not covered by this type.
+    pub fn repo(&self) -> &TestRepo {
+        self.repo.as_ref().expect("repo is only taken when dropped")
+    }
+
+    /// The runtime the benchmarks of this instance run on.
+    pub fn runtime(&self) -> &tokio::runtime::Runtime {
+        &self.runtime
+    }
+
+    /// Seeds the database like [TestRepo::with_data], blocking until the documents have been written.
+    pub fn with_data<S: couch_rs::document::TypedCouchDocument>(
+        &self,
+        data: &mut [S],
+    ) -> Result<usize, couch_rs::error::CouchError> {
+        self.runtime.block_on(self.repo().with_data(data))
+    }
+
+    /// Registers `query` as a benchmark named `name` with `criterion`, running it against the
+    /// database of this instance, which is shared by all iterations. Errors returned by `query` abort
+    /// the benchmark.
+    pub fn bench<'a, F, Fut, T, E>(
+        &'a self,
+        criterion: &mut criterion::Criterion,
+        name: &str,
+        query: F,
+    ) where
+        F: Fn(&'a TestRepo) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: fmt::Display,
+    {
+        let repo = self.repo();
+        criterion_bench(criterion, &self.runtime, name, || query(repo));
+    }
+
+    /// Registers `operation` as a benchmark named `name` with `criterion` like [BenchRepo::bench], for
+    /// operations that change the database, such as writes. `reset` runs before every iteration to
+    /// restore the state `operation` expects, for example by deleting the documents it creates, and is
+    /// not measured.
+    pub fn bench_with_reset<'a, R, RFut, F, Fut, T, E>(
+        &'a self,
+        criterion: &mut criterion::Criterion,
+        name: &str,
+        reset: R,
+        operation: F,
+    ) where
+        R: Fn(&'a TestRepo) -> RFut,
+        RFut: Future<Output = Result<(), E>>,
+        F: Fn(&'a TestRepo) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: fmt::Display,
+    {
+        let repo = self.repo();
+        let (reset, operation) = (&reset, &operation);
+        criterion.bench_function(name, |b| {
+            b.to_async(&self.runtime)
+                .iter_custom(|iterations| async move {
+                    let mut measured = Duration::ZERO;
+                    for _ in 0..iterations {
+                        if let Err(e) = reset(repo).await {
+                            panic!("Resetting benchmark database failed: {}", e);
+                        }
+                        let start = Instant::now();
+                        if let Err(e) = operation(repo).await {
+                            panic!("Benchmarked operation failed: {}", e);
+                        }
+                        measured += start.elapsed();
+                    }
+                    measured
+                })
+        });
+    }
+}
+
+#[cfg(feature = "criterion")]
+impl Drop for BenchRepo {
+    fn drop(&mut self) {
+        let _runtime = self.runtime.enter();
+        drop(self.repo.take());
+    }
+}
+
+fn report(name: &str, docs: u64, mut runs: Vec<Duration>) -> BenchReport {
+    runs.sort();
+    // nearest-rank percentile of the sorted runs
+    let percentile = |p: usize| match runs.len() {
+        0 => Duration::ZERO,
+        n => runs[(n * p).div_ceil(100).clamp(1, n) - 1],
+    };
+    let total: Duration = runs.iter().sum();
+
+    BenchReport {
+        name: name.to_string(),
+        docs,
+        iterations: runs.len(),
+        min: runs.first().copied().unwrap_or_default(),
+        p50: percentile(50),
+        p90: percentile(90),
+        p99: percentile(99),
+        max: runs.last().copied().unwrap_or_default(),
+        mean: match runs.len() {
+            0 => Duration::ZERO,
+            n => total / n as u32,
+        },
+    }
+}
+
+fn append_report(path: &str, report: &BenchReport) -> Result<(), Box<dyn Error>> {
+    let line = json!({
+        "name": report.name,
+        "docs": report.docs,
+        "iterations": report.iterations,
+        "min_us": report.min.as_micros() as u64,
+        "p50_us": report.p50.as_micros() as u64,
+        "p90_us": report.p90.as_micros() as u64,
+        "p99_us": report.p99.as_micros() as u64,
+        "max_us": report.max.as_micros() as u64,
+        "mean_us": report.mean.as_micros() as u64,
+    });
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", line)?;
+    Ok(())
+}
@@ -0,0 +1,136 @@
+//! Deletes test databases created by couch_rs_test, for CI jobs that clean up after their test runs.
+//!
+//! ```text
+//! couch-rs-test-clean --uri http://localhost:5984 --username admin --password secret \
+//!     [--prefix NAME] [--older-than DURATION] [--dry-run]
+//! ```
+//!
+//! Only databases carrying the marker written by couch_rs_test are deleted. `--prefix` restricts the
+//! deletion to databases created for that database name, after the `COUCH_TEST_PREFIX` prefix when that
+//! is set, as [purge_stale] does; `--older-than` (like `90s`, `30m`, `2h` or `1d`) to databases created longer ago. Credentials
+//! default to the `COUCHDB_USER` and `COUCHDB_PASSWORD` environment variables.
+
+use std::{process::ExitCode, time::Duration};
+
+use couch_rs_test::{find_stale, purge_stale, TestRepoConfig};
+
+const USAGE: &str = "usage: couch-rs-test-clean --uri URI [--username USER] [--password PASSWORD] \
+                     [--prefix NAME] [--older-than DURATION] [--dry-run]";
+
+struct Args {
+    uri: String,
+    username: String,
+    password: String,
+    prefix: String,
+    older_than: Duration,
+    dry_run: bool,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        uri: String::new(),
+        username: std::env::var("COUCHDB_USER").unwrap_or_default(),
+        password: std::env::var("COUCHDB_PASSWORD").unwrap_or_default(),
+        prefix: String::new(),
+        older_than: Duration::ZERO,
+        dry_run: false,
+    };
+
+    let mut argv = std::env::args().skip(1);
+    while let Some(arg) = argv.next() {
+        let mut value = || argv.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--uri" => args.uri = value()?,
+            "--username" => args.username = value()?,
+            "--password" => args.password = value()?,
+            "--prefix" => args.prefix = value()?,
+            "--older-than" => args.older_than = parse_duration(&value()?)?,
+            "--dry-run" => args.dry_run = true,
+            "--help" | "-h" => return Err(String::new()),
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+
+    if args.uri.is_empty() {
+        return Err("--uri is required".to_string());
+    }
+    Ok(args)
+}
+
+fn parse_duration(text: &str) -> Result<Duration, String> {
+    let invalid = || {
+        format!(
+            "invalid duration {}; use forms like 90s, 30m, 2h or 1d",
+            text
+        )
+    };
+    let unit_at = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let amount: u64 = text[..unit_at].parse().map_err(|_| invalid())?;
+    let seconds = match &text[unit_at..] {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    let seconds = amount.checked_mul(seconds).ok_or_else(invalid)?;
+    Ok(Duration::from_secs(seconds))
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            if !e.is_empty() {
+                eprintln!("{}", e);
+            }
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let config = TestRepoConfig::new(&args.uri, &args.username, &args.password, &args.prefix);
+
+    let deleted = match args.dry_run {
+        true => find_stale(&config, args.older_than).await,
+        false => purge_stale(&config, args.older_than).await,
+    };
+    match deleted {
+        Ok(names) => {
+            for name in names.iter() {
+                println!("{}", name);
+            }
+            let verb = if args.dry_run {
+                "Would delete"
+            } else {
+                "Deleted"
+            };
+            eprintln!("{} {} test databases", verb, names.len());
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Failed to clean up test databases: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_duration_reads_units() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("30m"), Ok(Duration::from_secs(30 * 60)));
+        assert_eq!(parse_duration("1d"), Ok(Duration::from_secs(24 * 60 * 60)));
+        assert!(parse_duration("2w").is_err());
+    }
+
+    #[test]
+    fn parse_duration_rejects_overflow() {
+        assert!(parse_duration(&format!("{}d", u64::MAX / 60)).is_err());
+    }
+}
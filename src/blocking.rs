@@ -0,0 +1,75 @@
+use std::{error::Error, future::Future};
+
+use couch_rs::{database::Database, document::TypedCouchDocument, error::CouchError};
+use tokio::runtime::{Builder, Runtime};
+
+use crate::{TestRepo, TestRepoConfig};
+
+/// A [TestRepo] for tests that are not async, such as plain `#[test]` functions or binaries that do
+/// not run a tokio runtime. It owns a runtime on which the repo is created, used and destroyed, so the
+/// database lifecycle is managed automatically as with [TestRepo].
+///
+/// ```rust
+/// use couch_rs_test::{SyncTestRepo, TestRepoConfig};
+/// use serde_json::json;
+///
+/// fn seeded(cfg: TestRepoConfig) -> SyncTestRepo {
+///     let repo = SyncTestRepo::new(cfg).expect("failed to create test database");
+///     repo.with_data(&mut [json!({"some": "data"})]).unwrap();
+///     repo
+/// }
+/// ```
+///
+/// The methods of this type block the calling thread, so they must not be called from within an async
+/// runtime. Any async helper of [TestRepo] can be used through [SyncTestRepo::block_on].
+///
+/// Only available with the `blocking` feature.
+pub struct SyncTestRepo {
+    // taken when dropped, so the repo is destroyed before the runtime shuts down
+    repo: Option<TestRepo>,
+    runtime: Runtime,
+}
+
+impl SyncTestRepo {
+    /// Creates a new instance like [TestRepo::new], blocking until the database has been created.
+    pub fn new(cfg: TestRepoConfig) -> Result<SyncTestRepo, Box<dyn Error>> {
+        // the drop watcher of the repo needs a worker thread of its own
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()?;
+        let repo = runtime.block_on(TestRepo::new(cfg))?;
+        Ok(SyncTestRepo {
+            repo: Some(repo),
+            runtime,
+        })
+    }
+
+    /// The wrapped repo, for helpers used through [SyncTestRepo::block_on].
+    pub fn repo(&self) -> &TestRepo {
+        self.repo.as_ref().expect("repo is only taken when dropped")
+    }
+
+    /// The database associated with this instance.
+    pub fn db(&self) -> &Database {
+        &self.repo().db
+    }
+
+    /// Pushes data to the database associated with this instance, like [TestRepo::with_data].
+    pub fn with_data<S: TypedCouchDocument>(&self, data: &mut [S]) -> Result<usize, CouchError> {
+        self.block_on(self.repo().with_data(data))
+    }
+
+    /// Runs `future` to completion on the runtime of this instance, for example a call to an async
+    /// helper of [SyncTestRepo::repo] or a query on [SyncTestRepo::db].
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+}
+
+impl Drop for SyncTestRepo {
+    fn drop(&mut self) {
+        let _runtime = self.runtime.enter();
+        drop(self.repo.take());
+    }
+}
@@ -0,0 +1,142 @@
+use couch_rs::{
+    document::TypedCouchDocument,
+    error::CouchError,
+    types::document::{DocumentCreatedDetails, DocumentCreatedResult},
+};
+use serde_json::{json, Value};
+
+use crate::{
+    compat::{Method, StatusCode},
+    naming::{encode_db_name, random_chars},
+    secret, TestRepo,
+};
+
+impl TestRepo {
+    /// Writes `docs` with one `_bulk_docs` request like
+    /// [couch_rs::database::bulk_docs](https://docs.rs/couch_rs/latest/couch_rs/database/struct.Database.html#method.bulk_docs),
+    /// retrying the request as set with [TestRepoConfig::with_retry](crate::TestRepoConfig::with_retry).
+    /// Unlike couch_rs, the status of a failed request is kept, so that transient failures such as
+    /// `503` responses can be told apart.
+    pub(crate) async fn bulk_docs<S: TypedCouchDocument>(
+        &self,
+        docs: &mut [S],
+    ) -> Result<Vec<DocumentCreatedResult>, CouchError> {
+        let mut attempt = 1;
+        loop {
+            match self.try_bulk_docs(docs).await {
+                Err(e) if self.cfg.retry.retries(attempt, &e) => {
+                    self.cfg
+                        .retry
+                        .pause(attempt, "Writing a batch of documents", &e)
+                        .await;
+                    attempt += 1;
+                }
+                result => return result.map_err(secret::redact_error),
+            }
+        }
+    }
+
+    async fn try_bulk_docs<S: TypedCouchDocument>(
+        &self,
+        docs: &mut [S],
+    ) -> Result<Vec<DocumentCreatedResult>, CouchError> {
+        self.namespace_ids(docs);
+        let values = docs
+            .iter()
+            .map(upsert_value)
+            .collect::<Result<Vec<_>, _>>()?;
+        let path = format!("{}/_bulk_docs", encode_db_name(&self.cfg.db_name));
+        let written: Vec<Value> = self
+            .client
+            .req(Method::POST, &path, None)
+            .body(json!({ "docs": values }).to_string())
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        if written.len() != docs.len() {
+            return Err(CouchError::new(
+                format!(
+                    "Unexpected size of response: {} given size of request: {}",
+                    written.len(),
+                    docs.len()
+                ),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ));
+        }
+        Ok(docs
+            .iter_mut()
+            .zip(written)
+            .map(|(doc, result)| created(doc, result))
+            .collect())
+    }
+}
+
+impl TestRepo {
+    // moves `docs` into the namespace of this instance, if it shares its database, generating the ids
+    // CouchDB would otherwise assign
+    pub(crate) fn namespace_ids<S: TypedCouchDocument>(&self, docs: &mut [S]) {
+        if self.namespace().is_none() {
+            return;
+        }
+        for doc in docs.iter_mut() {
+            let id = match doc.get_id().is_empty() {
+                true => self.namespaced_id(&random_chars(&mut rand::thread_rng(), 32)),
+                false => self.namespaced_id(&doc.get_id()),
+            };
+            doc.set_id(&id);
+        }
+    }
+}
+
+// the document as sent to CouchDB, without an empty _id or _rev
+fn upsert_value<S: TypedCouchDocument>(doc: &S) -> Result<Value, CouchError> {
+    let mut value = serde_json::to_value(doc)?;
+    let fields = value.as_object_mut().ok_or_else(|| {
+        CouchError::new(
+            "invalid document type, expected something that serializes as a JSON object"
+                .to_string(),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+    for (field, content) in [("_id", doc.get_id()), ("_rev", doc.get_rev())] {
+        match content.is_empty() {
+            true => fields.remove(field),
+            false => fields.insert(field.to_string(), Value::from(content.as_ref())),
+        };
+    }
+    Ok(value)
+}
+
+// turns one entry of a _bulk_docs response into a result, the way couch_rs does, and records the id and
+// revision of a written document on `doc`
+fn created<S: TypedCouchDocument>(doc: &mut S, result: Value) -> DocumentCreatedResult {
+    let id = result["id"].as_str().map(str::to_string);
+    if let Some(error) = result["error"].as_str() {
+        let status = match error {
+            "forbidden" => StatusCode::FORBIDDEN,
+            "unauthorized" => StatusCode::UNAUTHORIZED,
+            "conflict" => StatusCode::CONFLICT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        let reason = result["reason"].as_str().unwrap_or_default().to_string();
+        return Err(CouchError::new_with_id(id, reason, status));
+    }
+
+    match (id, result["rev"].as_str()) {
+        (Some(id), Some(rev)) => {
+            doc.set_id(&id);
+            doc.set_rev(rev);
+            Ok(DocumentCreatedDetails {
+                id,
+                rev: rev.to_string(),
+            })
+        }
+        _ => Err(CouchError::new(
+            "Unexpected response format".to_string(),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )),
+    }
+}
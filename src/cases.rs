@@ -0,0 +1,163 @@
+use std::{any::Any, error::Error, fmt, future::Future, panic::AssertUnwindSafe};
+
+use couch_rs::database::Database;
+use futures_util::FutureExt;
+use serde_json::{json, Value};
+
+use crate::{dump, TestRepo};
+
+/// A named case of a table-driven test run by [TestRepo::run_cases]: the documents seeded before the
+/// case runs, and an `input` of any type handed to the test body, such as a query and its expected
+/// result.
+///
+/// ```rust
+/// use couch_rs_test::Case;
+/// use serde_json::json;
+///
+/// let case = Case::new("single order")
+///     .with_docs(vec![json!({"type": "order", "total": 3})])
+///     .with_input(3);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Case<T = ()> {
+    /// The name of the case, used in the report of failed cases.
+    pub name: String,
+    /// The documents seeded before the case runs.
+    pub docs: Vec<Value>,
+    /// The input handed to the test body.
+    pub input: T,
+}
+
+impl Case<()> {
+    /// Create a case without documents or input.
+    pub fn new(name: &str) -> Case<()> {
+        Case {
+            name: name.to_string(),
+            docs: vec![],
+            input: (),
+        }
+    }
+}
+
+impl<T> Case<T> {
+    /// Seed `docs` before the case runs.
+    pub fn with_docs(self, docs: Vec<Value>) -> Case<T> {
+        Case { docs, ..self }
+    }
+
+    /// Hand `input` to the test body.
+    pub fn with_input<U>(self, input: U) -> Case<U> {
+        Case {
+            name: self.name,
+            docs: self.docs,
+            input,
+        }
+    }
+}
+
+impl TestRepo {
+    /// Runs `test` once for each of `cases`, one after another, formalizing the parametrized-test
+    /// pattern. Before each case, every document of the database associated with this instance except
+    /// design documents is deleted, and the documents of the case are seeded with [TestRepo::with_data],
+    /// so that cases only see their own data while sharing the views and indexes set up by the test.
+    /// `test` is called with the case, its documents carrying the revisions they were written with, and
+    /// a handle to the database.
+    ///
+    /// A case fails when `test` returns an error or panics, for example on a failed `assert!`; the
+    /// remaining cases are run nonetheless.
+    ///
+    /// ```rust
+    /// # use couch_rs_test::Case;
+    /// # use serde_json::json;
+    /// # async fn example(repo: &couch_rs_test::TestRepo) {
+    /// let cases = vec![
+    ///     Case::new("empty").with_input(0),
+    ///     Case::new("two orders")
+    ///         .with_docs(vec![json!({"type": "order"}), json!({"type": "order"})])
+    ///         .with_input(2),
+    /// ];
+    /// repo.run_cases(cases, |case, db| async move {
+    ///     let count = db.get_all_raw().await?.rows.len();
+    ///     assert_eq!(count, case.input, "documents seeded for {}", case.name);
+    ///     Ok::<_, couch_rs::error::CouchError>(())
+    /// })
+    /// .await;
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics after all cases have run if any of them failed, listing each failed case by name with its
+    /// error or panic message.
+    pub async fn run_cases<T, F, Fut, E>(&self, cases: Vec<Case<T>>, mut test: F)
+    where
+        F: FnMut(Case<T>, Database) -> Fut,
+        Fut: Future<Output = Result<(), E>>,
+        E: fmt::Display,
+    {
+        let total = cases.len();
+        let mut failures = vec![];
+
+        for mut case in cases {
+            log::info!("Running case {} on {}", case.name, self.cfg.db_name);
+            let name = case.name.clone();
+
+            if let Err(e) = self.reset_for_case(&mut case.docs).await {
+                failures.push(format!("{}: failed to seed: {}", name, e));
+                continue;
+            }
+            let run = AssertUnwindSafe(test(case, self.db.clone()))
+                .catch_unwind()
+                .await;
+            match run {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => failures.push(format!("{}: {}", name, e)),
+                Err(panic) => {
+                    failures.push(format!("{}: panicked: {}", name, panic_message(&panic)))
+                }
+            }
+        }
+
+        if !failures.is_empty() {
+            panic!(
+                "{} of {} cases failed on database {}:\n  {}",
+                failures.len(),
+                total,
+                self.db.name(),
+                failures.join("\n  ")
+            );
+        }
+    }
+
+    // deletes all documents but design documents, then seeds `docs`
+    async fn reset_for_case(&self, docs: &mut [Value]) -> Result<(), Box<dyn Error>> {
+        let mut deletions: Vec<Value> = dump::all_docs(&self.client, &self.cfg.db_name)
+            .await?
+            .into_iter()
+            .filter(|doc| {
+                !doc["_id"]
+                    .as_str()
+                    .map(|id| id.starts_with("_design/"))
+                    .unwrap_or(false)
+            })
+            .map(|doc| json!({"_id": doc["_id"], "_rev": doc["_rev"], "_deleted": true}))
+            .collect();
+        if !deletions.is_empty() {
+            self.bulk_docs(&mut deletions).await?;
+        }
+
+        if !docs.is_empty() {
+            self.with_data(docs).await?;
+        }
+        Ok(())
+    }
+}
+
+// extracts the message of a panic raised with a string, as `panic!` and `assert!` do
+fn panic_message(panic: &Box<dyn Any + Send>) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("non-string panic payload")
+}
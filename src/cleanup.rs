@@ -0,0 +1,158 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use couch_rs::error::CouchError;
+
+use crate::{marker, naming, TestRepo, TestRepoConfig};
+
+/// Deletes test databases left on the server whose creation time lies more than `older_than` in the
+/// past, returning the names of the deleted databases. This is intended to be called from a test-suite
+/// setup hook, or a small companion binary, to collect databases leaked by aborted test runs.
+///
+/// Only databases whose name starts with the database name of `config` followed by a dash (behind the
+/// `COUCH_TEST_PREFIX` prefix, when that is set, and followed by the shard of `config`, when one is set
+/// with [TestRepoConfig::with_shard] or in the environment) and that carry the marker document written
+/// by [TestRepo::new](crate::TestRepo::new) are considered, so databases not created by this crate are
+/// never deleted. An empty database name considers every database carrying the marker. Databases whose
+/// marker cannot be read, for lack of permission or because they were deleted in the meantime, or that
+/// cannot be deleted, are skipped with a warning.
+pub async fn purge_stale(
+    config: &TestRepoConfig,
+    older_than: Duration,
+) -> Result<Vec<String>, CouchError> {
+    let client = config.client()?;
+
+    let mut purged = vec![];
+    for db_name in find_stale(config, older_than).await? {
+        match client.destroy_db(&db_name).await {
+            Ok(true) => {
+                log::info!("Purged stale test database {}", db_name);
+                purged.push(db_name);
+            }
+            Ok(false) => {}
+            Err(e) => log::warn!("Failed to purge stale test database {}: {}", db_name, e),
+        }
+    }
+
+    Ok(purged)
+}
+
+/// Returns the names of the test databases [purge_stale] would delete with the same arguments, without
+/// deleting them, such as for a dry run of a cleanup job.
+pub async fn find_stale(
+    config: &TestRepoConfig,
+    older_than: Duration,
+) -> Result<Vec<String>, CouchError> {
+    let client = config.client()?;
+    let cutoff = marker::now_secs().saturating_sub(older_than.as_secs());
+
+    let mut stale = vec![];
+    let prefix = stale_prefix(config);
+    for db_name in client.list_dbs().await? {
+        if !db_name.starts_with(&prefix) {
+            continue;
+        }
+
+        // on a shared server, other databases may be unreadable or deleted while the sweep runs
+        match marker::read_marker(&client, &db_name).await {
+            Ok(Some(m)) if m.created_at < cutoff => stale.push(db_name),
+            Ok(_) => {}
+            Err(e) => log::warn!(
+                "Skipping database {}, whose marker cannot be read: {}",
+                db_name,
+                e
+            ),
+        }
+    }
+
+    Ok(stale)
+}
+
+// the start of the names of the test databases created with `config`; the dash keeps `users` from
+// matching the databases of `users_archive`
+fn stale_prefix(config: &TestRepoConfig) -> String {
+    match (config.resolved_shard(), config.db_name.as_str()) {
+        (Some(shard), db_name) => format!("{}{}-{}-", naming::env_prefix(), db_name, shard),
+        (None, "") => naming::env_prefix(),
+        (None, db_name) => format!("{}{}-", naming::env_prefix(), db_name),
+    }
+}
+
+/// A database on the server that was created by this crate, as listed by [TestRepo::list_test_dbs].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TestDatabase {
+    /// Unique name of the database.
+    pub name: String,
+    /// Database name configured in [TestRepoConfig] when the database was created, before the unique
+    /// part was added.
+    pub base_name: String,
+    /// Creation time of the database.
+    pub created_at: SystemTime,
+    /// Version of this crate that created the database.
+    pub crate_version: String,
+    /// Shard identifier embedded in the name of the database, if any; see
+    /// [TestRepoConfig::with_shard].
+    pub shard: Option<String>,
+}
+
+impl TestRepo {
+    /// Lists all databases on the server of `config` that were created by this crate and still exist,
+    /// whatever their name, with the metadata recorded when they were created. This is meant for
+    /// diagnostics and custom cleanup tooling; [purge_stale](crate::purge_stale) covers the common case
+    /// of deleting databases leaked by aborted test runs. Databases whose marker cannot be read, for lack
+    /// of permission or because they were deleted in the meantime, are left out with a warning.
+    pub async fn list_test_dbs(config: &TestRepoConfig) -> Result<Vec<TestDatabase>, CouchError> {
+        let client = config.client()?;
+
+        let mut found = vec![];
+        for db_name in client.list_dbs().await? {
+            // system databases never carry a marker
+            if db_name.starts_with('_') {
+                continue;
+            }
+            let marker = match marker::read_marker(&client, &db_name).await {
+                Ok(marker) => marker,
+                Err(e) => {
+                    log::warn!(
+                        "Skipping database {}, whose marker cannot be read: {}",
+                        db_name,
+                        e
+                    );
+                    continue;
+                }
+            };
+            if let Some(m) = marker {
+                found.push(TestDatabase {
+                    name: db_name,
+                    base_name: m.base_name,
+                    created_at: UNIX_EPOCH + Duration::from_secs(m.created_at),
+                    crate_version: m.crate_version,
+                    shard: m.shard,
+                });
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale_prefix_ends_at_the_database_name() {
+        let cfg = TestRepoConfig::new("", "", "", "users");
+        let prefix = stale_prefix(&cfg);
+        let env_prefix = naming::env_prefix();
+        assert!(!format!("{}users_archive-a1b2c3", env_prefix).starts_with(&prefix));
+        if naming::env_shard().is_none() {
+            assert!(format!("{}users-a1b2c3", env_prefix).starts_with(&prefix));
+        }
+    }
+
+    #[test]
+    fn stale_prefix_includes_the_shard() {
+        let cfg = TestRepoConfig::new("", "", "", "users").with_shard("ci 3");
+        assert!(stale_prefix(&cfg).ends_with("users-ci_3-"));
+    }
+}
@@ -0,0 +1,85 @@
+use couch_rs::error::CouchError;
+use serde_json::Value;
+
+use crate::{secret, TestRepo};
+
+impl TestRepo {
+    /// Returns the names of the nodes of the CouchDB cluster, such as `couchdb@node1.example.com`, as
+    /// configured in `_membership`, in name order. A single-node server reports one node.
+    pub async fn cluster_nodes(&self) -> Result<Vec<String>, CouchError> {
+        Ok(self.membership().await?.1)
+    }
+
+    /// Asserts that the CouchDB cluster consists of exactly the nodes named in `expected`, in any order,
+    /// and that the node serving the requests is connected to each of them, so that tests of sharding or
+    /// node failures can check the topology they rely on before they run.
+    ///
+    /// ```rust
+    /// # async fn example(repo: &couch_rs_test::TestRepo) {
+    /// repo.assert_cluster_nodes(&[
+    ///     "couchdb@couchdb-0.couchdb",
+    ///     "couchdb@couchdb-1.couchdb",
+    ///     "couchdb@couchdb-2.couchdb",
+    /// ])
+    /// .await;
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if nodes are missing from the cluster (`-`), unexpected (`+`) or not connected (`!`), or if
+    /// `_membership` cannot be read.
+    pub async fn assert_cluster_nodes(&self, expected: &[&str]) {
+        let (connected, configured) = self
+            .membership()
+            .await
+            .unwrap_or_else(|e| panic!("Failed to read the cluster membership: {}", e));
+
+        let mut diff = String::new();
+        for node in expected
+            .iter()
+            .filter(|node| !configured.iter().any(|n| n == *node))
+        {
+            diff.push_str(&format!("\n  - {}", node));
+        }
+        for node in configured
+            .iter()
+            .filter(|node| !expected.contains(&node.as_str()))
+        {
+            diff.push_str(&format!("\n  + {}", node));
+        }
+        for node in configured.iter().filter(|node| !connected.contains(node)) {
+            diff.push_str(&format!("\n  ! {}", node));
+        }
+        if !diff.is_empty() {
+            panic!(
+                "Cluster of {} has nodes {:?}, expected {:?}:{}",
+                secret::redact(&self.cfg.uri),
+                configured,
+                expected,
+                diff
+            );
+        }
+    }
+
+    // the nodes connected to the node serving the requests, and the nodes configured in the cluster
+    async fn membership(&self) -> Result<(Vec<String>, Vec<String>), CouchError> {
+        let membership = self.get_json("_membership").await?;
+        Ok((
+            node_names(&membership["all_nodes"]),
+            node_names(&membership["cluster_nodes"]),
+        ))
+    }
+}
+
+fn node_names(nodes: &Value) -> Vec<String> {
+    let mut names: Vec<String> = nodes
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .map(str::to_string)
+        .collect();
+    names.sort();
+    names
+}
@@ -0,0 +1,205 @@
+use couch_rs::{
+    document::TypedCouchDocument,
+    error::{CouchError, CouchResult},
+    types::document::DocumentCreatedResult,
+};
+use serde_json::{json, Value};
+
+use crate::{
+    compat::{Method, StatusCode},
+    naming::encode_db_name,
+    TestRepo,
+};
+
+// bounds the retries of a single document, so that a concurrent writer cannot keep a seed busy forever
+const MAX_ATTEMPTS: usize = 10;
+
+/// What [TestRepo::with_data] and the other fixture loaders do with a document whose `_id` is already
+/// taken, for example because two fixture files both define a `settings` document. Set with
+/// [TestRepoConfig::with_id_collision](crate::TestRepoConfig::with_id_collision).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IdCollision {
+    /// Keep the document already in the database and log a warning naming the skipped ids. This is the
+    /// default.
+    #[default]
+    Skip,
+    /// Fail the seed with a `409 Conflict` error naming the colliding ids. Documents without a collision
+    /// have been written nonetheless.
+    Error,
+    /// Replace the document already in the database, by fetching its current `_rev` and updating it.
+    Overwrite,
+    /// Write the document under a new `_id`, made by appending `-2`, `-3` and so on to the original one.
+    Suffix,
+}
+
+impl TestRepo {
+    /// Pushes data to the database associated with this instance like [TestRepo::with_data], but updates
+    /// the documents whose `_id` already exists instead of conflicting with them, so that fixtures can be
+    /// re-applied onto a reused or pooled database and leave it with the same contents every time. The
+    /// current revisions are fetched with one `_all_docs` request per batch of
+    /// [TestRepoConfig::with_batch_size](crate::TestRepoConfig::with_batch_size) documents; a document
+    /// changed by someone else in the meantime is handled as set with
+    /// [TestRepoConfig::with_id_collision](crate::TestRepoConfig::with_id_collision).
+    ///
+    /// ```rust
+    /// # use serde_json::json;
+    /// # async fn example(repo: &couch_rs_test::TestRepo) -> Result<(), couch_rs::error::CouchError> {
+    /// repo.with_data_upsert(&mut [json!({"_id": "settings", "theme": "dark"})]).await?;
+    /// repo.with_data_upsert(&mut [json!({"_id": "settings", "theme": "light"})]).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn with_data_upsert<S: TypedCouchDocument>(
+        &self,
+        data: &mut [S],
+    ) -> Result<usize, CouchError> {
+        for batch in data.chunks_mut(self.cfg.batch_size) {
+            self.set_current_revs(batch).await?;
+        }
+        self.with_data(data).await
+    }
+
+    // sets the revision stored in the database on each document of `docs` that has an id, and clears it
+    // on those not stored; in a shared database, the ids are namespaced first, as the write will do
+    async fn set_current_revs<S: TypedCouchDocument>(&self, docs: &mut [S]) -> CouchResult<()> {
+        self.namespace_ids(docs);
+        let ids: Vec<String> = docs
+            .iter()
+            .map(|doc| doc.get_id().to_string())
+            .filter(|id| !id.is_empty())
+            .collect();
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        let path = format!("{}/_all_docs", encode_db_name(&self.cfg.db_name));
+        let found: Value = self
+            .client
+            .req(Method::POST, &path, None)
+            .body(json!({ "keys": ids }).to_string())
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let revs: std::collections::HashMap<&str, &str> = found["rows"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|row| Some((row["key"].as_str()?, row["value"]["rev"].as_str()?)))
+            .collect();
+
+        for doc in docs.iter_mut() {
+            let id = doc.get_id().to_string();
+            if !id.is_empty() {
+                doc.set_rev(revs.get(id.as_str()).copied().unwrap_or_default());
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the configured [IdCollision] policy to the documents of `docs` whose write in `results`
+    /// failed with a conflict.
+    pub(crate) async fn resolve_collisions<S: TypedCouchDocument>(
+        &self,
+        docs: &mut [S],
+        results: &[DocumentCreatedResult],
+    ) -> Result<(), CouchError> {
+        let colliding: Vec<usize> = results
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| matches!(r, Err(e) if e.status() == Some(StatusCode::CONFLICT)))
+            .map(|(n, _)| n)
+            .collect();
+        if colliding.is_empty() {
+            return Ok(());
+        }
+        let ids = colliding
+            .iter()
+            .map(|&n| docs[n].get_id().to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        match self.cfg.id_collision {
+            IdCollision::Skip => {
+                log::warn!(
+                    "Skipped {} documents whose _id already exists in {}: {}",
+                    colliding.len(),
+                    self.cfg.db_name,
+                    ids
+                );
+                Ok(())
+            }
+            IdCollision::Error => Err(CouchError::new(
+                format!(
+                    "{} documents have an _id that already exists in {}: {}",
+                    colliding.len(),
+                    self.cfg.db_name,
+                    ids
+                ),
+                StatusCode::CONFLICT,
+            )),
+            IdCollision::Overwrite => {
+                for n in colliding {
+                    self.overwrite(&mut docs[n]).await?;
+                }
+                log::info!("Overwrote documents {} in {}", ids, self.cfg.db_name);
+                Ok(())
+            }
+            IdCollision::Suffix => {
+                for n in colliding {
+                    let original = docs[n].get_id().to_string();
+                    self.write_suffixed(&mut docs[n]).await?;
+                    log::info!(
+                        "Wrote document {} as {} in {}, as its _id already exists",
+                        original,
+                        docs[n].get_id(),
+                        self.cfg.db_name
+                    );
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // updates the stored document with the id of `doc` to `doc`
+    async fn overwrite<S: TypedCouchDocument>(&self, doc: &mut S) -> CouchResult<()> {
+        let id = doc.get_id().to_string();
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            // a deleted document has no current revision and is recreated without one
+            let written = match self.db.get::<Value>(&id).await {
+                Ok(current) => {
+                    doc.set_rev(&current.get_rev());
+                    self.db.save(doc).await
+                }
+                Err(e) if e.is_not_found() => self.db.create(doc).await,
+                Err(e) => return Err(e),
+            };
+            match written {
+                Err(e) if e.status() == Some(StatusCode::CONFLICT) && attempt < MAX_ATTEMPTS => {}
+                result => return result.map(|_| ()),
+            }
+        }
+    }
+
+    // writes `doc` under the first free id made by suffixing its id with a number
+    async fn write_suffixed<S: TypedCouchDocument>(&self, doc: &mut S) -> CouchResult<()> {
+        let id = doc.get_id().to_string();
+        for n in 2..MAX_ATTEMPTS + 2 {
+            doc.set_id(&format!("{}-{}", id, n));
+            match self.db.create(doc).await {
+                Err(e) if e.status() == Some(StatusCode::CONFLICT) => {}
+                result => return result.map(|_| ()),
+            }
+        }
+        Err(CouchError::new(
+            format!(
+                "No free _id found for document {} after {} attempts",
+                id, MAX_ATTEMPTS
+            ),
+            StatusCode::CONFLICT,
+        ))
+    }
+}
@@ -0,0 +1,133 @@
+use std::time::Duration;
+
+use couch_rs::error::CouchError;
+use serde_json::Value;
+use tokio::time::Instant;
+
+use crate::{
+    compat::{Method, StatusCode},
+    naming::encode_db_name,
+    trace::trace_event,
+    wait::{timeout_error, POLL_INTERVAL},
+    TestRepo,
+};
+
+impl TestRepo {
+    /// Triggers compaction of the database associated with this instance and of the view indexes of all
+    /// its design documents, then polls until every compaction has finished. This is intended for tests of
+    /// behavior that only shows after compaction, such as old revisions being pruned.
+    ///
+    /// If compaction is still running when `timeout` elapses, an error with status `REQUEST_TIMEOUT` is
+    /// returned.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "couch_rs_test::compact_and_wait", skip(self), fields(db = %self.db.name()))
+    )]
+    pub async fn compact_and_wait(&self, timeout: Duration) -> Result<(), CouchError> {
+        let design_docs = self.design_doc_names().await?;
+
+        if !self.db.compact().await {
+            return Err(compaction_error(format!(
+                "CouchDB refused to compact database {}",
+                self.db.name()
+            )));
+        }
+        for name in design_docs.iter() {
+            if !self.db.compact_index(name).await {
+                return Err(compaction_error(format!(
+                    "CouchDB refused to compact the views of _design/{} in database {}",
+                    name,
+                    self.db.name()
+                )));
+            }
+        }
+        // removes index files of views that no longer exist; runs with the compactions above
+        self.db.compact_views().await;
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            if !self.compaction_running(&design_docs).await? {
+                trace_event!("compaction finished");
+                return Ok(());
+            }
+
+            if Instant::now() >= deadline {
+                return Err(timeout_error(format!(
+                    "Timed out after {:?} waiting for compaction of database {}",
+                    timeout,
+                    self.db.name()
+                )));
+            }
+
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    async fn compaction_running(&self, design_docs: &[String]) -> Result<bool, CouchError> {
+        let db_name = encode_db_name(&self.cfg.db_name);
+        let info = self.get_json(&db_name).await?;
+        if info["compact_running"].as_bool().unwrap_or(false) {
+            return Ok(true);
+        }
+
+        for name in design_docs.iter() {
+            let info = self
+                .get_json(&format!(
+                    "{}/_design/{}/_info",
+                    db_name,
+                    encode_db_name(name)
+                ))
+                .await?;
+            if info["view_index"]["compact_running"]
+                .as_bool()
+                .unwrap_or(false)
+            {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    // names of the design documents in the database, without the `_design/` prefix
+    pub(crate) async fn design_doc_names(&self) -> Result<Vec<String>, CouchError> {
+        let response: Value = self
+            .client
+            .req(
+                Method::GET,
+                &format!("{}/_all_docs", encode_db_name(&self.cfg.db_name)),
+                None,
+            )
+            .query(&[("startkey", "\"_design/\""), ("endkey", "\"_design0\"")])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(response["rows"]
+            .as_array()
+            .map(|rows| {
+                rows.iter()
+                    .filter_map(|row| row["id"].as_str())
+                    .filter_map(|id| id.strip_prefix("_design/"))
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    pub(crate) async fn get_json(&self, path: &str) -> Result<Value, CouchError> {
+        Ok(self
+            .client
+            .req(Method::GET, path, None)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+}
+
+fn compaction_error(message: String) -> CouchError {
+    CouchError::new(message, StatusCode::INTERNAL_SERVER_ERROR)
+}
@@ -0,0 +1,16 @@
+//! The types through which this crate talks to the couch_rs version selected with the `couch_rs_0_9`
+//! or `couch_rs_0_10` feature. couch_rs 0.9 takes and returns the `Method` and `StatusCode` of
+//! http 0.2 and wraps errors of reqwest 0.11; couch_rs 0.10 moved to http 1.x and reqwest 0.12. Code
+//! handing a method or status to couch_rs, or comparing the status of a
+//! [CouchError](couch_rs::error::CouchError), uses these rather than naming the http crate, which is
+//! only a dependency of its own with `couch_rs_0_9` or the `mock` and `proxy` servers.
+
+#[cfg(feature = "couch_rs_0_9")]
+pub(crate) use http::{Method, StatusCode, Uri};
+#[cfg(feature = "couch_rs_0_9")]
+pub(crate) use reqwest::Error as ReqwestError;
+
+#[cfg(not(feature = "couch_rs_0_9"))]
+pub(crate) use couch_rs::http::{Method, StatusCode, Uri};
+#[cfg(not(feature = "couch_rs_0_9"))]
+pub(crate) use reqwest_0_12::Error as ReqwestError;
@@ -0,0 +1,109 @@
+use std::{fmt, future::Future};
+
+use couch_rs::database::Database;
+use futures_util::{stream, StreamExt};
+
+use crate::TestRepo;
+
+impl TestRepo {
+    /// Runs `n` instances of `task` against the database associated with this instance, at most
+    /// [TestRepoConfig::with_max_concurrency](crate::TestRepoConfig::with_max_concurrency) of them at a
+    /// time, and collects their results. Each instance is called with its number, from 0 to `n - 1`,
+    /// and a handle to the database. This standardizes tests of conflict handling and idempotency, such
+    /// as checking that exactly one of several concurrent updates of a document wins.
+    ///
+    /// ```rust
+    /// # use serde_json::{json, Value};
+    /// # async fn example(repo: &couch_rs_test::TestRepo) {
+    /// let results = repo
+    ///     .run_concurrently(10, |i, db| async move {
+    ///         let mut doc = json!({"_id": "counter", "writer": i});
+    ///         db.save(&mut doc).await
+    ///     })
+    ///     .await;
+    /// assert_eq!(results.ok_count(), 1);
+    /// # }
+    /// ```
+    pub async fn run_concurrently<F, Fut, T, E>(&self, n: usize, task: F) -> ConcurrentResults<T, E>
+    where
+        F: Fn(usize, Database) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut results: Vec<(usize, Result<T, E>)> = stream::iter(0..n)
+            .map(|i| {
+                let run = task(i, self.db.clone());
+                async move { (i, run.await) }
+            })
+            .buffer_unordered(self.cfg.max_concurrency)
+            .collect()
+            .await;
+        results.sort_by_key(|(i, _)| *i);
+
+        ConcurrentResults {
+            results: results.into_iter().map(|(_, result)| result).collect(),
+        }
+    }
+}
+
+/// Results of the tasks run by [TestRepo::run_concurrently], in the order of their numbers.
+#[derive(Debug)]
+pub struct ConcurrentResults<T, E> {
+    results: Vec<Result<T, E>>,
+}
+
+impl<T, E> ConcurrentResults<T, E> {
+    /// The values of the tasks that succeeded, with their numbers.
+    pub fn ok(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.results
+            .iter()
+            .enumerate()
+            .filter_map(|(i, result)| result.as_ref().ok().map(|value| (i, value)))
+    }
+
+    /// The errors of the tasks that failed, with their numbers.
+    pub fn errors(&self) -> impl Iterator<Item = (usize, &E)> {
+        self.results
+            .iter()
+            .enumerate()
+            .filter_map(|(i, result)| result.as_ref().err().map(|error| (i, error)))
+    }
+
+    /// Number of tasks that succeeded.
+    pub fn ok_count(&self) -> usize {
+        self.ok().count()
+    }
+
+    /// Number of tasks that failed.
+    pub fn error_count(&self) -> usize {
+        self.errors().count()
+    }
+
+    /// The results of all tasks, in the order of their numbers.
+    pub fn into_results(self) -> Vec<Result<T, E>> {
+        self.results
+    }
+}
+
+impl<T, E: fmt::Display> ConcurrentResults<T, E> {
+    /// Asserts that every task succeeded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any task failed, listing the errors by task number.
+    pub fn assert_all_ok(&self) {
+        if self.error_count() == 0 {
+            return;
+        }
+
+        let errors: String = self
+            .errors()
+            .map(|(i, error)| format!("\n  task {}: {}", i, error))
+            .collect();
+        panic!(
+            "{} of {} concurrent tasks failed:{}",
+            self.error_count(),
+            self.results.len(),
+            errors
+        );
+    }
+}
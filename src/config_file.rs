@@ -0,0 +1,197 @@
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::Deserialize;
+
+use crate::{password, SecretString, TestRepoConfig};
+
+/// Environment variable selecting the profile applied by [TestRepoConfig::from_file].
+pub(crate) const PROFILE_ENV_VAR: &str = "COUCH_TEST_PROFILE";
+
+// database name used when the file does not set one
+const DEFAULT_DB_NAME: &str = "test";
+
+/// Settings of a configuration file, or of one of its profiles.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct FileSettings {
+    uri: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    password_file: Option<PathBuf>,
+    password_command: Option<String>,
+    db_name: Option<String>,
+    batch_size: Option<usize>,
+    parallel_batches: Option<usize>,
+    max_concurrency: Option<usize>,
+    collision_retries: Option<u32>,
+    request_timeout_secs: Option<u64>,
+    teardown_timeout_secs: Option<u64>,
+}
+
+impl FileSettings {
+    // settings of `profile` where it has any, and of self otherwise; a profile naming any source of the
+    // password replaces all sources of the password
+    fn overlay(self, profile: FileSettings) -> FileSettings {
+        let (password, password_file, password_command) = match profile.has_password() {
+            true => (
+                profile.password,
+                profile.password_file,
+                profile.password_command,
+            ),
+            false => (self.password, self.password_file, self.password_command),
+        };
+        FileSettings {
+            uri: profile.uri.or(self.uri),
+            username: profile.username.or(self.username),
+            password,
+            password_file,
+            password_command,
+            db_name: profile.db_name.or(self.db_name),
+            batch_size: profile.batch_size.or(self.batch_size),
+            parallel_batches: profile.parallel_batches.or(self.parallel_batches),
+            max_concurrency: profile.max_concurrency.or(self.max_concurrency),
+            collision_retries: profile.collision_retries.or(self.collision_retries),
+            request_timeout_secs: profile.request_timeout_secs.or(self.request_timeout_secs),
+            teardown_timeout_secs: profile.teardown_timeout_secs.or(self.teardown_timeout_secs),
+        }
+    }
+
+    fn has_password(&self) -> bool {
+        self.password.is_some() || self.password_file.is_some() || self.password_command.is_some()
+    }
+}
+
+impl TestRepoConfig {
+    /// Load a configuration from the TOML file at `path`, so that connection settings live in one
+    /// versioned file rather than being repeated across test modules. Settings at the top of the file
+    /// apply everywhere; a profile, such as `local` or `ci`, overrides them when it is named in the
+    /// `COUCH_TEST_PROFILE` environment variable.
+    ///
+    /// ```toml
+    /// uri = "http://localhost:5984"
+    /// username = "admin"
+    /// password = "password"
+    /// # or, to keep the password out of the file
+    /// # password_file = "/run/secrets/couchdb_password"
+    /// # password_command = "pass show ci/couchdb"
+    /// # optional; defaults to "test", and can be changed per test with with_db_name
+    /// db_name = "users"
+    /// # further optional settings
+    /// batch_size = 500
+    /// parallel_batches = 4
+    /// max_concurrency = 16
+    /// collision_retries = 3
+    /// request_timeout_secs = 30
+    /// teardown_timeout_secs = 60
+    ///
+    /// [profiles.ci]
+    /// uri = "http://couchdb:5984"
+    /// request_timeout_secs = 120
+    /// ```
+    ///
+    /// `uri`, `username` and exactly one of `password`, `password_file` and `password_command` are
+    /// required, either at the top or in the selected profile. A relative `password_file` is relative to
+    /// the directory of the configuration file; see [TestRepoConfig::with_password_file] and
+    /// [TestRepoConfig::with_password_command] for how the password is read. Unknown settings and a
+    /// profile missing from the file are reported as errors.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<TestRepoConfig, Box<dyn Error>> {
+        let profile = std::env::var(PROFILE_ENV_VAR)
+            .ok()
+            .map(|p| p.trim().to_string())
+            .filter(|p| !p.is_empty());
+        TestRepoConfig::from_file_with_profile(path, profile.as_deref())
+    }
+
+    /// Load a configuration from the TOML file at `path` like [TestRepoConfig::from_file], applying the
+    /// profile named `profile`, if any, regardless of the environment.
+    pub fn from_file_with_profile<P: AsRef<Path>>(
+        path: P,
+        profile: Option<&str>,
+    ) -> Result<TestRepoConfig, Box<dyn Error>> {
+        let path = path.as_ref();
+        let mut file: toml::Table = toml::from_str(&fs::read_to_string(path)?)?;
+        let mut profiles: BTreeMap<String, FileSettings> = match file.remove("profiles") {
+            Some(profiles) => profiles.try_into()?,
+            None => BTreeMap::new(),
+        };
+        let mut settings: FileSettings = toml::Value::Table(file).try_into()?;
+
+        if let Some(name) = profile {
+            let overrides = profiles.remove(name).ok_or_else(|| {
+                format!(
+                    "{} has no profile {}; it defines {}",
+                    path.display(),
+                    name,
+                    match profiles.is_empty() {
+                        true => "none".to_string(),
+                        false => profiles.keys().cloned().collect::<Vec<_>>().join(", "),
+                    }
+                )
+            })?;
+            settings = settings.overlay(overrides);
+        }
+
+        let required = |value: Option<String>, name: &str| {
+            value.ok_or_else(|| format!("{} does not set {}", path.display(), name))
+        };
+        let password = match (
+            settings.password,
+            settings.password_file,
+            settings.password_command,
+        ) {
+            (Some(password), None, None) => SecretString::from(password),
+            (None, Some(file), None) => {
+                password::read_password_file(&path.parent().unwrap_or(Path::new("")).join(file))?
+            }
+            (None, None, Some(command)) => password::run_password_command(&command)?,
+            (None, None, None) => {
+                return Err(format!("{} does not set password", path.display()).into())
+            }
+            _ => {
+                return Err(format!(
+                    "{} sets more than one of password, password_file and password_command",
+                    path.display()
+                )
+                .into())
+            }
+        };
+        let mut cfg = TestRepoConfig::new(
+            &required(settings.uri, "uri")?,
+            &required(settings.username, "username")?,
+            "",
+            settings.db_name.as_deref().unwrap_or(DEFAULT_DB_NAME),
+        )
+        .with_password(password);
+        if let Some(batch_size) = settings.batch_size {
+            cfg = cfg.with_batch_size(batch_size);
+        }
+        if let Some(parallel_batches) = settings.parallel_batches {
+            cfg = cfg.with_parallel_batches(parallel_batches);
+        }
+        if let Some(max_concurrency) = settings.max_concurrency {
+            cfg = cfg.with_max_concurrency(max_concurrency);
+        }
+        if let Some(retries) = settings.collision_retries {
+            cfg = cfg.with_collision_retries(retries);
+        }
+        if let Some(secs) = settings.request_timeout_secs {
+            cfg = cfg.with_request_timeout(Some(Duration::from_secs(secs)));
+        }
+        if let Some(secs) = settings.teardown_timeout_secs {
+            cfg = cfg.with_teardown_timeout(Some(Duration::from_secs(secs)));
+        }
+        Ok(cfg)
+    }
+
+    /// Name the databases created with this configuration after `dbname`, for example to give each
+    /// test module its own name within a configuration loaded with [TestRepoConfig::from_file].
+    pub fn with_db_name(self, dbname: &str) -> TestRepoConfig {
+        self.with_name(dbname.to_string())
+    }
+}
@@ -0,0 +1,156 @@
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde_json::{Map, Value};
+
+use crate::TestRepo;
+
+// directories whose files each hold one function, keyed by file name
+const FUNCTION_DIRS: [&str; 4] = ["filters", "lists", "shows", "updates"];
+
+impl TestRepo {
+    /// Pushes the design document laid out in the directory at `path` to the database associated with
+    /// this instance, so that JavaScript view code can live in real `.js` files. See
+    /// [design_doc_from_dir] for the layout.
+    pub async fn with_design_doc_dir<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        let mut design_doc = design_doc_from_dir(path)?;
+        self.with_data(std::slice::from_mut(&mut design_doc))
+            .await?;
+        Ok(())
+    }
+}
+
+/// Reads a design document from a directory laid out the way couchapp and kanso did:
+///
+/// ```text
+/// users/
+///   _id                      "_design/users"; defaults to _design/ plus the directory name
+///   language                 optional, such as "javascript"
+///   validate_doc_update.js
+///   views/by_email/map.js
+///   views/by_email/reduce.js optional
+///   filters/active.js        likewise lists/, shows/ and updates/, one function per file
+///   options.json             any other .json file becomes the field of its name, parsed
+/// ```
+///
+/// Any other `.js` file at the top level becomes the field of its name, as source text. Hidden files
+/// are ignored.
+pub fn design_doc_from_dir<P: AsRef<Path>>(path: P) -> Result<Value, Box<dyn Error>> {
+    let dir = path.as_ref();
+    let mut doc = Map::new();
+
+    let id = match fs::read_to_string(dir.join("_id")) {
+        Ok(id) => id.trim().to_string(),
+        Err(_) => {
+            let name = dir
+                .file_name()
+                .and_then(|n| n.to_str())
+                .ok_or_else(|| format!("Cannot name a design document after {}", dir.display()))?;
+            format!("_design/{}", name)
+        }
+    };
+    doc.insert("_id".to_string(), Value::from(id));
+    if let Ok(language) = fs::read_to_string(dir.join("language")) {
+        doc.insert("language".to_string(), Value::from(language.trim()));
+    }
+
+    for path in entries(dir)? {
+        let (stem, extension) = match (file_stem(&path), path.extension().and_then(|e| e.to_str()))
+        {
+            (Some(stem), extension) => (stem, extension),
+            _ => continue,
+        };
+
+        if path.is_dir() {
+            if stem == "views" {
+                doc.insert("views".to_string(), views(&path)?);
+            } else if FUNCTION_DIRS.contains(&stem.as_str()) {
+                doc.insert(stem, functions(&path)?);
+            }
+            continue;
+        }
+        match extension {
+            Some("js") => {
+                doc.insert(stem, Value::from(read_source(&path)?));
+            }
+            Some("json") => {
+                let value = serde_json::from_str(&fs::read_to_string(&path)?)
+                    .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+                doc.insert(stem, value);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(Value::Object(doc))
+}
+
+// reads views/<name>/map.js and reduce.js
+fn views(dir: &Path) -> Result<Value, Box<dyn Error>> {
+    let mut views = Map::new();
+    for path in entries(dir)?.into_iter().filter(|p| p.is_dir()) {
+        let mut view = Map::new();
+        for function in ["map", "reduce"] {
+            let file = path.join(format!("{}.js", function));
+            if file.is_file() {
+                view.insert(function.to_string(), Value::from(read_source(&file)?));
+            }
+        }
+        if !view.contains_key("map") {
+            return Err(format!("View {} has no map.js", path.display()).into());
+        }
+        if let Some(name) = file_stem(&path) {
+            views.insert(name, Value::Object(view));
+        }
+    }
+    Ok(Value::Object(views))
+}
+
+// reads one function per .js file of `dir`, keyed by file name
+fn functions(dir: &Path) -> Result<Value, Box<dyn Error>> {
+    let mut functions = Map::new();
+    for path in entries(dir)? {
+        if path.extension().and_then(|e| e.to_str()) != Some("js") {
+            continue;
+        }
+        if let Some(name) = file_stem(&path) {
+            functions.insert(name, Value::from(read_source(&path)?));
+        }
+    }
+    Ok(Value::Object(functions))
+}
+
+// the visible entries of `dir`, sorted for a stable result
+fn entries(dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut entries = vec![];
+    for entry in
+        fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?
+    {
+        let path = entry?.path();
+        let hidden = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .map(|n| n.starts_with('.'))
+            .unwrap_or(true);
+        if !hidden {
+            entries.push(path);
+        }
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+fn file_stem(path: &Path) -> Option<String> {
+    path.file_stem()
+        .and_then(|s| s.to_str())
+        .map(str::to_string)
+}
+
+fn read_source(path: &Path) -> Result<String, Box<dyn Error>> {
+    let source = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    Ok(source.trim_end().to_string())
+}
@@ -0,0 +1,125 @@
+use std::{collections::BTreeMap, error::Error, fmt};
+
+use serde_json::Value;
+
+use crate::{snapshot::diff_fields, TestRepo};
+
+/// The differences between the documents of two test databases, as returned by [diff_repos].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RepoDiff {
+    /// Documents only found in the first database, in id order.
+    pub only_in_first: Vec<Value>,
+    /// Documents only found in the second database, in id order.
+    pub only_in_second: Vec<Value>,
+    /// Documents found in both databases with different contents, in id order.
+    pub changed: Vec<DocDiff>,
+}
+
+/// A document whose contents differ between two databases; see [RepoDiff].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DocDiff {
+    /// Id of the document.
+    pub id: String,
+    /// The differing fields by JSON pointer, each with its value in the first and in the second
+    /// database, such as `/total: 12 => 14`.
+    pub fields: Vec<String>,
+}
+
+impl RepoDiff {
+    /// Whether both databases hold the same documents.
+    pub fn is_empty(&self) -> bool {
+        self.only_in_first.is_empty() && self.only_in_second.is_empty() && self.changed.is_empty()
+    }
+}
+
+// lists documents only in the first database (`-`), only in the second (`+`) and changed (`~`),
+// like the differences reported by TestRepo::assert_snapshot
+impl fmt::Display for RepoDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for doc in self.only_in_first.iter() {
+            writeln!(f, "- {}", doc)?;
+        }
+        for doc in self.changed.iter() {
+            writeln!(f, "~ {}", doc.id)?;
+            for field in doc.fields.iter() {
+                writeln!(f, "    {}", field)?;
+            }
+        }
+        for doc in self.only_in_second.iter() {
+            writeln!(f, "+ {}", doc)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares the full contents of the databases of two test repositories, design documents
+/// included, for example to check that a replication copied every document, or that a migration
+/// produced output identical to a reference database seeded from a fixture. Revisions are ignored,
+/// as are the revision positions of attachments, so documents written independently with the same
+/// content compare equal. Documents of repositories sharing a database are compared without their
+/// [namespace](TestRepo::namespace).
+///
+/// ```rust
+/// # async fn example(
+/// #     migrated: &couch_rs_test::TestRepo,
+/// #     reference: &couch_rs_test::TestRepo,
+/// # ) -> Result<(), Box<dyn std::error::Error>> {
+/// let diff = couch_rs_test::diff_repos(migrated, reference).await?;
+/// assert!(diff.is_empty(), "migration output differs from the reference:\n{}", diff);
+/// # Ok(())
+/// # }
+/// ```
+pub async fn diff_repos(first: &TestRepo, second: &TestRepo) -> Result<RepoDiff, Box<dyn Error>> {
+    let first = contents(first).await?;
+    let second = contents(second).await?;
+
+    let mut diff = RepoDiff::default();
+    for (id, doc) in first.iter() {
+        match second.get(id) {
+            None => diff.only_in_first.push(doc.clone()),
+            Some(other) if other != doc => {
+                let mut fields = vec![];
+                diff_fields("", doc, other, &mut fields);
+                diff.changed.push(DocDiff {
+                    id: id.clone(),
+                    fields,
+                });
+            }
+            Some(_) => {}
+        }
+    }
+    diff.only_in_second = second
+        .into_iter()
+        .filter(|(id, _)| !first.contains_key(id))
+        .map(|(_, doc)| doc)
+        .collect();
+    Ok(diff)
+}
+
+// the documents of `repo` by id, without the fields that differ between equal copies
+async fn contents(repo: &TestRepo) -> Result<BTreeMap<String, Value>, Box<dyn Error>> {
+    let mut contents = BTreeMap::new();
+    for mut doc in repo.snapshot(&[]).await? {
+        let fields = match doc.as_object_mut() {
+            Some(fields) => fields,
+            None => continue,
+        };
+        fields.remove("_rev");
+        if let Some(Value::Object(attachments)) = fields.get_mut("_attachments") {
+            for attachment in attachments.values_mut() {
+                if let Some(attachment) = attachment.as_object_mut() {
+                    attachment.remove("revpos");
+                }
+            }
+        }
+
+        // snapshots of a shared database come without the namespace
+        let id = fields
+            .get("_id")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        contents.insert(id, doc);
+    }
+    Ok(contents)
+}
@@ -0,0 +1,120 @@
+use couch_rs::error::CouchError;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::{
+    compat::{Method, StatusCode},
+    naming::encode_db_name,
+    TestRepo,
+};
+
+impl TestRepo {
+    /// Returns the ids of all live documents in the database associated with this instance, design
+    /// documents included, in the order of `_all_docs`. In a shared database, only the ids of the
+    /// documents of this instance's [namespace](TestRepo::namespace) are returned.
+    ///
+    /// ```rust
+    /// # async fn example(repo: &couch_rs_test::TestRepo) -> Result<(), couch_rs::error::CouchError> {
+    /// assert_eq!(repo.doc_ids().await?, ["alice", "bob"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn doc_ids(&self) -> Result<Vec<String>, CouchError> {
+        let path = format!("{}/_all_docs", encode_db_name(&self.cfg.db_name));
+        let all_docs = match self.namespace_range() {
+            Some((start, end)) => {
+                self.client
+                    .req(Method::GET, &path, None)
+                    .query(&[
+                        ("start_key", serde_json::to_string(&start)?),
+                        ("end_key", serde_json::to_string(&end)?),
+                    ])
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?
+            }
+            None => self.get_json(&path).await?,
+        };
+        Ok(all_docs["rows"]
+            .as_array()
+            .map(|rows| {
+                rows.iter()
+                    .filter_map(|row| row["id"].as_str())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    /// Reads the document `id` from the database associated with this instance and deserializes it into
+    /// `T`, returning `None` if there is no such document or it has been deleted. Unlike
+    /// [couch_rs::database::Database::get](https://docs.rs/couch_rs/latest/couch_rs/database/struct.Database.html#method.get),
+    /// `T` only needs to implement `Deserialize`, so assertions can read documents into any shape. In a
+    /// shared database, `id` is looked up within this instance's [namespace](TestRepo::namespace).
+    ///
+    /// ```rust
+    /// # #[derive(serde::Deserialize)]
+    /// # struct User { name: String }
+    /// # async fn example(repo: &couch_rs_test::TestRepo) -> Result<(), couch_rs::error::CouchError> {
+    /// let user: Option<User> = repo.get_typed("alice").await?;
+    /// assert_eq!(user.map(|u| u.name).as_deref(), Some("Alice"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_typed<T: DeserializeOwned>(&self, id: &str) -> Result<Option<T>, CouchError> {
+        let response = self
+            .client
+            .req(
+                Method::GET,
+                &format!(
+                    "{}/{}",
+                    encode_db_name(&self.cfg.db_name),
+                    encode_doc_id(&self.namespaced_id(id))
+                ),
+                None,
+            )
+            .send()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let doc: Value = response.error_for_status()?.json().await?;
+        Ok(Some(serde_json::from_value(doc)?))
+    }
+}
+
+// encodes a document id for a request path, keeping the slash of design and local document ids, which
+// CouchDB expects unencoded
+pub(crate) fn encode_doc_id(id: &str) -> String {
+    for prefix in ["_design/", "_local/"] {
+        if let Some(name) = id.strip_prefix(prefix) {
+            return format!("{}{}", prefix, encode_db_name(name));
+        }
+    }
+    encode_db_name(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_doc_id_keeps_the_slash_of_design_and_local_ids() {
+        assert_eq!(encode_doc_id("_design/app"), "_design/app");
+        assert_eq!(encode_doc_id("_local/foo"), "_local/foo");
+        assert_eq!(encode_doc_id("_local/a/b"), "_local/a%2Fb");
+    }
+
+    #[test]
+    fn encode_doc_id_encodes_regular_ids_as_one_segment() {
+        assert_eq!(encode_doc_id("alice"), "alice");
+        assert_eq!(encode_doc_id("users/alice"), "users%2Falice");
+        assert_eq!(
+            encode_doc_id("org.couchdb.user:bob"),
+            "org%2Ecouchdb%2Euser%3Abob"
+        );
+    }
+}
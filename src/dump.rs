@@ -0,0 +1,61 @@
+use std::{error::Error, fs, path::Path};
+
+use couch_rs::Client;
+use serde_json::{json, Value};
+
+use crate::{compat::Method, naming::encode_db_name, TestRepo};
+
+impl TestRepo {
+    /// Writes all documents of the database associated with this instance, including design documents,
+    /// to a JSON file at `path`, returning the number of documents written. The file holds an object
+    /// with a `docs` array, the format accepted by the `_bulk_docs` endpoint.
+    ///
+    /// Databases can also be dumped automatically when the test owning them panics; see
+    /// [TestRepoConfig::with_dump_on_failure](crate::TestRepoConfig::with_dump_on_failure).
+    pub async fn dump_to<P: AsRef<Path>>(&self, path: P) -> Result<usize, Box<dyn Error>> {
+        dump_database(&self.client, &self.cfg.db_name, path.as_ref()).await
+    }
+}
+
+pub(crate) async fn dump_database(
+    client: &Client,
+    db_name: &str,
+    path: &Path,
+) -> Result<usize, Box<dyn Error>> {
+    let docs = all_docs(client, db_name).await?;
+
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(
+        path,
+        serde_json::to_string_pretty(&json!({ "docs": docs }))?,
+    )?;
+
+    Ok(docs.len())
+}
+
+/// Reads all documents of `db_name`, including design documents, in id order.
+pub(crate) async fn all_docs(client: &Client, db_name: &str) -> Result<Vec<Value>, Box<dyn Error>> {
+    let response: Value = client
+        .req(
+            Method::GET,
+            &format!("{}/_all_docs", encode_db_name(db_name)),
+            None,
+        )
+        .query(&[("include_docs", "true")])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    Ok(response["rows"]
+        .as_array()
+        .map(|rows| {
+            rows.iter()
+                .filter_map(|row| row.get("doc").cloned())
+                .collect()
+        })
+        .unwrap_or_default())
+}
@@ -0,0 +1,370 @@
+use std::{error::Error, path::Path};
+
+use crate::{
+    fixtures::{docs_from_export, parse_export},
+    TestRepo,
+};
+
+/// Embeds the fixture file at `path`, relative to the directory of the crate's `Cargo.toml`, into the
+/// test binary at compile time and checks that it holds valid JSON, failing compilation if it does not.
+/// Tests seeding from the returned [EmbeddedFixture] with [TestRepo::with_embedded_fixture] then do not
+/// depend on the working directory they are run from, and a broken fixture is caught before any test
+/// runs.
+///
+/// ```rust,ignore
+/// # async fn example(repo: &couch_rs_test::TestRepo) -> Result<(), Box<dyn std::error::Error>> {
+/// use couch_rs_test::include_fixture;
+///
+/// repo.with_embedded_fixture(include_fixture!("tests/fixtures/users.json"))
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The file may be in any format accepted by
+/// [TestRepo::with_data_from_export](crate::TestRepo::with_data_from_export) but JSON lines, which must
+/// be embedded with `repo.with_data_from_str(include_str!(..))`. Only the JSON syntax is checked at
+/// compile time; the shape of the export is checked when seeding. The check adds roughly a second of
+/// compile time per hundred kilobytes of fixture, and the compiler warns about fixtures of a few
+/// megabytes taking long to check; embed those with
+/// `repo.with_data_from_str(include_str!(..))` instead, which is only checked when seeding.
+#[macro_export]
+macro_rules! include_fixture {
+    ($path:expr) => {{
+        const TEXT: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/", $path));
+        // checking a large fixture takes longer than the compiler lets constants take by default
+        #[allow(long_running_const_eval)]
+        const _: () = assert!(
+            $crate::__is_valid_json(TEXT),
+            concat!("fixture ", $path, " does not hold valid JSON")
+        );
+        $crate::EmbeddedFixture::new($path, TEXT)
+    }};
+}
+
+/// A fixture file embedded into the test binary by [include_fixture!].
+#[derive(Clone, Copy, Debug)]
+pub struct EmbeddedFixture {
+    path: &'static str,
+    text: &'static str,
+}
+
+impl EmbeddedFixture {
+    /// Wrap the content `text` of the fixture file at `path`; use [include_fixture!] rather than
+    /// calling this directly.
+    pub const fn new(path: &'static str, text: &'static str) -> EmbeddedFixture {
+        EmbeddedFixture { path, text }
+    }
+
+    /// The path of the fixture file, relative to the directory of the crate's `Cargo.toml`.
+    pub fn path(&self) -> &'static str {
+        self.path
+    }
+
+    /// The content of the fixture file.
+    pub fn text(&self) -> &'static str {
+        self.text
+    }
+}
+
+impl TestRepo {
+    /// Seeds the database associated with this instance from a fixture embedded with
+    /// [include_fixture!], like [TestRepo::with_data_from_export] does from a file read at runtime.
+    /// Returns the number of documents pushed.
+    pub async fn with_embedded_fixture(
+        &self,
+        fixture: EmbeddedFixture,
+    ) -> Result<usize, Box<dyn Error>> {
+        self.with_data_from_text(Path::new(fixture.path), fixture.text)
+            .await
+    }
+
+    /// Seeds the database associated with this instance from `text`, holding documents in any format
+    /// accepted by [TestRepo::with_data_from_export], for fixtures built or embedded by the test itself.
+    /// Returns the number of documents pushed.
+    pub async fn with_data_from_str(&self, text: &str) -> Result<usize, Box<dyn Error>> {
+        self.with_data_from_text(Path::new("<string>"), text).await
+    }
+
+    // seeds from the export `text`, read from `path`, which names the fixture in validation errors
+    pub(crate) async fn with_data_from_text(
+        &self,
+        path: &Path,
+        text: &str,
+    ) -> Result<usize, Box<dyn Error>> {
+        // a byte order mark, which serde_json rejects, is skipped as include_fixture! does
+        let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+        #[cfg(feature = "jsonschema")]
+        crate::schema::validate_fixture(&self.cfg, path, text)?;
+        #[cfg(not(feature = "jsonschema"))]
+        let _ = path;
+
+        let export = parse_export(text)?;
+        let mut docs = docs_from_export(export)?;
+        Ok(self.with_data(&mut docs).await?)
+    }
+}
+
+// returned by the parsers below in place of the position after the parsed value, if it is invalid
+const INVALID: usize = usize::MAX;
+
+// objects and arrays nested this deep are rejected, as serde_json does when seeding
+const MAX_DEPTH: usize = 128;
+
+/// Checks that `text` is a single valid JSON value, in a way that can run at compile time; used by
+/// [include_fixture!].
+#[doc(hidden)]
+pub const fn is_valid_json(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    // a byte order mark, as some editors write, is skipped, as it is when seeding
+    let start = match bytes {
+        [0xef, 0xbb, 0xbf, ..] => 3,
+        _ => 0,
+    };
+    let end = value(bytes, start, 0);
+    end != INVALID && skip_whitespace(bytes, end) == bytes.len()
+}
+
+const fn skip_whitespace(bytes: &[u8], mut i: usize) -> usize {
+    while i < bytes.len() && matches!(bytes[i], b' ' | b'\t' | b'\n' | b'\r') {
+        i += 1;
+    }
+    i
+}
+
+const fn value(bytes: &[u8], i: usize, depth: usize) -> usize {
+    let i = skip_whitespace(bytes, i);
+    if i >= bytes.len() {
+        return INVALID;
+    }
+    match bytes[i] {
+        b'{' | b'[' if depth + 1 >= MAX_DEPTH => INVALID,
+        b'{' => object(bytes, i + 1, depth + 1),
+        b'[' => array(bytes, i + 1, depth + 1),
+        b'"' => string(bytes, i + 1),
+        b't' => literal(bytes, i, b"true"),
+        b'f' => literal(bytes, i, b"false"),
+        b'n' => literal(bytes, i, b"null"),
+        b'-' | b'0'..=b'9' => number(bytes, i),
+        _ => INVALID,
+    }
+}
+
+const fn object(bytes: &[u8], i: usize, depth: usize) -> usize {
+    let mut i = skip_whitespace(bytes, i);
+    if i < bytes.len() && bytes[i] == b'}' {
+        return i + 1;
+    }
+    loop {
+        i = skip_whitespace(bytes, i);
+        if i >= bytes.len() || bytes[i] != b'"' {
+            return INVALID;
+        }
+        i = string(bytes, i + 1);
+        if i == INVALID {
+            return INVALID;
+        }
+        i = skip_whitespace(bytes, i);
+        if i >= bytes.len() || bytes[i] != b':' {
+            return INVALID;
+        }
+        i = value(bytes, i + 1, depth);
+        if i == INVALID {
+            return INVALID;
+        }
+        i = skip_whitespace(bytes, i);
+        if i >= bytes.len() {
+            return INVALID;
+        }
+        match bytes[i] {
+            b'}' => return i + 1,
+            b',' => i += 1,
+            _ => return INVALID,
+        }
+    }
+}
+
+const fn array(bytes: &[u8], i: usize, depth: usize) -> usize {
+    let mut i = skip_whitespace(bytes, i);
+    if i < bytes.len() && bytes[i] == b']' {
+        return i + 1;
+    }
+    loop {
+        i = value(bytes, i, depth);
+        if i == INVALID {
+            return INVALID;
+        }
+        i = skip_whitespace(bytes, i);
+        if i >= bytes.len() {
+            return INVALID;
+        }
+        match bytes[i] {
+            b']' => return i + 1,
+            b',' => i += 1,
+            _ => return INVALID,
+        }
+    }
+}
+
+// parses the rest of a string whose opening quote precedes `i`
+const fn string(bytes: &[u8], mut i: usize) -> usize {
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => return i + 1,
+            b'\\' if i + 1 < bytes.len() => match bytes[i + 1] {
+                b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't' => i += 2,
+                b'u' => {
+                    let mut digit = i + 2;
+                    while digit < i + 6 {
+                        if digit >= bytes.len() || !bytes[digit].is_ascii_hexdigit() {
+                            return INVALID;
+                        }
+                        digit += 1;
+                    }
+                    i += 6;
+                }
+                _ => return INVALID,
+            },
+            0x00..=0x1f | b'\\' => return INVALID,
+            _ => i += 1,
+        }
+    }
+    INVALID
+}
+
+const fn number(bytes: &[u8], mut i: usize) -> usize {
+    if bytes[i] == b'-' {
+        i += 1;
+    }
+    if i < bytes.len() && bytes[i] == b'0' {
+        i += 1;
+    } else {
+        let start = i;
+        i = digits(bytes, i);
+        if i == start {
+            return INVALID;
+        }
+    }
+    if i < bytes.len() && bytes[i] == b'.' {
+        let start = i + 1;
+        i = digits(bytes, start);
+        if i == start {
+            return INVALID;
+        }
+    }
+    if i < bytes.len() && matches!(bytes[i], b'e' | b'E') {
+        i += 1;
+        if i < bytes.len() && matches!(bytes[i], b'+' | b'-') {
+            i += 1;
+        }
+        let start = i;
+        i = digits(bytes, i);
+        if i == start {
+            return INVALID;
+        }
+    }
+    i
+}
+
+const fn digits(bytes: &[u8], mut i: usize) -> usize {
+    while i < bytes.len() && bytes[i].is_ascii_digit() {
+        i += 1;
+    }
+    i
+}
+
+const fn literal(bytes: &[u8], i: usize, expected: &[u8]) -> usize {
+    let mut k = 0;
+    while k < expected.len() {
+        if i + k >= bytes.len() || bytes[i + k] != expected[k] {
+            return INVALID;
+        }
+        k += 1;
+    }
+    i + expected.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // whether `text` holds valid JSON at compile time, asserting that seeding agrees
+    fn valid(text: &str) -> bool {
+        let valid = is_valid_json(text);
+        let parsed = serde_json::from_str::<serde_json::Value>(
+            text.strip_prefix('\u{feff}').unwrap_or(text),
+        );
+        assert_eq!(valid, parsed.is_ok(), "{:?}", text);
+        valid
+    }
+
+    fn nested(depth: usize) -> String {
+        format!("{}1{}", "[".repeat(depth), "]".repeat(depth))
+    }
+
+    #[test]
+    fn literals() {
+        assert!(valid("true"));
+        assert!(valid("false"));
+        assert!(valid(" null \n"));
+        assert!(!valid("nul"));
+        assert!(!valid("True"));
+        assert!(!valid("nulls"));
+        assert!(!valid(""));
+    }
+
+    #[test]
+    fn numbers() {
+        for number in ["0", "-0", "12", "-3.25", "1e5", "1E+5", "2.5e-3"] {
+            assert!(valid(number), "{}", number);
+        }
+        for number in ["01", "1.", "-", "1e", "1e+", ".5", "+1", "0x1f"] {
+            assert!(!valid(number), "{}", number);
+        }
+    }
+
+    #[test]
+    fn strings_and_escapes() {
+        assert!(valid(r#""plain""#));
+        assert!(valid(r#""\" \\ \/ \b \f \n \r \t""#));
+        assert!(valid(r#""é😀""#));
+        assert!(valid("\"caf\u{e9}\""));
+        assert!(!valid(r#""\x41""#));
+        assert!(!valid(r#""\u12""#));
+        assert!(!valid("\"line\nbreak\""));
+        assert!(!valid(r#""unterminated"#));
+    }
+
+    #[test]
+    fn objects_and_arrays() {
+        assert!(valid(r#"{"docs": [{"_id": "alice", "tags": []}, {}]}"#));
+        assert!(!valid(r#"{"_id": "alice",}"#));
+        assert!(!valid(r#"[1, 2,]"#));
+        assert!(!valid(r#"{"_id" "alice"}"#));
+        assert!(!valid(r#"{_id: "alice"}"#));
+        assert!(!valid(r#"[1 2]"#));
+        assert!(!valid(r#"[1] [2]"#));
+    }
+
+    #[test]
+    fn byte_order_mark() {
+        assert!(valid("\u{feff}[{\"_id\": \"alice\"}]"));
+        assert!(!valid("\u{feff}"));
+    }
+
+    #[test]
+    fn depth() {
+        assert!(valid(&nested(MAX_DEPTH - 1)));
+        assert!(!valid(&nested(MAX_DEPTH)));
+        assert!(valid(&format!(
+            "{}{}",
+            "[".repeat(MAX_DEPTH - 1),
+            "]".repeat(MAX_DEPTH - 1)
+        )));
+        assert!(!valid(&format!(
+            "{}{}",
+            "{\"a\":".repeat(MAX_DEPTH),
+            "}".repeat(MAX_DEPTH)
+        )));
+    }
+}
@@ -0,0 +1,171 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use couch_rs::error::CouchError;
+use serde_json::Value;
+
+use crate::{MemoryBackend, TestBackend, TestRepoConfig};
+
+/// An operation of a [TestBackend] that a [FailingRepo] can fail.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BackendOperation {
+    /// [TestBackend::seed].
+    Seed,
+    /// [TestBackend::get].
+    Get,
+    /// [TestBackend::all_docs].
+    AllDocs,
+    /// [TestBackend::find].
+    Find,
+}
+
+/// A [TestBackend] wrapping another backend, [MemoryBackend] by default, that returns configured errors
+/// in place of its results, so that application setup code written against `TestBackend` can itself be
+/// tested for its handling of failed seeding or queries. Operations without a configured failure are
+/// passed through to the wrapped backend.
+///
+/// ```rust
+/// # use serde_json::json;
+/// use couch_rs::error::CouchError;
+/// use couch_rs_test::{BackendOperation, FailingRepo, TestBackend, TestRepoConfig};
+/// use http::StatusCode;
+///
+/// # async fn example() -> Result<(), CouchError> {
+/// let cfg = TestRepoConfig::new("http://localhost:5984", "admin", "password", "users");
+/// let backend: FailingRepo = FailingRepo::create(cfg).await?;
+/// backend.fail_next(
+///     BackendOperation::Seed,
+///     CouchError::new("service unavailable".to_string(), StatusCode::SERVICE_UNAVAILABLE),
+/// );
+/// assert!(backend.seed(&mut [json!({"name": "alice"})]).await.is_err());
+/// assert!(backend.seed(&mut [json!({"name": "alice"})]).await.is_ok());
+/// assert_eq!(backend.calls(BackendOperation::Seed), 2);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct FailingRepo<B: TestBackend = MemoryBackend> {
+    inner: B,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    failures: HashMap<BackendOperation, Failure>,
+    calls: HashMap<BackendOperation, usize>,
+}
+
+#[derive(Debug)]
+struct Failure {
+    error: CouchError,
+    // how many more calls fail, or None to fail every call
+    remaining: Option<usize>,
+}
+
+impl<B: TestBackend> FailingRepo<B> {
+    /// Wrap `inner`, initially without any failures.
+    pub fn wrap(inner: B) -> FailingRepo<B> {
+        FailingRepo {
+            inner,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Fail every following call of `operation` with `error`, replacing any failure configured for it.
+    pub fn fail(&self, operation: BackendOperation, error: CouchError) {
+        self.set_failure(operation, error, None);
+    }
+
+    /// Fail only the next call of `operation` with `error`, replacing any failure configured for it.
+    pub fn fail_next(&self, operation: BackendOperation, error: CouchError) {
+        self.fail_times(operation, 1, error);
+    }
+
+    /// Fail the next `times` calls of `operation` with `error`, replacing any failure configured for
+    /// it; for example to check that setup code retries a transient error. A `times` of 0 removes the
+    /// failure.
+    pub fn fail_times(&self, operation: BackendOperation, times: usize, error: CouchError) {
+        match times {
+            0 => self.recover(operation),
+            _ => self.set_failure(operation, error, Some(times)),
+        }
+    }
+
+    /// Stop failing calls of `operation`.
+    pub fn recover(&self, operation: BackendOperation) {
+        self.lock().failures.remove(&operation);
+    }
+
+    /// The number of calls of `operation` so far, failed or not.
+    pub fn calls(&self, operation: BackendOperation) -> usize {
+        self.lock().calls.get(&operation).copied().unwrap_or(0)
+    }
+
+    /// The wrapped backend, for inspecting or seeding it without injected failures.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    fn set_failure(
+        &self,
+        operation: BackendOperation,
+        error: CouchError,
+        remaining: Option<usize>,
+    ) {
+        self.lock()
+            .failures
+            .insert(operation, Failure { error, remaining });
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // counts a call of `operation`, returning the error it is to fail with, if any
+    fn call(&self, operation: BackendOperation) -> Result<(), CouchError> {
+        let mut state = self.lock();
+        *state.calls.entry(operation).or_default() += 1;
+
+        let failure = match state.failures.get_mut(&operation) {
+            Some(failure) => failure,
+            None => return Ok(()),
+        };
+        let error = failure.error.clone();
+        if let Some(remaining) = &mut failure.remaining {
+            *remaining -= 1;
+            if *remaining == 0 {
+                state.failures.remove(&operation);
+            }
+        }
+        Err(error)
+    }
+}
+
+impl<B: TestBackend> TestBackend for FailingRepo<B> {
+    async fn create(config: TestRepoConfig) -> Result<FailingRepo<B>, CouchError> {
+        Ok(FailingRepo::wrap(B::create(config).await?))
+    }
+
+    async fn seed(&self, docs: &mut [Value]) -> Result<usize, CouchError> {
+        self.call(BackendOperation::Seed)?;
+        self.inner.seed(docs).await
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<Value>, CouchError> {
+        self.call(BackendOperation::Get)?;
+        self.inner.get(id).await
+    }
+
+    async fn all_docs(&self) -> Result<Vec<Value>, CouchError> {
+        self.call(BackendOperation::AllDocs)?;
+        self.inner.all_docs().await
+    }
+
+    async fn find(&self, selector: &Value) -> Result<Vec<Value>, CouchError> {
+        self.call(BackendOperation::Find)?;
+        self.inner.find(selector).await
+    }
+
+    async fn destroy(self) {
+        self.inner.destroy().await
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::BTreeSet;
+
+use couch_rs::error::CouchError;
+use serde_json::{json, Value};
+
+use crate::{compat::Method, naming::encode_db_name, TestRepo};
+
+impl TestRepo {
+    /// Installs `function`, the JavaScript source of a changes filter function, as the filter `name` of
+    /// the design document `_design/{ddoc}` of the database associated with this instance, creating the
+    /// design document if needed. Other contents of an existing design document are kept. The filter is
+    /// then available as `{ddoc}/{name}`, for example to [TestRepo::assert_filter_passes].
+    ///
+    /// ```rust
+    /// # async fn example(repo: &couch_rs_test::TestRepo) -> Result<(), couch_rs::error::CouchError> {
+    /// repo.install_filter(
+    ///     "sync",
+    ///     "by_owner",
+    ///     "function(doc, req) { return doc.owner === req.query.owner; }",
+    /// )
+    /// .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn install_filter(
+        &self,
+        ddoc: &str,
+        name: &str,
+        function: &str,
+    ) -> Result<(), CouchError> {
+        let id = format!("_design/{}", ddoc);
+        let mut design_doc = match self.db.get_raw(&id).await {
+            Ok(existing) => existing,
+            Err(e) if e.is_not_found() => json!({ "_id": id }),
+            Err(e) => return Err(e),
+        };
+        if !design_doc["filters"].is_object() {
+            design_doc["filters"] = json!({});
+        }
+        design_doc["filters"][name] = Value::from(function);
+
+        match self.write_raw(&design_doc).await? {
+            (status, _) if status.is_success() => Ok(()),
+            (status, error) => Err(CouchError::new(
+                format!("Failed to install filter {} in {}: {}", name, id, error),
+                status,
+            )),
+        }
+    }
+
+    /// Returns the ids of the documents the changes filter `filter`, named `{ddoc}/{name}`, passes from
+    /// the changes feed of the database associated with this instance, in the order of the feed.
+    /// `params` are passed to the filter as `req.query`. Deleted documents are included when the filter
+    /// passes them.
+    pub async fn filtered_ids(
+        &self,
+        filter: &str,
+        params: &[(&str, &str)],
+    ) -> Result<Vec<String>, CouchError> {
+        let changes: Value = self
+            .client
+            .req(
+                Method::GET,
+                &format!("{}/_changes", encode_db_name(&self.cfg.db_name)),
+                None,
+            )
+            .query(&[("filter", filter)])
+            .query(params)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(changes["results"]
+            .as_array()
+            .map(|results| {
+                results
+                    .iter()
+                    .filter_map(|change| change["id"].as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    /// Asserts that the changes filter `filter`, named `{ddoc}/{name}`, passes exactly the documents
+    /// with the ids `expected` from the changes feed of the database associated with this instance, in
+    /// any order. `params` are passed to the filter as `req.query`. Use this to validate the filtered
+    /// feeds sync clients and gateways subscribe to.
+    ///
+    /// ```rust
+    /// # async fn example(repo: &couch_rs_test::TestRepo) {
+    /// repo.assert_filter_passes("sync/by_owner", &[("owner", "alice")], &["order-1", "order-3"])
+    ///     .await;
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the filter passes other documents, or if the changes feed cannot be read.
+    pub async fn assert_filter_passes(
+        &self,
+        filter: &str,
+        params: &[(&str, &str)],
+        expected: &[&str],
+    ) {
+        let passed = self
+            .filtered_ids(filter, params)
+            .await
+            .unwrap_or_else(|e| panic!("Failed to read changes filtered by {}: {}", filter, e));
+
+        let passed: BTreeSet<&str> = passed.iter().map(String::as_str).collect();
+        let expected: BTreeSet<&str> = expected.iter().copied().collect();
+        let missing: Vec<_> = expected.difference(&passed).collect();
+        let unexpected: Vec<_> = passed.difference(&expected).collect();
+        assert!(
+            missing.is_empty() && unexpected.is_empty(),
+            "Filter {} of database {} does not pass the expected documents; missing: {:?}, unexpected: {:?}",
+            filter,
+            self.db.name(),
+            missing,
+            unexpected
+        );
+    }
+}
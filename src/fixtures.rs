@@ -0,0 +1,321 @@
+use std::{error::Error, fs, path::Path};
+
+use couch_rs::error::CouchError;
+use serde_json::Value;
+
+use crate::{compat::StatusCode, TestRepo};
+
+impl TestRepo {
+    /// Seeds the database associated with this instance from a CouchDB export file, such as the output
+    /// of `couchdb-dump`, a saved `_all_docs?include_docs=true` response or a file written by
+    /// [TestRepo::dump_to]. This allows realistic, production-shaped data snapshots to be used as fixtures
+    /// directly.
+    ///
+    /// The file may hold an object with a `rows` array (the `_all_docs` format), an object with a `docs`
+    /// array (the `_bulk_docs` format), a plain array of documents, or JSON lines holding one document
+    /// or one array of documents per line, as `couchbackup` writes. The `_rev` of every document is
+    /// removed so that the documents are created afresh, as are attachment stubs, whose content is not
+    /// part of the export. Returns the number of documents pushed, as [TestRepo::with_data] does.
+    ///
+    /// With the `jsonschema` feature, documents are validated against the schemas registered with
+    /// [TestRepoConfig::with_schema](crate::TestRepoConfig::with_schema) before anything is pushed.
+    pub async fn with_data_from_export<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<usize, Box<dyn Error>> {
+        let text = fs::read_to_string(path.as_ref())?;
+        self.with_data_from_text(path.as_ref(), &text).await
+    }
+
+    /// Seeds the database associated with this instance from a JSON value holding either an array of
+    /// documents or an object whose fields each hold an array of documents, such as one collection per
+    /// document type; the collections are flattened into the bulk inserts of [TestRepo::with_data]. This
+    /// suits small inline fixtures written with `serde_json::json!`. Returns the number of documents
+    /// pushed.
+    ///
+    /// ```rust
+    /// # use serde_json::json;
+    /// # async fn example(repo: &couch_rs_test::TestRepo) -> Result<(), couch_rs::error::CouchError> {
+    /// repo.with_value(json!({
+    ///     "users": [{"_id": "alice", "type": "user"}, {"_id": "bob", "type": "user"}],
+    ///     "orders": [{"type": "order", "user": "alice"}],
+    /// }))
+    /// .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Collections are inserted in the alphabetical order of their names. Exports in the `_all_docs` or
+    /// `_bulk_docs` format are loaded with [TestRepo::with_data_from_str] instead.
+    pub async fn with_value(&self, value: Value) -> Result<usize, CouchError> {
+        let mut docs = docs_from_value(value)?;
+        self.with_data(&mut docs).await
+    }
+}
+
+// the documents of a value accepted by TestRepo::with_value
+fn docs_from_value(value: Value) -> Result<Vec<Value>, CouchError> {
+    let docs = match value {
+        Value::Array(docs) => docs,
+        Value::Object(collections) => {
+            let mut docs = vec![];
+            for (name, collection) in collections {
+                match collection {
+                    Value::Array(collection) => docs.extend(collection),
+                    _ => {
+                        return Err(invalid_value(&format!(
+                            "collection {} is not an array",
+                            name
+                        )))
+                    }
+                }
+            }
+            docs
+        }
+        _ => return Err(invalid_value("expected an array or an object of arrays")),
+    };
+
+    match docs.iter().all(Value::is_object) {
+        true => Ok(docs),
+        false => Err(invalid_value("documents must be JSON objects")),
+    }
+}
+
+/// Parses the text of a CouchDB export: a single JSON value, or JSON lines, which are returned as an
+/// array of the documents on all lines. The error is that of parsing `text` as a single value.
+pub(crate) fn parse_export(text: &str) -> Result<Value, serde_json::Error> {
+    let error = match serde_json::from_str(text) {
+        Ok(export) => return Ok(export),
+        Err(e) => e,
+    };
+    match json_lines(text) {
+        Some(docs) if !docs.is_empty() => {
+            Ok(Value::Array(docs.into_iter().map(|(_, doc)| doc).collect()))
+        }
+        _ => Err(error),
+    }
+}
+
+// the documents of each non-blank line of `text` with the line they are on, if every line holds a
+// JSON value; a line holding an array contributes its items
+fn json_lines(text: &str) -> Option<Vec<(usize, Value)>> {
+    let mut docs = vec![];
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(line).ok()? {
+            Value::Array(batch) => docs.extend(batch.into_iter().map(|doc| (i + 1, doc))),
+            doc => docs.push((i + 1, doc)),
+        }
+    }
+    Some(docs)
+}
+
+/// Extracts the documents from a CouchDB export, prepared for insertion into a fresh database.
+pub(crate) fn docs_from_export(export: Value) -> Result<Vec<Value>, CouchError> {
+    let docs = match export {
+        Value::Array(docs) => docs,
+        Value::Object(mut export) => match (export.remove("rows"), export.remove("docs")) {
+            (Some(Value::Array(rows)), _) => rows
+                .into_iter()
+                .filter_map(|mut row| row.get_mut("doc").map(Value::take))
+                .filter(|doc| doc.is_object())
+                .collect(),
+            (_, Some(Value::Array(docs))) => docs,
+            _ => return Err(invalid_export("expected a `rows` or `docs` array")),
+        },
+        _ => return Err(invalid_export("expected an object or an array")),
+    };
+
+    docs.into_iter()
+        .map(|mut doc| {
+            let fields = doc
+                .as_object_mut()
+                .ok_or_else(|| invalid_export("documents must be JSON objects"))?;
+            fields.remove("_rev");
+
+            if let Some(Value::Object(attachments)) = fields.get_mut("_attachments") {
+                attachments
+                    .retain(|_, attachment| attachment.get("stub") != Some(&Value::Bool(true)));
+                if attachments.is_empty() {
+                    fields.remove("_attachments");
+                }
+            }
+            Ok(doc)
+        })
+        .collect()
+}
+
+/// The documents of an export in the formats accepted by [docs_from_export], each with the line of
+/// `text` it starts on. Documents that cannot be located are left out.
+pub(crate) fn locate_docs(text: &str) -> Vec<(usize, Value)> {
+    use serde_json::value::RawValue;
+    use std::collections::BTreeMap;
+
+    let located = |raw: &RawValue| {
+        let offset = raw.get().as_ptr() as usize - text.as_ptr() as usize;
+        let line = text[..offset].matches('\n').count() + 1;
+        serde_json::from_str(raw.get()).ok().map(|doc| (line, doc))
+    };
+
+    let top: &RawValue = match serde_json::from_str(text) {
+        Ok(top) => top,
+        Err(_) => return json_lines(text).unwrap_or_default(),
+    };
+    fn items(raw: &RawValue) -> Option<Vec<&RawValue>> {
+        serde_json::from_str(raw.get()).ok()
+    }
+    if let Some(docs) = items(top) {
+        return docs.into_iter().filter_map(located).collect();
+    }
+
+    let export: BTreeMap<String, &RawValue> = serde_json::from_str(top.get()).unwrap_or_default();
+    match (
+        export.get("rows").and_then(|rows| items(rows)),
+        export.get("docs").and_then(|docs| items(docs)),
+    ) {
+        (Some(rows), _) => rows
+            .into_iter()
+            .filter_map(|row| {
+                let row: BTreeMap<String, &RawValue> = serde_json::from_str(row.get()).ok()?;
+                located(row.get("doc")?)
+            })
+            .collect(),
+        (_, Some(docs)) => docs.into_iter().filter_map(located).collect(),
+        _ => vec![],
+    }
+}
+
+fn invalid_value(reason: &str) -> CouchError {
+    CouchError::new(
+        format!("Invalid fixture value: {}", reason),
+        StatusCode::BAD_REQUEST,
+    )
+}
+
+fn invalid_export(reason: &str) -> CouchError {
+    CouchError::new(
+        format!("Invalid CouchDB export: {}", reason),
+        StatusCode::BAD_REQUEST,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn ids(docs: &[Value]) -> Vec<&str> {
+        docs.iter()
+            .map(|doc| doc["_id"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn export_rows_are_read_from_their_doc() {
+        let export = json!({
+            "total_rows": 3,
+            "rows": [
+                {"id": "alice", "key": "alice", "value": {"rev": "1-a"}, "doc": {"_id": "alice", "_rev": "1-a"}},
+                {"id": "bob", "key": "bob", "value": {"rev": "2-b"}, "doc": {"_id": "bob", "_rev": "2-b"}},
+                {"key": "carol", "error": "not_found"}
+            ]
+        });
+        let docs = docs_from_export(export).unwrap();
+        assert_eq!(docs, [json!({"_id": "alice"}), json!({"_id": "bob"})]);
+    }
+
+    #[test]
+    fn export_docs_are_read() {
+        let export = json!({"docs": [{"_id": "alice"}, {"_id": "bob", "_rev": "1-b"}]});
+        assert_eq!(ids(&docs_from_export(export).unwrap()), ["alice", "bob"]);
+    }
+
+    #[test]
+    fn export_bare_array_is_read() {
+        let export = json!([{"_id": "alice"}, {"_id": "bob"}]);
+        assert_eq!(ids(&docs_from_export(export).unwrap()), ["alice", "bob"]);
+    }
+
+    #[test]
+    fn export_json_lines_are_read() {
+        let text = "{\"_id\": \"alice\", \"_rev\": \"1-a\"}\n\n[{\"_id\": \"bob\"}, {\"_id\": \"carol\"}]\n";
+        let docs = docs_from_export(parse_export(text).unwrap()).unwrap();
+        assert_eq!(ids(&docs), ["alice", "bob", "carol"]);
+        assert_eq!(docs[0], json!({"_id": "alice"}));
+
+        let lines: Vec<usize> = locate_docs(text)
+            .into_iter()
+            .map(|(line, _)| line)
+            .collect();
+        assert_eq!(lines, [1, 3, 3]);
+    }
+
+    #[test]
+    fn export_attachment_stubs_are_removed() {
+        let export = json!([
+            {"_id": "alice", "_attachments": {"photo.png": {"stub": true, "length": 42}}},
+            {"_id": "bob", "_attachments": {
+                "photo.png": {"stub": true},
+                "notes.txt": {"content_type": "text/plain", "data": "aGk="}
+            }}
+        ]);
+        let docs = docs_from_export(export).unwrap();
+        assert_eq!(docs[0], json!({"_id": "alice"}));
+        assert_eq!(
+            docs[1]["_attachments"],
+            json!({"notes.txt": {"content_type": "text/plain", "data": "aGk="}})
+        );
+    }
+
+    #[test]
+    fn malformed_exports_are_rejected() {
+        for export in [
+            json!({"total_rows": 0}),
+            json!({"rows": {"doc": {}}}),
+            json!("alice"),
+            json!([{"_id": "alice"}, "bob"]),
+        ] {
+            let e = docs_from_export(export.clone()).unwrap_err();
+            assert_eq!(e.status(), Some(StatusCode::BAD_REQUEST), "{}", export);
+            assert!(e.to_string().contains("Invalid CouchDB export"), "{}", e);
+        }
+    }
+
+    #[test]
+    fn malformed_text_is_rejected() {
+        assert!(parse_export("").is_err());
+        assert!(parse_export("{\"_id\": \"alice\"}\n{\"_id\": ").is_err());
+        let e = parse_export("[{\"_id\": \"alice\"},]").unwrap_err();
+        assert_eq!(e.line(), 1);
+    }
+
+    #[test]
+    fn value_arrays_and_collections_are_read() {
+        let docs = docs_from_value(json!([{"_id": "alice"}])).unwrap();
+        assert_eq!(ids(&docs), ["alice"]);
+
+        let docs = docs_from_value(json!({
+            "users": [{"_id": "alice"}, {"_id": "bob"}],
+            "orders": [{"_id": "order-1"}]
+        }))
+        .unwrap();
+        assert_eq!(ids(&docs), ["order-1", "alice", "bob"]);
+    }
+
+    #[test]
+    fn malformed_values_are_rejected() {
+        for value in [
+            json!("alice"),
+            json!({"users": {"_id": "alice"}}),
+            json!([{"_id": "alice"}, 42]),
+            json!({"users": [[{"_id": "alice"}]]}),
+        ] {
+            let e = docs_from_value(value.clone()).unwrap_err();
+            assert_eq!(e.status(), Some(StatusCode::BAD_REQUEST), "{}", value);
+            assert!(e.to_string().contains("Invalid fixture value"), "{}", e);
+        }
+    }
+}
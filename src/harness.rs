@@ -0,0 +1,279 @@
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use couch_rs::types::{find::SortSpec, index::IndexFields};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    couchapp::design_doc_from_dir,
+    fixtures::{docs_from_export, parse_export},
+    TestRepo, TestRepoConfig,
+};
+
+/// Declarative description of the databases a [TestHarness] creates, typically all databases an
+/// application uses.
+///
+/// The spec is deserializable, so it may also be kept in a file next to the tests.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct HarnessSpec {
+    #[serde(default)]
+    databases: Vec<DatabaseSpec>,
+}
+
+impl HarnessSpec {
+    /// Create a spec without any databases.
+    pub fn new() -> HarnessSpec {
+        HarnessSpec::default()
+    }
+
+    /// Add a database to the spec.
+    pub fn with_database(mut self, database: DatabaseSpec) -> HarnessSpec {
+        self.databases.push(database);
+        self
+    }
+}
+
+/// One database of a [HarnessSpec]: its name, design documents and seed documents.
+#[derive(Deserialize, Clone, Debug)]
+pub struct DatabaseSpec {
+    name: String,
+    #[serde(default)]
+    design_docs: Vec<Value>,
+    #[serde(default)]
+    indexes: Vec<MangoIndex>,
+    #[serde(default)]
+    docs: Vec<Value>,
+}
+
+/// A Mango index of a [DatabaseSpec].
+#[derive(Deserialize, Clone, Debug)]
+pub struct MangoIndex {
+    name: String,
+    fields: Vec<String>,
+    #[serde(default)]
+    ddoc: Option<String>,
+}
+
+impl DatabaseSpec {
+    /// Create a spec for an empty database. `name` is used as the configured database name, from which
+    /// the unique name of the test database is derived.
+    pub fn new(name: &str) -> DatabaseSpec {
+        DatabaseSpec {
+            name: name.to_string(),
+            design_docs: vec![],
+            indexes: vec![],
+            docs: vec![],
+        }
+    }
+
+    /// Add a design document, which must carry an `_id` starting with `_design/`.
+    pub fn with_design_doc(mut self, design_doc: Value) -> DatabaseSpec {
+        self.design_docs.push(design_doc);
+        self
+    }
+
+    /// Add a Mango index named `name` over `fields`. The index is created in its own design document
+    /// unless `ddoc` names one.
+    pub fn with_index(mut self, name: &str, fields: &[&str], ddoc: Option<&str>) -> DatabaseSpec {
+        self.indexes.push(MangoIndex {
+            name: name.to_string(),
+            fields: fields.iter().map(|f| f.to_string()).collect(),
+            ddoc: ddoc.map(str::to_string),
+        });
+        self
+    }
+
+    /// Add documents to seed the database with.
+    pub fn with_docs(mut self, docs: Vec<Value>) -> DatabaseSpec {
+        self.docs.extend(docs);
+        self
+    }
+}
+
+/// A named set of test databases created together from a [HarnessSpec], simulating an application's
+/// full CouchDB footprint in one call.
+///
+/// Each database is a [TestRepo], destroyed when the harness is dropped.
+///
+/// ```rust
+/// # use serde_json::json;
+/// use couch_rs_test::{DatabaseSpec, HarnessSpec, TestHarness, TestRepoConfig};
+///
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let spec = HarnessSpec::new()
+///     .with_database(
+///         DatabaseSpec::new("users")
+///             .with_design_doc(json!({"_id": "_design/users", "views": {}}))
+///             .with_docs(vec![json!({"_id": "alice"})]),
+///     )
+///     .with_database(DatabaseSpec::new("orders"));
+///
+/// let harness = TestHarness::new(
+///     TestRepoConfig::new("http://localhost:5984", "admin", "password", "app"),
+///     spec,
+/// )
+/// .await?;
+/// let users = harness.repo("users").unwrap();
+/// # Ok(())
+/// # }
+/// ```
+pub struct TestHarness {
+    repos: BTreeMap<String, TestRepo>,
+}
+
+impl TestHarness {
+    /// Creates every database of `spec` and seeds it with its design documents, indexes and documents.
+    ///
+    /// `config` supplies the server, credentials and all other settings; the database name it configures
+    /// is replaced by the name of each database in the spec. If creating or seeding any database fails,
+    /// including CouchDB rejecting any one document, the databases created so far are destroyed and the
+    /// error is returned. Documents whose `_id` is taken fail too, unless
+    /// [TestRepoConfig::with_id_collision] is set to overwrite them or write them under a new id.
+    pub async fn new(
+        config: TestRepoConfig,
+        spec: HarnessSpec,
+    ) -> Result<TestHarness, Box<dyn Error>> {
+        for database in spec.databases.iter() {
+            if let Some(doc) = database.design_docs.iter().find(|doc| {
+                !doc["_id"]
+                    .as_str()
+                    .is_some_and(|id| id.starts_with("_design/"))
+            }) {
+                return Err(format!(
+                    "Design document of database {} without an _id starting with _design/: {}",
+                    database.name, doc
+                )
+                .into());
+            }
+        }
+
+        let mut repos = BTreeMap::new();
+        for database in spec.databases {
+            if repos.contains_key(&database.name) {
+                return Err(format!("Database {} appears twice in the spec", database.name).into());
+            }
+
+            let repo = TestRepo::new(config.clone().with_name(database.name.clone())).await?;
+            let mut docs = database.design_docs;
+            docs.extend(database.docs);
+            if !docs.is_empty() {
+                repo.with_data_strict(&mut docs).await?;
+            }
+            for index in database.indexes {
+                let fields =
+                    IndexFields::new(index.fields.into_iter().map(SortSpec::Simple).collect());
+                repo.db
+                    .insert_index(&index.name, fields, None, index.ddoc)
+                    .await?;
+            }
+            repos.insert(database.name, repo);
+        }
+
+        Ok(TestHarness { repos })
+    }
+
+    /// Builds a harness from a fixture manifest, a TOML file (when its extension is `.toml`) or JSON file
+    /// describing the databases to create. Paths in the manifest are relative to the manifest itself.
+    ///
+    /// ```toml
+    /// [[databases]]
+    /// name = "users"
+    /// # files holding one design document, or an array of them, or directories laid out as
+    /// # described for design_doc_from_dir
+    /// design_docs = ["design/users.json"]
+    /// # files in any format accepted by TestRepo::with_data_from_export
+    /// docs = ["data/users.json"]
+    ///
+    /// [[databases.indexes]]
+    /// name = "by-email"
+    /// fields = ["email"]
+    ///
+    /// [[databases]]
+    /// name = "orders"
+    /// ```
+    ///
+    /// `config` is used as in [TestHarness::new]; with the `jsonschema` feature, every docs file is
+    /// validated against the schemas registered in it before any database is created.
+    pub async fn from_manifest<P: AsRef<Path>>(
+        config: TestRepoConfig,
+        path: P,
+    ) -> Result<TestHarness, Box<dyn Error>> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        let manifest: Manifest = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&text)?,
+            _ => serde_json::from_str(&text)?,
+        };
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+
+        let mut spec = HarnessSpec::new();
+        for database in manifest.databases {
+            let mut db_spec = DatabaseSpec::new(&database.name);
+            for file in database.design_docs.iter() {
+                let path = base.join(file);
+                if path.is_dir() {
+                    db_spec.design_docs.push(design_doc_from_dir(&path)?);
+                    continue;
+                }
+                match read_json(&path)? {
+                    Value::Array(design_docs) => db_spec.design_docs.extend(design_docs),
+                    design_doc => db_spec.design_docs.push(design_doc),
+                }
+            }
+            for file in database.docs.iter() {
+                let path = base.join(file);
+                #[cfg(feature = "jsonschema")]
+                crate::schema::validate_fixture(&config, &path, &fs::read_to_string(&path)?)?;
+                db_spec.docs.extend(docs_from_export(read_export(&path)?)?);
+            }
+            db_spec.indexes = database.indexes;
+            spec = spec.with_database(db_spec);
+        }
+
+        TestHarness::new(config, spec).await
+    }
+
+    /// The test database created for the database named `name` in the spec.
+    pub fn repo(&self, name: &str) -> Option<&TestRepo> {
+        self.repos.get(name)
+    }
+
+    /// Iterates over the databases of the harness by the names used in the spec.
+    pub fn repos(&self) -> impl Iterator<Item = (&str, &TestRepo)> {
+        self.repos.iter().map(|(name, repo)| (name.as_str(), repo))
+    }
+}
+
+// the on-disk form of a HarnessSpec, referring to files instead of holding documents
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    databases: Vec<ManifestDatabase>,
+}
+
+#[derive(Deserialize)]
+struct ManifestDatabase {
+    name: String,
+    #[serde(default)]
+    design_docs: Vec<PathBuf>,
+    #[serde(default)]
+    indexes: Vec<MangoIndex>,
+    #[serde(default)]
+    docs: Vec<PathBuf>,
+}
+
+fn read_json(path: &Path) -> Result<Value, Box<dyn Error>> {
+    serde_json::from_str(&fs::read_to_string(path)?)
+        .map_err(|e| format!("Failed to parse {}: {}", path.display(), e).into())
+}
+
+// like read_json, but also accepting exports written as JSON lines
+fn read_export(path: &Path) -> Result<Value, Box<dyn Error>> {
+    parse_export(&fs::read_to_string(path)?)
+        .map_err(|e| format!("Failed to parse {}: {}", path.display(), e).into())
+}
@@ -0,0 +1,23 @@
+use std::{future::Future, pin::Pin, sync::Arc};
+
+use couch_rs::database::Database;
+
+/// A hook registered on [TestRepoConfig](crate::TestRepoConfig), called with the test database at a
+/// point of the [TestRepo](crate::TestRepo) lifecycle.
+pub(crate) type Hook =
+    Arc<dyn Fn(Database) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync>;
+
+pub(crate) fn hook<F, Fut>(hook: F) -> Hook
+where
+    F: Fn(Database) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    Arc::new(move |db| Box::pin(hook(db)))
+}
+
+/// Runs `hooks` one after another.
+pub(crate) async fn run(hooks: &[Hook], db: &Database) {
+    for hook in hooks.iter() {
+        hook(db.clone()).await;
+    }
+}
@@ -0,0 +1,93 @@
+use couch_rs::{
+    error::CouchError,
+    types::{find::SortSpec, index::IndexFields},
+};
+use serde_json::Value;
+
+use crate::{selector, TestRepo};
+
+/// A query configured with [TestRepoConfig::with_indexed_query](crate::TestRepoConfig::with_indexed_query)
+/// that no index serves, as reported by [TestRepo::suggest_indexes].
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexSuggestion {
+    /// Selector of the query, which falls back to a full scan of `_all_docs`.
+    pub selector: Value,
+    /// Fields, in dotted form, of a json index that would serve the query: those the selector
+    /// constrains outside of `$or`, `$nor` and `$not`. Empty if the selector constrains none, in which
+    /// case no index can serve it.
+    pub fields: Vec<String>,
+    /// Name of the index created for the query by [TestRepo::create_suggested_indexes], if any.
+    pub created: Option<String>,
+}
+
+impl TestRepo {
+    /// Runs each query configured with
+    /// [TestRepoConfig::with_indexed_query](crate::TestRepoConfig::with_indexed_query) through the
+    /// `_explain` endpoint of the database associated with this instance, and returns those that would
+    /// fall back to a full scan, each with the fields of an index that would serve it. Best called once
+    /// the database is seeded and its design documents deployed, so that a selector drifting away from
+    /// the indexes shipped with the application is caught before it shows up as a slow query.
+    ///
+    /// ```rust
+    /// # use serde_json::json;
+    /// use couch_rs_test::{TestRepo, TestRepoConfig};
+    ///
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let cfg = TestRepoConfig::new("http://localhost:5984", "admin", "password", "users")
+    ///     .with_indexed_query(json!({"email": "alice@example.com"}));
+    /// let repo = TestRepo::new(cfg).await?;
+    /// for suggestion in repo.suggest_indexes().await? {
+    ///     println!("missing index on {:?}", suggestion.fields);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn suggest_indexes(&self) -> Result<Vec<IndexSuggestion>, CouchError> {
+        let mut suggestions = vec![];
+        for selector in self.cfg.indexed_queries.iter() {
+            let explained = self.explain(selector).await?;
+            if explained["index"]["type"] != "special" {
+                continue;
+            }
+            let fields = selector::fields(selector);
+            log::warn!(
+                "Query {} on database {} falls back to a full scan; an index on {:?} would serve it",
+                selector,
+                self.cfg.db_name,
+                fields
+            );
+            suggestions.push(IndexSuggestion {
+                selector: selector.clone(),
+                fields,
+                created: None,
+            });
+        }
+        Ok(suggestions)
+    }
+
+    /// Like [TestRepo::suggest_indexes], but also creates the suggested indexes in the database
+    /// associated with this instance, named `suggested-` followed by their fields, so that the tests
+    /// that follow run against indexed queries. The returned suggestions name the indexes created;
+    /// queries constraining no field are returned without one.
+    pub async fn create_suggested_indexes(&self) -> Result<Vec<IndexSuggestion>, CouchError> {
+        let mut suggestions = self.suggest_indexes().await?;
+        for suggestion in suggestions.iter_mut() {
+            if suggestion.fields.is_empty() {
+                continue;
+            }
+            let name = format!("suggested-{}", suggestion.fields.join("-"));
+            let fields = IndexFields::new(
+                suggestion
+                    .fields
+                    .iter()
+                    .cloned()
+                    .map(SortSpec::Simple)
+                    .collect(),
+            );
+            self.db.insert_index(&name, fields, None, None).await?;
+            log::info!("Created index {} in database {}", name, self.cfg.db_name);
+            suggestion.created = Some(name);
+        }
+        Ok(suggestions)
+    }
+}
@@ -0,0 +1,155 @@
+use couch_rs::{
+    error::CouchError,
+    types::system::{DbInfo, SizeInfo},
+};
+
+use crate::{naming::encode_db_name, TestRepo};
+
+impl TestRepo {
+    /// Returns the information CouchDB reports for the database associated with this instance: the
+    /// counts of live and deleted documents, the sizes of the database file and its contents, the update
+    /// and purge sequences and the cluster settings.
+    pub async fn info(&self) -> Result<DbInfo, CouchError> {
+        self.client.get_info(&self.cfg.db_name).await
+    }
+
+    /// Asserts that the database associated with this instance holds `expected` live documents, design
+    /// documents included. In a shared database, only the documents of this instance's
+    /// [namespace](TestRepo::namespace) and the design documents are counted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the count differs or the documents cannot be counted.
+    pub async fn assert_doc_count(&self, expected: u64) {
+        let count = self
+            .doc_count()
+            .await
+            .unwrap_or_else(|e| panic!("Failed to count documents of {}: {}", self.db.name(), e));
+        assert_eq!(
+            count,
+            expected,
+            "Database {} holds {} documents, expected {}",
+            self.db.name(),
+            count,
+            expected
+        );
+    }
+
+    /// Asserts that the database associated with this instance holds `expected` deleted documents, so
+    /// that tests of cleanup or deletion logic can check the tombstones left behind rather than only the
+    /// live documents. Purged documents leave no tombstone.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the count differs or the database information cannot be read.
+    pub async fn assert_del_doc_count(&self, expected: u64) {
+        let info = self.info_or_panic().await;
+        assert_eq!(
+            info.doc_del_count,
+            expected,
+            "Database {} holds {} deleted documents, expected {}",
+            self.db.name(),
+            info.doc_del_count,
+            expected
+        );
+    }
+
+    /// Returns the number of live documents in the database associated with this instance other than
+    /// design documents, which the document count reported by CouchDB includes. In a shared database,
+    /// only the documents of this instance's [namespace](TestRepo::namespace) are counted.
+    pub async fn non_design_doc_count(&self) -> Result<u64, CouchError> {
+        if let Some(namespace) = self.namespace() {
+            return self.namespace_doc_count(namespace).await;
+        }
+        let info = self.info().await?;
+        let design_docs = self.design_doc_names().await?.len() as u64;
+        Ok(info.doc_count.saturating_sub(design_docs))
+    }
+
+    // counts the live documents, design documents included, of the database or, in a shared database,
+    // of the namespace of this instance
+    pub(crate) async fn doc_count(&self) -> Result<u64, CouchError> {
+        match self.namespace() {
+            Some(namespace) => {
+                let design_docs = self.design_doc_names().await?.len() as u64;
+                Ok(self.namespace_doc_count(namespace).await? + design_docs)
+            }
+            None => Ok(self.info().await?.doc_count),
+        }
+    }
+
+    /// Asserts that the database associated with this instance holds no live documents other than design
+    /// documents, for example after exercising deletion or cleanup code. Tombstones of deleted documents
+    /// are not counted; see [TestRepo::assert_del_doc_count] for those.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any document other than a design document is left, or the documents cannot be counted.
+    pub async fn assert_empty(&self) {
+        let count = self
+            .non_design_doc_count()
+            .await
+            .unwrap_or_else(|e| panic!("Failed to count documents of {}: {}", self.db.name(), e));
+        assert_eq!(
+            count,
+            0,
+            "Database {} holds {} documents other than design documents, expected none",
+            self.db.name(),
+            count
+        );
+    }
+
+    /// Asserts that the sizes reported for the database associated with this instance satisfy
+    /// `predicate`, for example that compaction shrank the file below a bound. Sizes are in bytes:
+    /// `active` is the size of live data, `external` the uncompressed size of the documents and `file`
+    /// the size of the database files.
+    ///
+    /// ```rust
+    /// # async fn example(repo: &couch_rs_test::TestRepo) {
+    /// repo.assert_sizes(|sizes| sizes.file < 10 * 1024 * 1024).await;
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `predicate` returns `false` or the database information cannot be read.
+    pub async fn assert_sizes<F: FnOnce(&SizeInfo) -> bool>(&self, predicate: F) {
+        let info = self.info_or_panic().await;
+        assert!(
+            predicate(&info.sizes),
+            "Sizes of database {} do not satisfy the predicate: {:?}",
+            self.db.name(),
+            info.sizes
+        );
+    }
+
+    /// Asserts whether the database associated with this instance is partitioned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the database is partitioned and `expected` is `false`, or the other way around, or if
+    /// the database information cannot be read.
+    pub async fn assert_partitioned(&self, expected: bool) {
+        // couch_rs does not expose the partitioned flag, so it is read from the raw response
+        let info = self
+            .get_json(&encode_db_name(&self.cfg.db_name))
+            .await
+            .unwrap_or_else(|e| panic!("Failed to read information of {}: {}", self.db.name(), e));
+        let partitioned = info["props"]["partitioned"].as_bool().unwrap_or(false);
+        assert_eq!(
+            partitioned,
+            expected,
+            "Database {} is {}partitioned, expected it {}to be",
+            self.db.name(),
+            if partitioned { "" } else { "not " },
+            if expected { "" } else { "not " }
+        );
+    }
+
+    // reads the database information, panicking on failure
+    async fn info_or_panic(&self) -> DbInfo {
+        self.info()
+            .await
+            .unwrap_or_else(|e| panic!("Failed to read information of {}: {}", self.db.name(), e))
+    }
+}
@@ -41,14 +41,189 @@
 //!     repo
 //! }
 //! ```
+//! 
+//! # Features
+//! 
+//! * `couch_rs_0_9` (default) and `couch_rs_0_10`: select the couch_rs version the helpers are built
+//!   against, which must be the one the code under test uses, as [TestRepo::db] and the errors
+//!   returned are couch_rs types. Projects on couch_rs 0.10 depend on this crate with
+//!   `default-features = false, features = ["couch_rs_0_10"]`. When both are enabled, couch_rs 0.9 is
+//!   used. The examples of this documentation are tested with `couch_rs_0_9`.
+//! * `tracing`: emit [tracing](https://docs.rs/tracing) spans and events for database creation, seeding,
+//!   query helpers and destruction.
+//! * `proxy`: route requests through a local [proxy] that can record and replay CouchDB interactions
+//!   and inject faults.
+//! * `mock`: run tests against an in-process CouchDB [mock] emulator instead of a server.
+//! * `wiremock`: run tests against a [stub] server whose responses can be stubbed per endpoint.
+//! * `blocking`: use [SyncTestRepo] in tests that are not async.
+//! * `derive`: generate constructors and a `seed` method for typed fixture documents with
+//!   `#[derive(TestFixture)]`.
+//! * `cli`: build the `couch-rs-test-clean` binary, which deletes test databases left on a server, for
+//!   example at the end of a CI pipeline.
+//! * `criterion`: register query benchmarks with [criterion](https://docs.rs/criterion) against a
+//!   database seeded once; see [bench::BenchRepo] and [bench::criterion_bench].
+//! * `jsonschema`: validate fixture documents against a JSON Schema per document type; see
+//!   [TestRepoConfig::with_schema].
+//!
+//! # Async runtimes
+//!
+//! couch_rs sends its requests through reqwest, which needs a tokio reactor. Only the lifecycle of a
+//! test database is independent of the executor running the test: [TestRepo::new] and the teardown
+//! of a dropped [TestRepo] run on a runtime owned by this crate when called outside of a tokio
+//! runtime, for example by async-std or smol, and `SyncTestRepo` (with the `blocking` feature) owns a
+//! runtime of its own. All other async methods of [TestRepo], like couch_rs itself, send requests or
+//! wait on tokio timers from the calling task, and must therefore be polled within a tokio context.
+//! This includes the polling helpers such as [TestRepo::wait_for_doc] and [TestRepo::eventually].
+//! Under async-std, enabling its `tokio1` feature provides one; under smol, the test body can be
+//! wrapped with [async-compat](https://docs.rs/async-compat):
+//!
+//! ```rust,ignore
+//! smol::block_on(async_compat::Compat::new(async {
+//!     let repo = TestRepo::new(cfg).await.unwrap();
+//!     repo.wait_for_doc::<Value>("alice", Duration::from_secs(5)).await.unwrap();
+//! }));
+//! ```
 
 #![warn(missing_docs)]
 
-use std::error::Error;
-use couch_rs::{database::Database, document::TypedCouchDocument, error::CouchError, Client};
-use rand::{distributions::Alphanumeric, Rng};
+#[cfg(not(any(feature = "couch_rs_0_9", feature = "couch_rs_0_10")))]
+compile_error!("one of the features couch_rs_0_9 (the default) and couch_rs_0_10 must be enabled");
+
+// with both features enabled, as by --all-features, couch_rs 0.9 is used
+#[cfg(all(feature = "couch_rs_0_10", not(feature = "couch_rs_0_9")))]
+extern crate couch_rs_0_10 as couch_rs;
+
+mod access;
+mod backend;
+pub mod bench;
+#[cfg(feature = "blocking")]
+mod blocking;
+mod bulk;
+mod cases;
+mod cleanup;
+mod cluster;
+mod collision;
+mod compact;
+mod compat;
+mod concurrency;
+mod config_file;
+mod copy;
+mod couchapp;
+mod diff;
+mod docs;
+mod dump;
+mod embed;
+mod failing;
+mod filter;
+mod fixtures;
+mod harness;
+mod hooks;
+mod indexes;
+mod info;
+mod lint;
+mod load;
+mod local;
+mod marker;
+mod migration;
+#[cfg(feature = "mock")]
+pub mod mock;
+mod presets;
+mod progress;
+pub mod naming;
+mod paging;
+mod password;
+#[cfg(feature = "proxy")]
+pub mod proxy;
+mod purge;
+mod query;
+mod registry;
+mod replication;
+mod retry;
+mod rt;
+#[cfg(feature = "jsonschema")]
+mod schema;
+mod secret;
+mod selector;
+mod seq;
+mod scope;
+mod shared;
+mod sibling;
+mod slots;
+mod snapshot;
+mod store;
+mod streaming;
+#[cfg(feature = "wiremock")]
+pub mod stub;
+mod summary;
+mod tasks;
+mod trace;
+mod validation;
+mod version;
+mod wait;
+
+use std::{
+    error::Error,
+    future::Future,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use couch_rs::{
+    database::Database, document::TypedCouchDocument, error::CouchError,
+    types::document::DocumentCreatedResult, Client,
+};
+use futures_util::{stream, StreamExt, TryStreamExt};
+pub use access::{Access, AccessOutcome};
+pub use backend::{MemoryBackend, TestBackend};
+#[cfg(feature = "blocking")]
+pub use blocking::SyncTestRepo;
+pub use cases::Case;
+pub use cleanup::{find_stale, purge_stale, TestDatabase};
+pub use collision::IdCollision;
+pub use concurrency::ConcurrentResults;
+pub use copy::Anonymizer;
+pub use couchapp::design_doc_from_dir;
+pub use diff::{diff_repos, DocDiff, RepoDiff};
+#[cfg(feature = "derive")]
+pub use couch_rs_test_derive::TestFixture;
+#[doc(hidden)]
+pub use embed::is_valid_json as __is_valid_json;
+pub use embed::EmbeddedFixture;
+pub use failing::{BackendOperation, FailingRepo};
+pub use harness::{DatabaseSpec, HarnessSpec, MangoIndex, TestHarness};
+pub use indexes::IndexSuggestion;
+pub use lint::{validate_fixtures, FixtureIssue, FixtureIssueKind};
+pub use load::{LoadReport, WriteLoad};
+pub use migration::MigrationHarness;
+pub use paging::{KeyPage, Page};
+pub use presets::Preset;
+pub use progress::SeedProgress;
+pub use registry::{leak_report, teardown_all, LeakReport, LeakedDatabase};
+pub use replication::{ReplicationHandle, ReplicationState};
+pub use retry::RetryPolicy;
+pub use scope::Scope;
+pub use secret::SecretString;
+pub use summary::{run_summary, RunSummary};
+pub use tasks::ActiveTask;
+pub use validation::Rejection;
+use naming::{NameStrategy, RandomSuffix};
+use trace::trace_event;
+use tokio::runtime::{Handle, RuntimeFlavor};
 use tokio_util::sync::CancellationToken;
 
+const DEFAULT_COLLISION_RETRIES: u32 = 3;
+const DEFAULT_BATCH_SIZE: usize = 1000;
+const DEFAULT_PARALLEL_BATCHES: usize = 1;
+const DEFAULT_MAX_CONCURRENCY: usize = 16;
+const DEFAULT_DROP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const DEFAULT_TEARDOWN_TIMEOUT: Duration = Duration::from_secs(60);
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+type Siblings = Arc<Mutex<Vec<String>>>;
+
 /// Configuration for [TestRepo]. 
 /// 
 /// This configuration is to create a new [couch_rs::Client](https://docs.rs/couch_rs/latest/couch_rs/struct.Client.html) 
@@ -57,8 +232,48 @@ use tokio_util::sync::CancellationToken;
 pub struct TestRepoConfig {
     uri: String,
     username: String,
-    password: String,
+    password: SecretString,
     db_name: String,
+    seed: Option<u64>,
+    name_strategy: Arc<dyn NameStrategy>,
+    test_name: Option<String>,
+    shard: Option<String>,
+    // whether the generated database name is made valid, as set by TestRepoConfig::sanitized
+    sanitize: bool,
+    collision_retries: u32,
+    dump_dir: Option<PathBuf>,
+    batch_size: usize,
+    parallel_batches: usize,
+    max_concurrency: usize,
+    global_db_limit: Option<usize>,
+    shared: bool,
+    // the id of the schema version document and the field holding the version
+    schema_version_doc: (String, String),
+    // the selectors of the queries checked by TestRepo::suggest_indexes
+    indexed_queries: Vec<serde_json::Value>,
+    // the prefix of document ids within a shared database, set once the database has been joined
+    namespace: Option<String>,
+    id_collision: IdCollision,
+    progress: Option<progress::ProgressFn>,
+    preset_template: Option<presets::TemplateFn>,
+    drop_poll_interval: Duration,
+    teardown_timeout: Option<Duration>,
+    cleanup_on_signal: bool,
+    request_timeout: Option<Duration>,
+    retry: RetryPolicy,
+    on_created: Vec<hooks::Hook>,
+    after_seed: Vec<hooks::Hook>,
+    before_destroy: Vec<hooks::Hook>,
+    #[cfg(feature = "mock")]
+    mock: bool,
+    #[cfg(feature = "jsonschema")]
+    schemas: std::collections::BTreeMap<String, serde_json::Value>,
+    #[cfg(feature = "jsonschema")]
+    schema_type_field: String,
+    #[cfg(feature = "proxy")]
+    proxy: Option<proxy::ProxyConfig>,
+    #[cfg(feature = "proxy")]
+    http_log: bool,
 }
 
 impl TestRepoConfig {
@@ -68,11 +283,526 @@ impl TestRepoConfig {
         TestRepoConfig {
             uri: uri.to_string(),
             username: uname.to_string(),
-            password: pwd.to_string(),
+            password: SecretString::from(pwd),
             db_name: dbname.to_string(),
+            seed: None,
+            name_strategy: Arc::new(RandomSuffix),
+            test_name: None,
+            shard: None,
+            sanitize: false,
+            collision_retries: DEFAULT_COLLISION_RETRIES,
+            dump_dir: None,
+            batch_size: DEFAULT_BATCH_SIZE,
+            parallel_batches: DEFAULT_PARALLEL_BATCHES,
+            max_concurrency: DEFAULT_MAX_CONCURRENCY,
+            global_db_limit: None,
+            shared: false,
+            schema_version_doc: (
+                version::DEFAULT_SCHEMA_VERSION_ID.to_string(),
+                version::DEFAULT_SCHEMA_VERSION_FIELD.to_string(),
+            ),
+            indexed_queries: vec![],
+            namespace: None,
+            id_collision: IdCollision::Skip,
+            progress: None,
+            preset_template: None,
+            drop_poll_interval: DEFAULT_DROP_POLL_INTERVAL,
+            teardown_timeout: Some(DEFAULT_TEARDOWN_TIMEOUT),
+            cleanup_on_signal: false,
+            request_timeout: Some(DEFAULT_REQUEST_TIMEOUT),
+            retry: RetryPolicy::default(),
+            on_created: vec![],
+            after_seed: vec![],
+            before_destroy: vec![],
+            #[cfg(feature = "mock")]
+            mock: false,
+            #[cfg(feature = "jsonschema")]
+            schemas: std::collections::BTreeMap::new(),
+            #[cfg(feature = "jsonschema")]
+            schema_type_field: schema::DEFAULT_TYPE_FIELD.to_string(),
+            #[cfg(feature = "proxy")]
+            proxy: None,
+            #[cfg(feature = "proxy")]
+            http_log: false,
+        }
+    }
+
+    /// Generate the random database name suffix from `seed` rather than from an unseeded random source.
+    /// Re-running a failing test with the same seed recreates the same database names, which helps when
+    /// correlating CouchDB server logs with test output, also when tests run in parallel: the names
+    /// drawn by a test depend only on the seed, the test name and the databases it created before. The
+    /// seed may also be provided through the `COUCH_TEST_SEED` environment variable; a seed set here
+    /// takes precedence.
+    pub fn with_seed(self, seed: u64) -> TestRepoConfig {
+        TestRepoConfig {
+            seed: Some(seed),
+            ..self
+        }
+    }
+
+    /// Create a new configuration like [TestRepoConfig::new], but first check that `dbname` is a valid
+    /// CouchDB database name (it must match `^[a-z][a-z0-9_$()+/-]*$` and be at most 238 characters
+    /// long). An invalid name results in an error describing the problem, rather than a failed request
+    /// to the server when the database is created.
+    pub fn try_new(
+        uri: &str,
+        uname: &str,
+        pwd: &str,
+        dbname: &str,
+    ) -> Result<TestRepoConfig, CouchError> {
+        naming::validate_db_name(dbname)?;
+        Ok(TestRepoConfig::new(uri, uname, pwd, dbname))
+    }
+
+    /// Rewrite the configured database name into a valid CouchDB database name: uppercase letters are
+    /// lowercased, other characters CouchDB does not allow are replaced by underscores, `db` is
+    /// prepended when the name does not start with a letter, and names longer than CouchDB allows are
+    /// cut short. The same is done to the name generated when the database is created, whose
+    /// prefix, shard, test name and suffix would otherwise make a long name invalid again: the
+    /// configured part is shortened until the generated name fits.
+    pub fn sanitized(self) -> TestRepoConfig {
+        let db_name = naming::sanitize_db_name(&self.db_name);
+        TestRepoConfig {
+            sanitize: true,
+            ..self.with_name(db_name)
+        }
+    }
+
+    /// Select the [NameStrategy] used to derive the unique database name from the configured database
+    /// name. Defaults to [RandomSuffix]; see the [naming] module for the other built-in strategies.
+    pub fn with_name_strategy<N: NameStrategy + 'static>(self, strategy: N) -> TestRepoConfig {
+        TestRepoConfig {
+            name_strategy: Arc::new(strategy),
+            ..self
+        }
+    }
+
+    /// Generate the unique part of database names as `length` random characters from `charset`, instead
+    /// of the 12 lowercase alphanumeric characters of the default [RandomSuffix]; for example a longer
+    /// suffix to keep collisions unlikely in huge parallel test matrices. This selects the
+    /// [naming::CharsetSuffix] strategy, replacing any strategy set with
+    /// [TestRepoConfig::with_name_strategy].
+    ///
+    /// ```rust
+    /// use couch_rs_test::{naming::SuffixCharset, TestRepoConfig};
+    ///
+    /// let cfg = TestRepoConfig::new("http://localhost:5984", "admin", "password", "users")
+    ///     .with_suffix(16, SuffixCharset::Hex);
+    /// ```
+    pub fn with_suffix(self, length: usize, charset: naming::SuffixCharset) -> TestRepoConfig {
+        self.with_name_strategy(naming::CharsetSuffix::new(length, charset))
+    }
+
+    /// Embed the name of the test using this configuration in the database name, so that a database found
+    /// lingering on the server can be traced directly to the test that created it. The name is inserted
+    /// between the configured database name and the unique part generated by the [NameStrategy], with
+    /// characters not allowed in CouchDB database names replaced by underscores. The [test_name!] macro
+    /// expands to the name of the enclosing test function.
+    pub fn named(self, test_name: &str) -> TestRepoConfig {
+        TestRepoConfig {
+            test_name: Some(test_name.to_string()),
+            ..self
+        }
+    }
+
+    /// Embed the shard identifier `shard` in the database name, after the configured database name and
+    /// before the test name, if any, so that the databases of a test run split across several machines
+    /// against one CouchDB server can be attributed to the machine that created them, and cleaned up
+    /// per shard with [purge_stale] and a configuration naming the same shard. Characters not allowed in
+    /// CouchDB database names are replaced by underscores.
+    ///
+    /// Without a shard set here, the `COUCH_TEST_SHARD` environment variable is used, and then
+    /// `NEXTEST_PARTITION`.
+    ///
+    /// ```rust
+    /// use couch_rs_test::TestRepoConfig;
+    ///
+    /// // databases are named like users-ci_3-4f0nq8l2x1zd
+    /// let cfg = TestRepoConfig::new("http://localhost:5984", "admin", "password", "users")
+    ///     .with_shard("ci/3");
+    /// ```
+    pub fn with_shard(self, shard: &str) -> TestRepoConfig {
+        TestRepoConfig {
+            shard: Some(shard.to_string()),
+            ..self
+        }
+    }
+
+    // the shard identifier of this configuration or the environment, as embedded in database names
+    pub(crate) fn resolved_shard(&self) -> Option<String> {
+        self.shard
+            .clone()
+            .or_else(naming::env_shard)
+            .map(|shard| naming::sanitize(&shard))
+    }
+
+    /// Set how many times database creation is retried with a newly generated name when the generated
+    /// name collides with an existing database. Defaults to 3 retries; when every attempt collides,
+    /// [TestRepo::new] returns an error.
+    pub fn with_collision_retries(self, retries: u32) -> TestRepoConfig {
+        TestRepoConfig {
+            collision_retries: retries,
+            ..self
+        }
+    }
+
+    /// When the test owning a [TestRepo] panics, write all documents of its database to a JSON file in
+    /// `dir` before the database is destroyed, so postmortem analysis does not require keeping the
+    /// database alive on the server. The file is named after the unique database name; see
+    /// [TestRepo::dump_to] for its format.
+    pub fn with_dump_on_failure<P: AsRef<Path>>(self, dir: P) -> TestRepoConfig {
+        TestRepoConfig {
+            dump_dir: Some(dir.as_ref().to_path_buf()),
+            ..self
+        }
+    }
+
+    /// Set the maximum number of documents sent in a single `_bulk_docs` request by [TestRepo::with_data]
+    /// and the other fixture loaders. Larger data sets are split into batches of this size, so that
+    /// fixtures with hundreds of thousands of documents do not fail or time out in one request. Defaults
+    /// to 1000; a size of 0 is treated as 1.
+    pub fn with_batch_size(self, batch_size: usize) -> TestRepoConfig {
+        TestRepoConfig {
+            batch_size: batch_size.max(1),
+            ..self
         }
     }
 
+    /// Set how many `_bulk_docs` batches [TestRepo::with_data] and the other fixture loaders send
+    /// concurrently. Raising this cuts the time needed to seed large performance fixtures; batches are
+    /// still reported in order. Defaults to 1, sending one batch at a time; 0 is treated as 1.
+    pub fn with_parallel_batches(self, parallel_batches: usize) -> TestRepoConfig {
+        TestRepoConfig {
+            parallel_batches: parallel_batches.max(1),
+            ..self
+        }
+    }
+
+    /// Set how many tasks [TestRepo::run_concurrently] runs at a time. Defaults to 16; 0 is treated as 1.
+    pub fn with_max_concurrency(self, max_concurrency: usize) -> TestRepoConfig {
+        TestRepoConfig {
+            max_concurrency: max_concurrency.max(1),
+            ..self
+        }
+    }
+
+    /// Cap the number of test databases that exist at the same time on the CouchDB server, across all
+    /// processes on this machine, at `limit`; creating a test database beyond it waits until another
+    /// test's database has been destroyed. This protects small CouchDB instances from overload when test
+    /// runners such as cargo-nextest run every test in a process of its own, each unaware of the
+    /// others. Databases created with [TestRepo::create_sibling] share the slot of their test
+    /// repository.
+    ///
+    /// The limit is enforced with lock files in the directory named by the `COUCH_TEST_LOCK_DIR`
+    /// environment variable, or in a `couch_rs_test` directory in the system's temporary directory, and
+    /// counted per server uri. Locks are released by the operating system when a process exits, so a
+    /// crashed test never holds on to its slot. Without a limit set here, the limit is read from the
+    /// `COUCH_TEST_MAX_DATABASES` environment variable; without either, databases are not limited. The
+    /// in-process emulator is never limited.
+    pub fn with_global_db_limit(self, limit: usize) -> TestRepoConfig {
+        TestRepoConfig {
+            global_db_limit: Some(limit.max(1)),
+            ..self
+        }
+    }
+
+    /// Share one database between all test repositories of this process created with the same server
+    /// and database name, rather than creating a database per test, trading full isolation for much
+    /// faster setup in large suites. The shared database is created by the first test using it and
+    /// destroyed when the process exits; each [TestRepo] instead gets a namespace of its own, named after
+    /// the test, such as `creates_user-x3k9q2:`, whose documents are deleted when it is dropped.
+    ///
+    /// [TestRepo::with_data] and the other seeding helpers prefix the `_id` of every seeded document
+    /// with the namespace, generating an id for documents without one, and the document, paging,
+    /// streaming, counting and snapshot helpers only see the documents of the namespace. Design documents
+    /// are not namespaced and are shared by all tests; seed them once, for example with
+    /// [TestRepoConfig::on_created], which runs when the shared database is created. Documents
+    /// written directly through [TestRepo::db](TestRepo#structfield.db) should use ids built with
+    /// [TestRepo::namespaced_id], and views and database-wide information such as [TestRepo::info]
+    /// still cover every namespace.
+    ///
+    /// ```rust
+    /// use couch_rs_test::TestRepoConfig;
+    ///
+    /// let cfg = TestRepoConfig::new("http://localhost:5984", "admin", "password", "users")
+    ///     .with_shared_database();
+    /// ```
+    ///
+    /// Shared databases cannot be used with the emulator or a proxy, and are not counted against
+    /// [TestRepoConfig::with_global_db_limit].
+    pub fn with_shared_database(self) -> TestRepoConfig {
+        TestRepoConfig {
+            shared: true,
+            ..self
+        }
+    }
+
+    /// Set the document and field in which the application under test records the schema version of
+    /// its database, as read by [TestRepo::schema_version] and checked by
+    /// [TestRepo::assert_schema_version]. Defaults to the `version` field of the
+    /// `_local/schema_version` document.
+    ///
+    /// ```rust
+    /// use couch_rs_test::TestRepoConfig;
+    ///
+    /// let cfg = TestRepoConfig::new("http://localhost:5984", "admin", "password", "users")
+    ///     .with_schema_version_doc("app-metadata", "schema");
+    /// ```
+    pub fn with_schema_version_doc(self, id: &str, field: &str) -> TestRepoConfig {
+        TestRepoConfig {
+            schema_version_doc: (id.to_string(), field.to_string()),
+            ..self
+        }
+    }
+
+    /// Add the selector of a Mango query made by the application under test, for
+    /// [TestRepo::suggest_indexes] to check that an index serves it. Can be called repeatedly.
+    ///
+    /// ```rust
+    /// use couch_rs_test::TestRepoConfig;
+    /// use serde_json::json;
+    ///
+    /// let cfg = TestRepoConfig::new("http://localhost:5984", "admin", "password", "orders")
+    ///     .with_indexed_query(json!({"customer": "alice", "total": {"$gt": 10}}))
+    ///     .with_indexed_query(json!({"status": "open"}));
+    /// ```
+    pub fn with_indexed_query(mut self, selector: serde_json::Value) -> TestRepoConfig {
+        self.indexed_queries.push(selector);
+        self
+    }
+
+    /// Set what [TestRepo::with_data] and the other fixture loaders do with a document whose `_id` is
+    /// already taken, typically by a document of another fixture file. Defaults to [IdCollision::Skip],
+    /// which keeps the existing document and logs a warning naming the skipped ids.
+    pub fn with_id_collision(self, policy: IdCollision) -> TestRepoConfig {
+        TestRepoConfig {
+            id_collision: policy,
+            ..self
+        }
+    }
+
+    /// Call `progress` after each `_bulk_docs` batch written by [TestRepo::with_data] and the other fixture
+    /// loaders, so that long-running seeds can report how far they got instead of appearing hung in CI
+    /// output.
+    ///
+    /// ```rust
+    /// use couch_rs_test::TestRepoConfig;
+    ///
+    /// let cfg = TestRepoConfig::new("http://localhost:5984", "admin", "password", "perf")
+    ///     .with_progress(|progress| log::info!("Seeded {}", progress));
+    /// ```
+    pub fn with_progress<F>(self, progress: F) -> TestRepoConfig
+    where
+        F: Fn(&SeedProgress) + Send + Sync + 'static,
+    {
+        TestRepoConfig {
+            progress: Some(Arc::new(progress)),
+            ..self
+        }
+    }
+
+    /// Generate the documents seeded by [TestRepo::seed_preset] with `template`, which is called with the
+    /// number of each document, from 0 up to the size of the preset.
+    /// 
+    /// ```rust
+    /// use couch_rs_test::TestRepoConfig;
+    /// use serde_json::json;
+    ///
+    /// let cfg = TestRepoConfig::new("http://localhost:5984", "admin", "password", "perf")
+    ///     .with_preset_template(|n| json!({"type": "order", "total": n % 100}));
+    /// ```
+    pub fn with_preset_template<F>(self, template: F) -> TestRepoConfig
+    where
+        F: Fn(usize) -> serde_json::Value + Send + Sync + 'static,
+    {
+        TestRepoConfig {
+            preset_template: Some(Arc::new(template)),
+            ..self
+        }
+    }
+
+    /// Set how often the drop watcher checks whether the [TestRepo] has been dropped, and how often
+    /// dropping it checks whether the database has been destroyed. Defaults to 100 milliseconds.
+    pub fn with_drop_poll_interval(self, interval: Duration) -> TestRepoConfig {
+        TestRepoConfig {
+            drop_poll_interval: interval,
+            ..self
+        }
+    }
+
+    /// Set how long dropping a [TestRepo] waits for its database to be destroyed. When the wait runs out,
+    /// the name of the leaked database is logged and the drop returns instead of hanging the test runner.
+    /// Defaults to 60 seconds; `None` waits indefinitely.
+    pub fn with_teardown_timeout(self, timeout: Option<Duration>) -> TestRepoConfig {
+        TestRepoConfig {
+            teardown_timeout: timeout,
+            ..self
+        }
+    }
+
+    /// Install a handler that destroys every test database created by this process when it receives SIGINT
+    /// or SIGTERM (Ctrl-C on Windows), and then exits, so that interrupted local runs do not leave orphaned
+    /// databases behind. The handler is installed process-wide when the first [TestRepo] with this option
+    /// is created. See also [teardown_all].
+    pub fn with_cleanup_on_signal(self) -> TestRepoConfig {
+        TestRepoConfig {
+            cleanup_on_signal: true,
+            ..self
+        }
+    }
+
+    /// Register an async hook called with the test database once it has been created, before
+    /// [TestRepo::new] returns; for example to warm caches or register the database with an external
+    /// service. Hooks run in the order they were registered.
+    ///
+    /// ```rust
+    /// use couch_rs_test::TestRepoConfig;
+    ///
+    /// let cfg = TestRepoConfig::new("http://localhost:5984", "admin", "password", "users")
+    ///     .on_created(|db| async move { log::info!("Created {}", db.name()) });
+    /// ```
+    pub fn on_created<F, Fut>(mut self, hook: F) -> TestRepoConfig
+    where
+        F: Fn(Database) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.on_created.push(hooks::hook(hook));
+        self
+    }
+
+    /// Register an async hook called with the test database after each seeding operation, such as
+    /// [TestRepo::with_data], has written its documents.
+    pub fn after_seed<F, Fut>(mut self, hook: F) -> TestRepoConfig
+    where
+        F: Fn(Database) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.after_seed.push(hooks::hook(hook));
+        self
+    }
+
+    /// Register an async hook called with the test database just before it is destroyed, after the
+    /// [TestRepo] has been dropped.
+    pub fn before_destroy<F, Fut>(mut self, hook: F) -> TestRepoConfig
+    where
+        F: Fn(Database) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.before_destroy.push(hooks::hook(hook));
+        self
+    }
+
+    /// Run against an in-process CouchDB emulator instead of the server at the configured uri, for fast
+    /// unit-level tests and offline development. See the [mock] module for what is emulated.
+    /// 
+    /// Only available with the `mock` feature.
+    #[cfg(feature = "mock")]
+    pub fn with_mock(self) -> TestRepoConfig {
+        TestRepoConfig { mock: true, ..self }
+    }
+
+    /// Validate fixture documents whose `type` field is `doc_type` against the JSON Schema `schema` when
+    /// they are loaded from a file, by [TestRepo::with_data_from_export] or [TestHarness::from_manifest].
+    /// A file with an offending document fails to load before anything is pushed, with an error naming
+    /// the file, the line of the document and the violated constraints. Documents of other types are not
+    /// validated.
+    /// 
+    /// Only available with the `jsonschema` feature.
+    #[cfg(feature = "jsonschema")]
+    pub fn with_schema(mut self, doc_type: &str, schema: serde_json::Value) -> TestRepoConfig {
+        self.schemas.insert(doc_type.to_string(), schema);
+        self
+    }
+
+    /// Read the type of a document for [TestRepoConfig::with_schema] from `field` rather than `type`.
+    /// 
+    /// Only available with the `jsonschema` feature.
+    #[cfg(feature = "jsonschema")]
+    pub fn with_schema_type_field(self, field: &str) -> TestRepoConfig {
+        TestRepoConfig {
+            schema_type_field: field.to_string(),
+            ..self
+        }
+    }
+
+    /// Route all requests to CouchDB through a local proxy configured by `proxy`, for example to record
+    /// or replay them with a [proxy::Cassette]. See the [proxy] module for details.
+    /// 
+    /// Only available with the `proxy` feature.
+    #[cfg(feature = "proxy")]
+    pub fn with_proxy(self, proxy: proxy::ProxyConfig) -> TestRepoConfig {
+        TestRepoConfig {
+            proxy: Some(proxy),
+            ..self
+        }
+    }
+
+    /// Log the request line and body of every request to CouchDB and the status and body of its
+    /// response at debug level, to diagnose for example why a fixture did not appear. Bodies are
+    /// truncated, and credentials in paths and JSON bodies are redacted. Requests are logged from a
+    /// local [proxy], which is started for this if none is configured; unlike faults, logging covers the
+    /// requests setting up the test database, and those destroying it when the instance is dropped
+    /// within a multi-threaded runtime. Within a current-thread runtime, such as that of
+    /// `#[tokio::test]`, the proxy cannot serve requests while the instance is dropped, so the database
+    /// is destroyed with requests sent straight to CouchDB, which are not logged.
+    ///
+    /// Only available with the `proxy` feature.
+    #[cfg(feature = "proxy")]
+    pub fn with_http_log(self) -> TestRepoConfig {
+        TestRepoConfig {
+            http_log: true,
+            ..self
+        }
+    }
+
+    /// Set the timeout of every request the CouchDB client makes, from connecting until the response body
+    /// has been received, or `None` to wait indefinitely. Defaults to 10 seconds, the default of couch_rs;
+    /// the timeout is applied in whole seconds, rounded up. Raise it for suites whose parallel tests keep
+    /// the server busy enough for requests to queue.
+    /// 
+    /// couch_rs builds its HTTP client itself, so other client settings cannot be passed through. Proxies
+    /// are taken from the `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY` environment variables. To avoid
+    /// exhausting connections in large parallel suites, bound the requests in flight instead, with
+    /// [TestRepoConfig::with_parallel_batches] and [TestRepoConfig::with_max_concurrency].
+    pub fn with_request_timeout(self, timeout: Option<Duration>) -> TestRepoConfig {
+        TestRepoConfig {
+            request_timeout: timeout,
+            ..self
+        }
+    }
+
+    /// Set how creating, seeding and destroying test databases is retried after transient server errors,
+    /// such as the `503` responses of a shared CI server under load. Defaults to
+    /// [RetryPolicy::default]; use [RetryPolicy::none] to fail on the first error.
+    pub fn with_retry(self, policy: RetryPolicy) -> TestRepoConfig {
+        TestRepoConfig {
+            retry: policy,
+            ..self
+        }
+    }
+
+    /// Creates a client for the configured server with the configured credentials and settings.
+    pub(crate) fn client(&self) -> Result<Client, CouchError> {
+        self.client_for(&self.uri)
+    }
+
+    /// Creates a client for the server at `uri` with the configured credentials and settings.
+    pub(crate) fn client_for(&self, uri: &str) -> Result<Client, CouchError> {
+        self.client_as(uri, &self.username, self.password.expose())
+    }
+
+    /// Creates a client for the server at `uri` with the configured settings and the given credentials.
+    pub(crate) fn client_as(&self, uri: &str, username: &str, password: &str) -> Result<Client, CouchError> {
+        let timeout = self
+            .request_timeout
+            .map(|t| (t.as_secs() + u64::from(t.subsec_nanos() > 0)).max(1));
+        Client::new_with_timeout(uri, Some(username), Some(password), timeout)
+    }
+
+    fn with_uri(self, uri: String) -> TestRepoConfig {
+        TestRepoConfig { uri, ..self }
+    }
+
     fn with_name(self, db_unique_name: String) -> TestRepoConfig {
         TestRepoConfig {
             db_name: db_unique_name,
@@ -81,6 +811,30 @@ impl TestRepoConfig {
     }
 }
 
+// prints the connection and seeding settings, with the password and any credentials in the uri redacted
+impl std::fmt::Debug for TestRepoConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TestRepoConfig")
+            .field("uri", &secret::redact(&self.uri))
+            .field("username", &self.username)
+            .field("password", &self.password)
+            .field("db_name", &self.db_name)
+            .field("seed", &self.seed)
+            .field("test_name", &self.test_name)
+            .field("shard", &self.shard)
+            .field("batch_size", &self.batch_size)
+            .field("parallel_batches", &self.parallel_batches)
+            .field("max_concurrency", &self.max_concurrency)
+            .field("global_db_limit", &self.global_db_limit)
+            .field("shared", &self.shared)
+            .field("id_collision", &self.id_collision)
+            .field("request_timeout", &self.request_timeout)
+            .field("teardown_timeout", &self.teardown_timeout)
+            .field("retry", &self.retry)
+            .finish_non_exhaustive()
+    }
+}
+
 /// A wrapper for a struct that encapsulates functionality of an application's data layer. 
 /// 
 /// Creation of a new instance of this struct will create a unique [couch_rs::database::Database](https://docs.rs/couch_rs/latest/couch_rs/database/struct.Database.html). 
@@ -92,8 +846,28 @@ pub struct TestRepo {
     /// TestRepo's ephemeral CouchDB database. 
     pub db: Database,
 
+    client: Client,
+    cfg: TestRepoConfig,
+
     drop_token: CancellationToken,
     dropped_token: CancellationToken,
+    dump_on_drop: Arc<AtomicBool>,
+    torn_down: Arc<AtomicBool>,
+    // names of the databases created with create_sibling, destroyed along with the database
+    siblings: Siblings,
+    // the slot taken under a global limit on test databases, released once the database is destroyed
+    _slot: Option<slots::Slot>,
+
+    // declared after the tokens, so the proxy outlives the destruction of the database
+    #[cfg(feature = "proxy")]
+    proxy: Option<proxy::Proxy>,
+
+    // declared last, so the emulator outlives the proxy and the destruction of the database
+    #[cfg(feature = "mock")]
+    mock: Option<mock::MockServer>,
+    // keeps the stub server up until the database is destroyed, even if the test drops its handle
+    #[cfg(feature = "wiremock")]
+    stubs: Option<stub::Stubs>,
 }
 
 impl TestRepo {
@@ -101,104 +875,519 @@ impl TestRepo {
     /// This function will create a new [couch_rs::Client](https://docs.rs/couch_rs/latest/couch_rs/struct.Client.html)
     /// from the parameters passed as part of the [TestRepoConfig] argument and  then create a new database 
     /// in CouchDB using the client connection and a database name consisting of the name defined in config 
-    /// plus a random suffix.This randomization of database names helps prevent collisions during parallel 
-    /// test excutions against the same CouchDB instance. 
+    /// plus a unique part chosen by the configured [NameStrategy] (a random suffix by default). This 
+    /// randomization of database names helps prevent collisions during parallel 
+    /// test excutions against the same CouchDB instance. Should a generated name collide with an existing
+    /// database anyway, a new name is generated up to the number of retries set with
+    /// [TestRepoConfig::with_collision_retries] before an error is returned.
     /// 
     /// This function also creates a drop token and watcher to determine when this instance is de-allocated
     /// The watcher spawn an asynchronous thread that will observe the drop token every 100 milliseconds.
     /// when this instance is deallocated, the drop token is destroyed and the watcher will trigger the
     /// destruction of the database instance created by this method. 
+    /// 
+    /// Outside of a tokio runtime, for example in a test run by async-std or smol, the database is created
+    /// and later destroyed on a runtime owned by this crate. The other helpers of TestRepo, like couch_rs
+    /// itself, need a tokio context; see [Async runtimes](crate#async-runtimes).
     pub async fn new(arg_cfg: TestRepoConfig) -> Result<TestRepo, Box<dyn Error>> {
-        // create random identifier for database and append to db name
-        let test_identifier = rand::thread_rng()
-            .sample_iter(&Alphanumeric)
-            .take(12)
-            .map(char::from)
-            .collect::<String>()
-            .to_lowercase();
+        if rt::in_tokio() {
+            return TestRepo::create(arg_cfg).await;
+        }
+        // errors are carried across threads as text, as they need not be Send
+        rt::run(async move { TestRepo::create(arg_cfg).await.map_err(|e| e.to_string()) })
+            .await
+            .map_err(Into::into)
+    }
 
-        let db_unique_name = format!("{}-{}", arg_cfg.db_name, test_identifier);
-        let cfg = arg_cfg.with_name(db_unique_name);
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "couch_rs_test::create", skip_all, fields(db_name = %arg_cfg.db_name))
+    )]
+    async fn create(arg_cfg: TestRepoConfig) -> Result<TestRepo, Box<dyn Error>> {
+        if arg_cfg.shared {
+            return TestRepo::join_shared(arg_cfg).await;
+        }
 
-        let client = Client::new(&cfg.uri, &cfg.username, &cfg.password)?;
+        let start = Instant::now();
+        // create unique database name from the configured db name
+        let seed = naming::resolve_seed(arg_cfg.seed);
+        if let Some(seed) = seed {
+            log::info!("Generating database name for {} from seed {}", arg_cfg.db_name, seed);
+        }
+        let shard = arg_cfg.resolved_shard();
+        let db_name = match &shard {
+            Some(shard) => format!("{}-{}", arg_cfg.db_name, shard),
+            None => arg_cfg.db_name.clone(),
+        };
+        let db_name = match &arg_cfg.test_name {
+            Some(test_name) => format!("{}-{}", db_name, naming::sanitize(test_name)),
+            None => db_name,
+        };
+        let arg_cfg = TestRepoConfig { shard, ..arg_cfg };
+        let mut rng = naming::name_rng(&db_name, seed);
 
+        // wait for a free slot when the number of test databases on the server is capped
+        #[cfg(feature = "mock")]
+        let mocked = arg_cfg.mock;
+        #[cfg(not(feature = "mock"))]
+        let mocked = false;
+        let slot = match arg_cfg.global_db_limit.or_else(slots::env_limit) {
+            Some(limit) if !mocked => Some(slots::acquire(&arg_cfg.uri, limit).await?),
+            _ => None,
+        };
 
-        let drop_token = CancellationToken::new();
-        let dropped_token = TestRepo::start_drop_watcher(&drop_token, cfg.clone()).await;
-
-        // connect to database and return wrapping repository
-        log::info!("Creating database {} for testing", cfg.db_name);
-
-        // create test database - panic on fail
-        match client.make_db(&cfg.db_name).await {
-            Ok(_) => {}
-            Err(e) => {
-                match e.status() {
-                    Some(code) => {
-                        match code {
-                            // database already exists; this should not happen,
-                            // requires manual cleanup
-                            http::status::StatusCode::PRECONDITION_FAILED => {
-                                panic!(
-                                    "Database {} already exists and must be manually removed.",
-                                    cfg.db_name
-                                )
-                            }
-                            _ => panic!("Error while creating new database: {}", e),
-                        }
-                    }
-                    None => panic!("Error while creating new database: {}", e),
+        // start the emulator, if configured, and use it in place of the server from here on
+        #[cfg(feature = "mock")]
+        let (mock, arg_cfg) = match arg_cfg.mock {
+            true => {
+                let mock = mock::MockServer::start().await?;
+                let uri = mock.uri().to_string();
+                (Some(mock), arg_cfg.with_uri(uri))
+            }
+            false => (None, arg_cfg),
+        };
+
+        // start the proxy, if any, and connect through it from here on
+        #[cfg(feature = "proxy")]
+        let proxy_cfg = match arg_cfg.http_log {
+            true => Some(arg_cfg.proxy.clone().unwrap_or_default().with_http_log()),
+            false => arg_cfg.proxy.clone(),
+        };
+        #[cfg(feature = "proxy")]
+        let (proxy, arg_cfg) = match proxy_cfg {
+            Some(proxy_cfg) => {
+                let proxy = proxy::Proxy::start(proxy_cfg, &arg_cfg.uri).await?;
+                let uri = proxy.uri().to_string();
+                (Some(proxy), arg_cfg.with_uri(uri))
+            }
+            None => (None, arg_cfg),
+        };
+
+        if arg_cfg.cleanup_on_signal {
+            registry::install_signal_handler();
+        }
+
+        let client = arg_cfg.client()?;
+        let prefixed_name = format!("{}{}", naming::env_prefix(), db_name);
+        let prefixed_name = match arg_cfg.sanitize {
+            true => naming::sanitize_db_name(&prefixed_name),
+            false => prefixed_name,
+        };
+
+        // create test database, regenerating the name on collision - panic on other failures
+        let mut attempt: u32 = 0;
+        let cfg = loop {
+            attempt += 1;
+            let db_unique_name = match arg_cfg.sanitize {
+                true => {
+                    naming::fitted_unique_name(&*arg_cfg.name_strategy, &prefixed_name, &mut rng)
                 }
+                false => arg_cfg.name_strategy.unique_name(&prefixed_name, &mut rng),
+            };
+            naming::validate_db_name(&db_unique_name)?;
+
+            log::info!("Creating database {} for testing", db_unique_name);
+
+            #[cfg(feature = "proxy")]
+            if let Some(proxy) = &proxy {
+                proxy.set_db_name(&db_unique_name);
             }
+
+            let created = arg_cfg
+                .retry
+                .run("Creating a test database", || client.make_db(&db_unique_name))
+                .await;
+            match created {
+                Ok(_) => {
+                    trace_event!(db = %db_unique_name, attempt, "created test database");
+                    break arg_cfg.with_name(db_unique_name);
+                }
+                Err(e) => match e.status() {
+                    // database already exists; retry with a new name until attempts run out
+                    Some(compat::StatusCode::PRECONDITION_FAILED) => {
+                        if attempt > arg_cfg.collision_retries {
+                            return Err(Box::new(CouchError::new(
+                                format!(
+                                    "Database name collided with an existing database on all {} attempts; last tried {}",
+                                    attempt, db_unique_name
+                                ),
+                                compat::StatusCode::PRECONDITION_FAILED,
+                            )));
+                        }
+                        log::warn!("Database {} already exists; retrying with a new name", db_unique_name);
+                    }
+                    _ => panic!("Error while creating new database: {}", e),
+                },
+            };
         };
 
+        // remember the database until it is destroyed, for teardown_all; a replayed database only
+        // exists in the cassette
+        #[cfg(feature = "proxy")]
+        let server_uri = match &proxy {
+            Some(proxy) => proxy.upstream().map(str::to_string),
+            None => Some(cfg.uri.clone()),
+        };
+        #[cfg(not(feature = "proxy"))]
+        let server_uri = Some(cfg.uri.clone());
+        // an emulated database disappears with the emulator
+        #[cfg(feature = "mock")]
+        let server_uri = server_uri.filter(|_| mock.is_none());
+        if let Some(uri) = server_uri {
+            registry::register(&cfg, uri);
+            summary::record_created(start.elapsed());
+        }
+
+        let drop_token = CancellationToken::new();
+        let dump_on_drop = Arc::new(AtomicBool::new(false));
+        let torn_down = Arc::new(AtomicBool::new(false));
+        let siblings = Siblings::default();
+        let dropped_token = TestRepo::start_drop_watcher(
+            &drop_token,
+            cfg.clone(),
+            dump_on_drop.clone(),
+            torn_down.clone(),
+            siblings.clone(),
+        )
+        .await;
+
+        // record the creation of the database so that stale databases can be collected later
+        let marker = marker::Marker::new(&db_name, cfg.shard.as_deref());
+        if let Err(e) = marker::write_marker(&client, &cfg.db_name, &marker).await {
+            log::warn!("Failed to write marker document to {}: {}", cfg.db_name, e);
+        }
+
+        let db = client.db(&cfg.db_name).await?;
+        hooks::run(&cfg.on_created, &db).await;
+
+        // setup is complete; the test itself may now see injected faults
+        #[cfg(feature = "proxy")]
+        if let Some(proxy) = &proxy {
+            proxy.set_armed(true);
+        }
+
         Ok(TestRepo {
-            db: client.db(&cfg.db_name).await?,
-            drop_token: drop_token,
-            dropped_token: dropped_token,
+            db,
+            client,
+            cfg,
+            drop_token,
+            dropped_token,
+            dump_on_drop,
+            torn_down,
+            siblings,
+            _slot: slot,
+            #[cfg(feature = "proxy")]
+            proxy,
+            #[cfg(feature = "mock")]
+            mock,
+            #[cfg(feature = "wiremock")]
+            stubs: None,
         })
     }
 
+    /// Creates a new instance of TestRepo like [TestRepo::new], but against a [stub] server rather than
+    /// the CouchDB server at the configured uri, and returns it with the [stub::Stubs] handle used to
+    /// stub responses of its database. This makes tests of error handling, such as what repository code
+    /// does when `_bulk_docs` returns status 500, a matter of a couple of lines.
+    /// 
+    /// Only available with the `wiremock` feature.
+    #[cfg(feature = "wiremock")]
+    pub async fn stubbed(cfg: TestRepoConfig) -> Result<(TestRepo, stub::Stubs), Box<dyn Error>> {
+        let stubs = stub::Stubs::start().await;
+        let mut repo = TestRepo::new(cfg.with_uri(stubs.uri())).await?;
+
+        // a stubbed database only exists in the stub server
+        registry::unregister(&repo.cfg.db_name);
+
+        let stubs = stubs.for_db(&repo.cfg.db_name);
+        repo.stubs = Some(stubs.clone());
+        Ok((repo, stubs))
+    }
+
+    /// Returns the [couch_rs::Client](https://docs.rs/couch_rs/latest/couch_rs/struct.Client.html) this
+    /// instance created its database with, so that tests can reach server-level endpoints such as
+    /// `_all_dbs`, `_up` or `_node/_local/_stats` through the same authenticated connection. With a
+    /// proxy or the emulator configured, the client talks to them rather than to CouchDB.
+    ///
+    /// ```rust
+    /// # async fn example(repo: &couch_rs_test::TestRepo) -> Result<(), couch_rs::error::CouchError> {
+    /// let dbs = repo.client().list_dbs().await?;
+    /// assert!(dbs.contains(&repo.db.name().to_string()));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
     /// Pushes data to the unique database associated with this instance. Data is pushed via the 
     /// [couch_rs::database::bulk_docs](https://docs.rs/couch_rs/latest/couch_rs/database/struct.Database.html#method.bulk_docs)
-    /// method. 
+    /// method, in batches of at most [TestRepoConfig::with_batch_size] documents, of which up to
+    /// [TestRepoConfig::with_parallel_batches] are sent concurrently; the returned count covers all batches.
+    /// Documents whose `_id` is already taken are handled as set with [TestRepoConfig::with_id_collision].
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "couch_rs_test::seed", skip_all, fields(db = %self.db.name(), docs = data.len()))
+    )]
     pub async fn with_data<S: TypedCouchDocument>(
         &self,
         data: &mut [S],
     ) -> Result<usize, CouchError> {
-        let result = self.db.bulk_docs(data).await?;
-        return Ok(result.len());
+        Ok(self.seed(data).await?.len())
+    }
+
+    // seeds like with_data, but fails if CouchDB rejects any document, other than a colliding one that
+    // the configured IdCollision policy overwrote or wrote under a new id
+    pub(crate) async fn with_data_strict<S: TypedCouchDocument>(
+        &self,
+        data: &mut [S],
+    ) -> Result<usize, CouchError> {
+        let results = self.seed(data).await?;
+        let resolved = matches!(
+            self.cfg.id_collision,
+            IdCollision::Overwrite | IdCollision::Suffix
+        );
+        let rejected: Vec<&CouchError> = results
+            .iter()
+            .filter_map(|result| match result {
+                Err(e) if !(resolved && e.status() == Some(compat::StatusCode::CONFLICT)) => Some(e),
+                _ => None,
+            })
+            .collect();
+        if let Some(first) = rejected.first() {
+            let status = first
+                .status()
+                .unwrap_or(compat::StatusCode::INTERNAL_SERVER_ERROR);
+            let reasons: Vec<String> = rejected.iter().map(|e| e.to_string()).collect();
+            return Err(CouchError::new(
+                format!(
+                    "{} documents were rejected by {}: {}",
+                    rejected.len(),
+                    self.cfg.db_name,
+                    reasons.join("; ")
+                ),
+                status,
+            ));
+        }
+        Ok(results.len())
+    }
+
+    // pushes `data` as documented for with_data, returning the result of writing each document
+    async fn seed<S: TypedCouchDocument>(
+        &self,
+        data: &mut [S],
+    ) -> Result<Vec<DocumentCreatedResult>, CouchError> {
+        let start = Instant::now();
+        let mut progress = SeedProgress {
+            batches_done: 0,
+            batches_total: Some(data.len().div_ceil(self.cfg.batch_size)),
+            docs_done: 0,
+            docs_total: Some(data.len()),
+        };
+        // collected up front, so that the future of this function does not hold the closure and stays Send
+        let batches: Vec<_> = data
+            .chunks_mut(self.cfg.batch_size)
+            .map(|batch| self.bulk_docs(batch))
+            .collect();
+        let result: Vec<_> = stream::iter(batches)
+            .buffered(self.cfg.parallel_batches)
+            .inspect_ok(|written| {
+                progress.batches_done += 1;
+                progress.docs_done += written.len();
+                if let Some(report) = &self.cfg.progress {
+                    report(&progress);
+                }
+            })
+            .try_collect::<Vec<_>>()
+            .await?
+            .into_iter()
+            .flatten()
+            .collect();
+        self.resolve_collisions(data, &result).await?;
+        let written = result.iter().filter(|r| r.is_ok()).count();
+        self.record_seeded(written, start.elapsed());
+        trace_event!(
+            written,
+            failed = result.len() - written,
+            "seeded test database"
+        );
+        hooks::run(&self.cfg.after_seed, &self.db).await;
+        Ok(result)
+    }
+
+    // counts seeded documents towards the run summary, which leaves out the databases of the emulator
+    pub(crate) fn record_seeded(&self, docs: usize, elapsed: Duration) {
+        #[cfg(feature = "mock")]
+        if self.mock.is_some() {
+            return;
+        }
+        summary::record_seeded(docs, elapsed);
     }
 
     async fn start_drop_watcher(
         drop_token: &CancellationToken,
         cfg: TestRepoConfig,
+        dump_on_drop: Arc<AtomicBool>,
+        torn_down: Arc<AtomicBool>,
+        siblings: Siblings,
     ) -> CancellationToken {
         let drop_child = drop_token.child_token();
 
         let dropped_token = CancellationToken::new();
         let dropped_child = dropped_token.child_token();
 
-        tokio::spawn(async move {
+        rt::spawn(async move {
+            // also cancels when the task is dropped along with a runtime shutting down, so that Drop
+            // does not wait for a watcher that no longer exists
+            let _dropped = dropped_token.drop_guard();
+
             while !drop_child.is_cancelled() {
-                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                tokio::time::sleep(cfg.drop_poll_interval).await;
+            }
+            // Drop tore the database down on a thread of its own
+            if torn_down.load(Ordering::SeqCst) {
+                return;
             }
 
-            TestRepo::drop(cfg).await;
-
-            dropped_token.cancel();
+            TestRepo::teardown(cfg, dump_on_drop.load(Ordering::SeqCst), siblings).await;
+            torn_down.store(true, Ordering::SeqCst);
         });
 
         dropped_child
     }
 
+    async fn teardown(cfg: TestRepoConfig, dump: bool, siblings: Siblings) {
+        let siblings = siblings.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        for db_name in siblings {
+            let sibling_cfg = cfg.clone().with_name(db_name);
+            if dump {
+                TestRepo::dump_before_drop(&sibling_cfg).await;
+            }
+            TestRepo::drop(sibling_cfg).await;
+        }
+
+        if dump {
+            TestRepo::dump_before_drop(&cfg).await;
+        }
+
+        // a shared database is left to the other tests using it
+        if let Some(namespace) = &cfg.namespace {
+            shared::clear_namespace(&cfg, namespace).await;
+            return;
+        }
+
+        TestRepo::drop(cfg).await;
+    }
+
+    // destroys the database from a separate thread with its own runtime, for when the drop watcher cannot
+    // run; returns once the database is destroyed or the teardown timeout has passed
+    fn teardown_on_thread(&self, start: Instant) {
+        let cfg = match self.detached_cfg() {
+            Some(cfg) => cfg,
+            None => return,
+        };
+
+        let dump = self.dump_on_drop.load(Ordering::SeqCst);
+        let teardown = registry::run_on_thread(TestRepo::teardown(cfg, dump, self.siblings.clone()));
+        // set before waiting, so that the watcher leaves the database to this thread even if the wait
+        // times out
+        self.torn_down.store(true, Ordering::SeqCst);
+
+        self.wait_for_teardown(start, || teardown.is_finished());
+    }
+
+    // the configuration reaching the database from a runtime other than the one serving this instance,
+    // or None if the database only exists within that runtime
+    pub(crate) fn detached_cfg(&self) -> Option<TestRepoConfig> {
+        // the emulator is served by the runtime that cannot make progress, and takes its data with it
+        #[cfg(feature = "mock")]
+        if self.mock.is_some() {
+            return None;
+        }
+
+        #[allow(unused_mut)]
+        let mut cfg = self.cfg.clone();
+        #[cfg(feature = "proxy")]
+        if let Some(proxy) = &self.proxy {
+            // the proxy is served by the runtime that cannot make progress
+            cfg = cfg.with_uri(proxy.upstream()?.to_string());
+        }
+        Some(cfg)
+    }
+
+    // polls `done` until it returns true or the teardown timeout has passed; logs the leaked database in
+    // the latter case
+    fn wait_for_teardown<F: Fn() -> bool>(&self, start: Instant, done: F) -> bool {
+        while !done() {
+            if let Some(timeout) = self.cfg.teardown_timeout {
+                if start.elapsed() >= timeout {
+                    log::error!(
+                        "Timed out after {:?} waiting for database {} to be destroyed; it may have leaked",
+                        timeout,
+                        self.cfg.db_name
+                    );
+                    return false;
+                }
+            }
+            std::thread::sleep(self.cfg.drop_poll_interval);
+        }
+        true
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "couch_rs_test::dump", skip_all, fields(db = %cfg.db_name))
+    )]
+    async fn dump_before_drop(cfg: &TestRepoConfig) {
+        let dir = match &cfg.dump_dir {
+            Some(dir) => dir,
+            None => return,
+        };
+        let path = dir.join(format!("{}.json", cfg.db_name.replace('/', "_")));
+
+        let dumped = match cfg.client() {
+            Ok(c) => dump::dump_database(&c, &cfg.db_name, &path).await,
+            Err(e) => Err(e.into()),
+        };
+        match dumped {
+            Ok(n) => log::info!("Dumped {} documents of {} to {}", n, cfg.db_name, path.display()),
+            Err(e) => log::error!("Error while dumping {}: {}", cfg.db_name, e),
+        }
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "couch_rs_test::destroy", skip_all, fields(db = %cfg.db_name))
+    )]
     async fn drop(cfg: TestRepoConfig) {
         // delete test db - panic on fail
-        let c = couch_rs::Client::new(&cfg.uri, &cfg.username, &cfg.password).unwrap();
+        let c = cfg.client().unwrap();
+
+        if !cfg.before_destroy.is_empty() {
+            match c.db(&cfg.db_name).await {
+                Ok(db) => hooks::run(&cfg.before_destroy, &db).await,
+                Err(e) => log::error!("Error while running hooks before destroying {}: {}", cfg.db_name, e),
+            }
+        }
+
+        // replications of a destroyed database would be retried by the scheduler indefinitely
+        if let Err(e) = replication::remove_replications(&c, &cfg.db_name).await {
+            log::debug!("Could not remove replications of {}: {}", cfg.db_name, e);
+        }
 
-        match c.destroy_db(&cfg.db_name).await {
+        let start = Instant::now();
+        let destroyed = cfg
+            .retry
+            .run("Destroying a test database", || c.destroy_db(&cfg.db_name))
+            .await;
+        match destroyed {
             Ok(b) => match b {
-                true => log::info!("Cleaned up database {}", cfg.db_name),
+                true => {
+                    trace_event!("destroyed test database");
+                    registry::unregister(&cfg.db_name);
+                    summary::record_destroyed(start.elapsed());
+                    log::info!("Cleaned up database {}", cfg.db_name)
+                }
                 false => log::info!("Failed to clean up database {}", cfg.db_name),
             },
 
@@ -210,10 +1399,63 @@ impl TestRepo {
 
 impl Drop for TestRepo {
     fn drop(&mut self) {
+        #[cfg(feature = "proxy")]
+        if let Some(proxy) = &self.proxy {
+            proxy.set_armed(false);
+        }
+
+        // the owning test is failing; keep its data for postmortem analysis
+        if std::thread::panicking() && self.cfg.dump_dir.is_some() {
+            self.dump_on_drop.store(true, Ordering::SeqCst);
+        }
+
+        let start = Instant::now();
+
+        // a current-thread runtime cannot run the drop watcher while this thread waits for it, and a
+        // runtime that has shut down has taken the watcher with it
+        let watcher_blocked = matches!(
+            Handle::try_current(),
+            Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::CurrentThread
+        );
+        if watcher_blocked || self.dropped_token.is_cancelled() {
+            self.teardown_on_thread(start);
+            // ends the watcher, which would otherwise poll until the runtime shuts down; it tears the
+            // database down itself only if no thread did
+            self.drop_token.cancel();
+            return;
+        }
+
         self.drop_token.cancel();
 
-        while !self.dropped_token.is_cancelled() {
-            std::thread::sleep(std::time::Duration::from_millis(100));
+        let finished = self.wait_for_teardown(start, || self.dropped_token.is_cancelled());
+        if finished && !self.torn_down.load(Ordering::SeqCst) {
+            // the runtime shut down while the watcher was tearing down
+            self.teardown_on_thread(start);
+        }
+    }
+}
+
+// used by the code generated by `#[derive(TestFixture)]`
+#[cfg(feature = "derive")]
+#[doc(hidden)]
+pub mod __private {
+    use couch_rs::document::TypedCouchDocument;
+    pub use couch_rs::error::CouchError;
+
+    use crate::{hooks, TestRepo};
+
+    pub async fn seed<S: TypedCouchDocument>(repo: &TestRepo, mut doc: S) -> Result<S, CouchError> {
+        let start = std::time::Instant::now();
+        let docs = std::slice::from_mut(&mut doc);
+        let written = repo.bulk_docs(docs).await?;
+        repo.resolve_collisions(docs, &written).await?;
+        // a skipped collision leaves the stored document in place, like with_data
+        match written.into_iter().next() {
+            Some(Err(e)) if e.status() != Some(crate::compat::StatusCode::CONFLICT) => return Err(e),
+            Some(Ok(_)) => repo.record_seeded(1, start.elapsed()),
+            _ => {}
         }
+        hooks::run(&repo.cfg.after_seed, &repo.db).await;
+        Ok(doc)
     }
 }
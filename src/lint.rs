@@ -0,0 +1,230 @@
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use serde_json::Value;
+
+use crate::fixtures::{docs_from_export, locate_docs, parse_export};
+
+// special members CouchDB accepts in a document written to it
+const SPECIAL_FIELDS: [&str; 9] = [
+    "_id",
+    "_rev",
+    "_attachments",
+    "_deleted",
+    "_revisions",
+    "_revs_info",
+    "_conflicts",
+    "_deleted_conflicts",
+    "_local_seq",
+];
+
+/// The kind of a [FixtureIssue].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FixtureIssueKind {
+    /// The file cannot be read.
+    Unreadable,
+    /// The file is not valid JSON.
+    InvalidJson,
+    /// The file is valid JSON, but not in a format accepted by
+    /// [TestRepo::with_data_from_export](crate::TestRepo::with_data_from_export), or holds a document
+    /// that is not a JSON object.
+    InvalidExport,
+    /// The document has the same `_id` as a document seen before, in the same or an earlier file.
+    DuplicateId,
+    /// The document carries a `_rev`, which makes its creation in a fresh database fail with a conflict.
+    RevisionPresent,
+    /// The `_id` of the document is not a string, is empty, or starts with an underscore without being
+    /// a design or local document id.
+    InvalidId,
+    /// The document has a top-level field starting with an underscore that CouchDB reserves and
+    /// rejects.
+    ReservedField,
+}
+
+/// A problem found in a fixture file by [validate_fixtures].
+#[derive(Clone, Debug)]
+pub struct FixtureIssue {
+    /// The file the problem was found in.
+    pub file: PathBuf,
+    /// The line the offending document starts on, or the line of a syntax error; `None` for problems
+    /// with the file as a whole.
+    pub line: Option<usize>,
+    /// What kind of problem this is.
+    pub kind: FixtureIssueKind,
+    /// A description of the problem.
+    pub message: String,
+}
+
+impl fmt::Display for FixtureIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.file.display(), line, self.message),
+            None => write!(f, "{}: {}", self.file.display(), self.message),
+        }
+    }
+}
+
+/// Checks the fixture file at `path`, or every `.json` file in the directory at `path` in name order,
+/// for mistakes that otherwise surface as confusing `_bulk_docs` errors: invalid JSON, files in no
+/// format accepted by [TestRepo::with_data_from_export](crate::TestRepo::with_data_from_export),
+/// `_id` values occurring more than once across the files, documents carrying a `_rev`, invalid `_id`
+/// values and reserved fields. Returns the issues found, in file and line order; an empty result means
+/// the fixtures are fine. No server is contacted.
+///
+/// ```rust,no_run
+/// let issues = couch_rs_test::validate_fixtures("tests/fixtures");
+/// assert!(
+///     issues.is_empty(),
+///     "{}",
+///     issues.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n")
+/// );
+/// ```
+pub fn validate_fixtures<P: AsRef<Path>>(path: P) -> Vec<FixtureIssue> {
+    let path = path.as_ref();
+    let files = match fixture_files(path) {
+        Ok(files) => files,
+        Err(e) => {
+            return vec![FixtureIssue {
+                file: path.to_path_buf(),
+                line: None,
+                kind: FixtureIssueKind::Unreadable,
+                message: e.to_string(),
+            }]
+        }
+    };
+
+    let mut issues = vec![];
+    let mut seen: HashMap<String, (PathBuf, usize)> = HashMap::new();
+    for file in files {
+        validate_file(&file, &mut seen, &mut issues);
+    }
+    issues
+}
+
+// the file at `path`, or the .json files directly in the directory at `path`, sorted by name
+fn fixture_files(path: &Path) -> std::io::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = vec![];
+    for entry in fs::read_dir(path)? {
+        let file = entry?.path();
+        if file.is_file() && file.extension().map(|e| e == "json").unwrap_or(false) {
+            files.push(file);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// checks one file, remembering its ids in `seen` to find duplicates across files
+fn validate_file(
+    file: &Path,
+    seen: &mut HashMap<String, (PathBuf, usize)>,
+    issues: &mut Vec<FixtureIssue>,
+) {
+    let mut issue = |line: Option<usize>, kind: FixtureIssueKind, message: String| {
+        issues.push(FixtureIssue {
+            file: file.to_path_buf(),
+            line,
+            kind,
+            message,
+        })
+    };
+
+    let text = match fs::read_to_string(file) {
+        Ok(text) => text,
+        Err(e) => return issue(None, FixtureIssueKind::Unreadable, e.to_string()),
+    };
+    let export = match parse_export(&text) {
+        Ok(export) => export,
+        Err(e) => return issue(Some(e.line()), FixtureIssueKind::InvalidJson, e.to_string()),
+    };
+    let docs = locate_docs(&text);
+    if docs.is_empty() {
+        if let Err(e) = docs_from_export(export) {
+            return issue(None, FixtureIssueKind::InvalidExport, e.to_string());
+        }
+    }
+
+    for (line, doc) in docs {
+        let fields = match doc.as_object() {
+            Some(fields) => fields,
+            None => {
+                issue(
+                    Some(line),
+                    FixtureIssueKind::InvalidExport,
+                    "document is not a JSON object".to_string(),
+                );
+                continue;
+            }
+        };
+
+        match fields.get("_id") {
+            None => {}
+            Some(Value::String(id)) if id.is_empty() => issue(
+                Some(line),
+                FixtureIssueKind::InvalidId,
+                "_id is empty".to_string(),
+            ),
+            Some(Value::String(id))
+                if id.starts_with('_')
+                    && !id.starts_with("_design/")
+                    && !id.starts_with("_local/") =>
+            {
+                issue(
+                    Some(line),
+                    FixtureIssueKind::InvalidId,
+                    format!(
+                        "_id {} starts with an underscore, which CouchDB reserves",
+                        id
+                    ),
+                )
+            }
+            Some(Value::String(id)) => match seen.get(id) {
+                Some((first_file, first_line)) => issue(
+                    Some(line),
+                    FixtureIssueKind::DuplicateId,
+                    format!(
+                        "duplicate _id {}, first defined at {}:{}",
+                        id,
+                        first_file.display(),
+                        first_line
+                    ),
+                ),
+                None => {
+                    seen.insert(id.clone(), (file.to_path_buf(), line));
+                }
+            },
+            Some(other) => issue(
+                Some(line),
+                FixtureIssueKind::InvalidId,
+                format!("_id {} is not a string", other),
+            ),
+        }
+
+        if fields.contains_key("_rev") {
+            issue(
+                Some(line),
+                FixtureIssueKind::RevisionPresent,
+                "document carries a _rev; remove it so the document can be created".to_string(),
+            );
+        }
+        for field in fields
+            .keys()
+            .filter(|f| f.starts_with('_') && !SPECIAL_FIELDS.contains(&f.as_str()))
+        {
+            issue(
+                Some(line),
+                FixtureIssueKind::ReservedField,
+                format!(
+                    "field {} starts with an underscore, which CouchDB reserves",
+                    field
+                ),
+            );
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::time::{Duration, Instant};
+
+use couch_rs::database::Database;
+use rand::Rng;
+use serde_json::Value;
+use tokio::task::JoinHandle;
+use tokio_util::sync::{CancellationToken, DropGuard};
+
+use crate::{compat::StatusCode, rt, TestRepo};
+
+impl TestRepo {
+    /// Starts writing documents produced by `doc_factory` to the database associated with this instance
+    /// at `rate` writes per second for `duration`, in a background task, while the test goes on, for
+    /// example to verify application behavior under contention. `doc_factory` is called with the number
+    /// of the write, starting at 0; a document whose `_id` is already taken is written as an update of
+    /// the current revision.
+    ///
+    /// The interval between writes is jittered by up to half of its length in either direction, so the
+    /// load does not march in lockstep with other periodic work. Writes are sequential, so the rate is
+    /// an upper bound when individual writes take longer than the interval.
+    ///
+    /// The load stops after `duration`, when [WriteLoad::finish] is awaited, or when the returned handle
+    /// is dropped.
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use serde_json::json;
+    /// # async fn example(repo: &couch_rs_test::TestRepo) {
+    /// let load = repo.load_writes(50.0, Duration::from_secs(5), |n| json!({"n": n}));
+    /// // ... assertions against the application while writes go on
+    /// let report = load.finish().await;
+    /// assert_eq!(report.failed, 0);
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate` is not a positive number.
+    pub fn load_writes<F>(&self, rate: f64, duration: Duration, doc_factory: F) -> WriteLoad
+    where
+        F: FnMut(usize) -> Value + Send + 'static,
+    {
+        assert!(
+            rate.is_finite() && rate > 0.0,
+            "write rate must be a positive number, got {}",
+            rate
+        );
+
+        let stop = CancellationToken::new();
+        let task = rt::spawn(apply_load(
+            self.db.clone(),
+            Duration::from_secs_f64(1.0 / rate),
+            duration,
+            doc_factory,
+            stop.clone(),
+        ));
+
+        WriteLoad {
+            task,
+            _stop: stop.drop_guard(),
+        }
+    }
+}
+
+/// Handle to a write load started with [TestRepo::load_writes].
+#[derive(Debug)]
+pub struct WriteLoad {
+    task: JoinHandle<LoadReport>,
+    _stop: DropGuard,
+}
+
+impl WriteLoad {
+    /// Stops the load, unless it has finished already, and returns what it achieved.
+    pub async fn stop(self) -> LoadReport {
+        let WriteLoad { task, _stop: stop } = self;
+        drop(stop);
+        task.await.unwrap_or_default()
+    }
+
+    /// Waits until the load has run for its full duration and returns what it achieved.
+    pub async fn finish(self) -> LoadReport {
+        // the guard is held until the task is done, so the load is not stopped early
+        let WriteLoad { task, _stop } = self;
+        task.await.unwrap_or_default()
+    }
+}
+
+/// Outcome of a write load started with [TestRepo::load_writes].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LoadReport {
+    /// Number of documents written successfully.
+    pub written: usize,
+    /// Number of writes rejected with a revision conflict.
+    pub conflicts: usize,
+    /// Number of writes that failed otherwise.
+    pub failed: usize,
+    /// Time the load ran for.
+    pub elapsed: Duration,
+}
+
+async fn apply_load<F>(
+    db: Database,
+    interval: Duration,
+    duration: Duration,
+    mut doc_factory: F,
+    stop: CancellationToken,
+) -> LoadReport
+where
+    F: FnMut(usize) -> Value,
+{
+    let start = Instant::now();
+    let mut report = LoadReport::default();
+
+    let mut n = 0;
+    while start.elapsed() < duration && !stop.is_cancelled() {
+        let mut doc = doc_factory(n);
+        n += 1;
+
+        let written = match doc["_id"].as_str() {
+            Some(id) => {
+                // an existing document is updated rather than rejected
+                if doc.get("_rev").is_none() {
+                    if let Ok(current) = db.get::<Value>(id).await {
+                        doc["_rev"] = current["_rev"].clone();
+                    }
+                }
+                db.save(&mut doc).await
+            }
+            None => db.create(&mut doc).await,
+        };
+        match written {
+            Ok(_) => report.written += 1,
+            Err(e) if e.status() == Some(StatusCode::CONFLICT) => report.conflicts += 1,
+            Err(e) => {
+                log::debug!("Write {} of the load on {} failed: {}", n, db.name(), e);
+                report.failed += 1;
+            }
+        }
+
+        let jitter = rand::thread_rng().gen_range(0.5..1.5);
+        let remaining = duration.saturating_sub(start.elapsed());
+        tokio::select! {
+            _ = tokio::time::sleep(interval.mul_f64(jitter).min(remaining)) => {}
+            _ = stop.cancelled() => break,
+        }
+    }
+
+    report.elapsed = start.elapsed();
+    log::info!(
+        "Applied write load to {}: {} written, {} conflicts, {} failed in {:?}",
+        db.name(),
+        report.written,
+        report.conflicts,
+        report.failed,
+        report.elapsed
+    );
+    report
+}
@@ -0,0 +1,94 @@
+use couch_rs::error::CouchError;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use crate::{
+    compat::{Method, StatusCode},
+    naming::encode_db_name,
+    TestRepo,
+};
+
+const LOCAL_PREFIX: &str = "_local/";
+
+impl TestRepo {
+    /// Reads the `_local` document with the given id from the database associated with this instance,
+    /// returning `None` if it does not exist. `_local` documents are neither replicated nor returned by
+    /// `_all_docs`, and are commonly used by sync clients to store replication checkpoints.
+    ///
+    /// The id may be given with or without the `_local/` prefix.
+    pub async fn get_local<T: DeserializeOwned>(&self, id: &str) -> Result<Option<T>, CouchError> {
+        let response = self
+            .client
+            .req(Method::GET, &self.local_path(id), None)
+            .send()
+            .await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let doc = response.error_for_status()?.json::<T>().await?;
+        Ok(Some(doc))
+    }
+
+    /// Creates or replaces the `_local` document with the given id in the database associated with this
+    /// instance, for example to seed the checkpoint a sync client resumes from. The current revision of an
+    /// existing document is looked up, so `doc` does not need to carry a `_rev`.
+    ///
+    /// The id may be given with or without the `_local/` prefix.
+    pub async fn put_local<T: Serialize>(&self, id: &str, doc: &T) -> Result<(), CouchError> {
+        let mut body = serde_json::to_value(doc)?;
+        if !body.is_object() {
+            return Err(CouchError::new(
+                format!("_local document {} must serialize to a JSON object", id),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+        if let Some(rev) = self
+            .get_local::<Value>(id)
+            .await?
+            .and_then(|existing| existing.get("_rev").cloned())
+        {
+            body["_rev"] = rev;
+        }
+
+        self.client
+            .req(Method::PUT, &self.local_path(id), None)
+            .body(serde_json::to_string(&body)?)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Deletes the `_local` document with the given id from the database associated with this instance,
+    /// returning whether it existed.
+    pub async fn delete_local(&self, id: &str) -> Result<bool, CouchError> {
+        let rev = match self.get_local::<Value>(id).await? {
+            Some(existing) => existing
+                .get("_rev")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+            None => return Ok(false),
+        };
+
+        self.client
+            .req(Method::DELETE, &self.local_path(id), None)
+            .query(&[("rev", rev)])
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(true)
+    }
+
+    fn local_path(&self, id: &str) -> String {
+        let id = id.strip_prefix(LOCAL_PREFIX).unwrap_or(id);
+        format!(
+            "{}/{}{}",
+            encode_db_name(&self.cfg.db_name),
+            LOCAL_PREFIX,
+            encode_db_name(id)
+        )
+    }
+}
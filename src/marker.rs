@@ -0,0 +1,85 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use couch_rs::{error::CouchError, Client};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    compat::{Method, StatusCode},
+    naming::encode_db_name,
+};
+
+/// Id of the `_local` document written into every database created by this crate. `_local` documents
+/// are neither replicated nor returned by `_all_docs`, so the marker does not interfere with tests.
+pub(crate) const MARKER_ID: &str = "_local/couch_rs_test";
+
+/// Metadata stored in the marker document of a test database.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct Marker {
+    /// Database name configured in [crate::TestRepoConfig], before the unique part was added.
+    pub base_name: String,
+    /// Creation time of the database, in seconds since the UNIX epoch.
+    pub created_at: u64,
+    /// Version of this crate that created the database.
+    pub crate_version: String,
+    /// Shard identifier embedded in the database name, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shard: Option<String>,
+}
+
+impl Marker {
+    pub(crate) fn new(base_name: &str, shard: Option<&str>) -> Marker {
+        Marker {
+            base_name: base_name.to_string(),
+            created_at: now_secs(),
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            shard: shard.map(str::to_string),
+        }
+    }
+}
+
+pub(crate) fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+pub(crate) async fn write_marker(
+    client: &Client,
+    db_name: &str,
+    marker: &Marker,
+) -> Result<(), CouchError> {
+    client
+        .req(
+            Method::PUT,
+            &format!("{}/{}", encode_db_name(db_name), MARKER_ID),
+            None,
+        )
+        .body(serde_json::to_string(marker)?)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// Reads the marker of `db_name`, returning `None` for databases that were not created by this crate.
+pub(crate) async fn read_marker(
+    client: &Client,
+    db_name: &str,
+) -> Result<Option<Marker>, CouchError> {
+    let response = client
+        .req(
+            Method::GET,
+            &format!("{}/{}", encode_db_name(db_name), MARKER_ID),
+            None,
+        )
+        .send()
+        .await?;
+
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+
+    let marker = response.error_for_status()?.json::<Marker>().await?;
+    Ok(Some(marker))
+}
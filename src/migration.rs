@@ -0,0 +1,190 @@
+use std::{
+    error::Error,
+    fs,
+    future::Future,
+    path::{Path, PathBuf},
+};
+
+use couch_rs::database::Database;
+use serde_json::Value;
+
+use crate::{
+    fixtures::{docs_from_export, parse_export},
+    snapshot::diff_docs,
+    TestRepo, TestRepoConfig,
+};
+
+/// A test of a schema migration: seeds a test database with documents in the old schema, runs the
+/// migration against it and asserts that the documents it leaves match the expected documents in the
+/// new schema.
+///
+/// ```rust
+/// # use serde_json::json;
+/// use couch_rs_test::{MigrationHarness, TestRepoConfig};
+///
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// MigrationHarness::new(TestRepoConfig::new(
+///     "http://localhost:5984",
+///     "admin",
+///     "password",
+///     "users",
+/// ))
+/// .with_before(vec![json!({"_id": "alice", "name": "Alice Smith"})])
+/// .with_expected(vec![json!({"_id": "alice", "first": "Alice", "last": "Smith", "version": 2})])
+/// .run(|db| async move { split_names(&db).await })
+/// .await?;
+/// # Ok(())
+/// # }
+/// # async fn split_names(_: &couch_rs::database::Database) -> Result<(), couch_rs::error::CouchError> {
+/// #     Ok(())
+/// # }
+/// ```
+///
+/// Documents are compared by id, ignoring their `_rev` and the fields set with
+/// [MigrationHarness::with_ignored_fields]. Design documents are only compared when an expected
+/// document is one, so that migrations need not be checked for the indexes they create.
+#[derive(Clone, Debug)]
+pub struct MigrationHarness {
+    config: TestRepoConfig,
+    before: Vec<Value>,
+    before_files: Vec<PathBuf>,
+    expected: Vec<Value>,
+    expected_files: Vec<PathBuf>,
+    ignored_fields: Vec<String>,
+}
+
+impl MigrationHarness {
+    /// Create a harness testing migrations against a test database created with `config`.
+    pub fn new(config: TestRepoConfig) -> MigrationHarness {
+        MigrationHarness {
+            config,
+            before: vec![],
+            before_files: vec![],
+            expected: vec![],
+            expected_files: vec![],
+            ignored_fields: vec![],
+        }
+    }
+
+    /// Add documents in the old schema to seed the database with before the migration.
+    pub fn with_before(mut self, docs: Vec<Value>) -> MigrationHarness {
+        self.before.extend(docs);
+        self
+    }
+
+    /// Add the documents of the fixture file at `path`, in any format accepted by
+    /// [TestRepo::with_data_from_export], to seed the database with before the migration.
+    pub fn with_before_from_file<P: AsRef<Path>>(mut self, path: P) -> MigrationHarness {
+        self.before_files.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Add documents the database is expected to hold after the migration; each needs an `_id`.
+    pub fn with_expected(mut self, docs: Vec<Value>) -> MigrationHarness {
+        self.expected.extend(docs);
+        self
+    }
+
+    /// Add the documents of the file at `path`, in any format accepted by
+    /// [TestRepo::with_data_from_export], to those expected after the migration.
+    pub fn with_expected_from_file<P: AsRef<Path>>(mut self, path: P) -> MigrationHarness {
+        self.expected_files.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Ignore the fields at the JSON pointers in `pointers`, such as `/migrated_at`, when comparing
+    /// documents, for fields whose values the migration cannot reproduce between runs. Ignored fields
+    /// need not appear in the expected documents.
+    pub fn with_ignored_fields(mut self, pointers: &[&str]) -> MigrationHarness {
+        self.ignored_fields
+            .extend(pointers.iter().map(|pointer| pointer.to_string()));
+        self
+    }
+
+    /// Creates the test database, seeds it with the documents in the old schema, runs `migration`
+    /// against it, and asserts that it then holds exactly the expected documents. Returns the test
+    /// repository, for further assertions, for example that running the migration again changes
+    /// nothing.
+    ///
+    /// Errors creating or seeding the database, reading fixture files, or returned by `migration` are
+    /// returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the documents after the migration differ from the expected documents, listing missing
+    /// (`-`), unexpected (`+`) and changed (`~`) documents.
+    pub async fn run<F, Fut, E>(self, migration: F) -> Result<TestRepo, Box<dyn Error>>
+    where
+        F: FnOnce(Database) -> Fut,
+        Fut: Future<Output = Result<(), E>>,
+        E: Into<Box<dyn Error>>,
+    {
+        let mut before = self.before;
+        for path in self.before_files.iter() {
+            before.extend(read_docs(path)?);
+        }
+        let mut expected = self.expected;
+        for path in self.expected_files.iter() {
+            expected.extend(read_docs(path)?);
+        }
+        if let Some(doc) = expected.iter().find(|doc| !doc["_id"].is_string()) {
+            return Err(format!("Expected document without an _id: {}", doc).into());
+        }
+
+        let repo = TestRepo::new(self.config).await?;
+        if !before.is_empty() {
+            repo.with_data(&mut before).await?;
+        }
+
+        migration(repo.db.clone()).await.map_err(Into::into)?;
+
+        let compare_design_docs = expected.iter().any(is_design_doc);
+        let mut actual: Vec<Value> = repo
+            .snapshot(&[])
+            .await?
+            .into_iter()
+            .filter(|doc| compare_design_docs || !is_design_doc(doc))
+            .collect();
+        for doc in actual.iter_mut() {
+            remove_field(doc, "/_rev");
+        }
+        for doc in actual.iter_mut().chain(expected.iter_mut()) {
+            for pointer in self.ignored_fields.iter() {
+                remove_field(doc, pointer);
+            }
+        }
+
+        let diff = diff_docs(&expected, &actual);
+        if !diff.is_empty() {
+            panic!(
+                "Documents of {} after the migration differ from the expected documents:{}",
+                repo.cfg.db_name, diff
+            );
+        }
+        Ok(repo)
+    }
+}
+
+fn read_docs(path: &Path) -> Result<Vec<Value>, Box<dyn Error>> {
+    let export = parse_export(&fs::read_to_string(path)?)
+        .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+    Ok(docs_from_export(export)?)
+}
+
+// removes the field at the JSON pointer `pointer` from `doc`, if it has one
+fn remove_field(doc: &mut Value, pointer: &str) {
+    let (parent, key) = match pointer.rsplit_once('/') {
+        Some(split) => split,
+        None => return,
+    };
+    let key = key.replace("~1", "/").replace("~0", "~");
+    if let Some(Value::Object(fields)) = doc.pointer_mut(parent) {
+        fields.remove(&key);
+    }
+}
+
+fn is_design_doc(doc: &Value) -> bool {
+    doc["_id"]
+        .as_str()
+        .is_some_and(|id| id.starts_with("_design/"))
+}
@@ -0,0 +1,771 @@
+//! An in-process CouchDB emulator, for fast unit-level tests and offline development with the regular
+//! [TestRepo](crate::TestRepo) API.
+//!
+//! With [TestRepoConfig::with_mock](crate::TestRepoConfig::with_mock), [TestRepo::new](crate::TestRepo::new)
+//! starts an emulator on a free port of the loopback interface and connects to it instead of the
+//! configured uri; credentials are accepted but not checked. The emulator is stopped, and all its data
+//! discarded, when the repo is dropped.
+//!
+//! The emulator covers the part of the CouchDB API used to seed and inspect test data:
+//!
+//! * creating, inspecting, listing and deleting databases
+//! * reading, writing and deleting documents, including design and `_local` documents, with revision
+//!   checks
+//! * `_bulk_docs`
+//! * `_all_docs`, with `include_docs`, `keys`, `start_key`, `end_key`, `inclusive_end`, `descending`,
+//!   `skip` and `limit`
+//! * `_find`, with a subset of Mango selectors (equality, comparisons, `$in`, `$nin`, `$exists`,
+//!   `$type`, `$size` and the combination operators), `fields`, `sort`, `skip`, `limit` and
+//!   `bookmark`
+//! * `_index` and `_explain`, which picks the first index all of whose fields a selector constrains
+//! * `_changes`, with `since`, `include_docs`, `limit` and the `normal` and `longpoll` feeds
+//! * `_active_tasks`, which is always empty, and `_membership`, listing the single node
+//!   `nonode@nohost`
+//!
+//! Views, filtered changes, attachments and replication are not emulated; such requests are answered
+//! with status 501 so that tests relying on them fail clearly.
+//!
+//! Only available with the `mock` feature.
+
+use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
+    convert::Infallible,
+    error::Error,
+    net::{Ipv4Addr, SocketAddr, TcpListener},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use http::{Method, Request, Response, StatusCode};
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Server,
+};
+use serde_json::{json, Map, Value};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    naming::percent_decode,
+    selector,
+    store::{DocStore, WriteError},
+};
+
+const DEFAULT_FIND_LIMIT: usize = 25;
+// the name of the node of the emulator, as of a CouchDB server set up as a single node
+const MOCK_NODE: &str = "nonode@nohost";
+// prefix of the bookmarks returned by _find, followed by the offset of the next page
+const BOOKMARK_PREFIX: &str = "mock-";
+// how long a long poll of the changes feed waits for a change unless it sets a timeout, as in CouchDB
+const DEFAULT_LONGPOLL_TIMEOUT_MS: u64 = 60_000;
+// how often a long poll rereads the database while waiting for a change
+const LONGPOLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Default)]
+struct Db {
+    docs: DocStore,
+    local: BTreeMap<String, Value>,
+    // the Mango indexes created, by name, with their fields
+    indexes: BTreeMap<String, Vec<String>>,
+}
+
+type Dbs = Mutex<BTreeMap<String, Db>>;
+
+type Reply = (StatusCode, Value);
+
+/// A running emulator; it is shut down when this is dropped.
+pub(crate) struct MockServer {
+    uri: String,
+    shutdown: CancellationToken,
+}
+
+impl MockServer {
+    /// Starts an emulator on a free port of the loopback interface.
+    pub(crate) async fn start() -> Result<MockServer, Box<dyn Error>> {
+        let dbs: Arc<Dbs> = Arc::new(Mutex::new(BTreeMap::new()));
+
+        let listener = TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)))?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+
+        let server = Server::from_tcp(listener)?.serve(make_service_fn(move |_| {
+            let dbs = dbs.clone();
+            async move { Ok::<_, Infallible>(service_fn(move |req| handle(dbs.clone(), req))) }
+        }));
+
+        let shutdown = CancellationToken::new();
+        let cancelled = shutdown.clone();
+        tokio::spawn(async move {
+            if let Err(e) = server
+                .with_graceful_shutdown(cancelled.cancelled_owned())
+                .await
+            {
+                log::error!("CouchDB emulator on {} failed: {}", addr, e);
+            }
+        });
+
+        log::info!("Started CouchDB emulator on {}", addr);
+
+        Ok(MockServer {
+            uri: format!("http://{}", addr),
+            shutdown,
+        })
+    }
+
+    /// The uri clients should connect to instead of CouchDB.
+    pub(crate) fn uri(&self) -> &str {
+        &self.uri
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.shutdown.cancel();
+    }
+}
+
+async fn handle(dbs: Arc<Dbs>, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let method = req.method().clone();
+    let segments: Vec<String> = req
+        .uri()
+        .path()
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(percent_decode)
+        .collect();
+    let query = parse_query(req.uri().query().unwrap_or_default());
+
+    let body = match hyper::body::to_bytes(req.into_body()).await {
+        Ok(bytes) if bytes.iter().all(u8::is_ascii_whitespace) => Ok(Value::Null),
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .map_err(|e| error(StatusCode::BAD_REQUEST, "bad_request", &e.to_string())),
+        Err(e) => Err(error(
+            StatusCode::BAD_REQUEST,
+            "bad_request",
+            &e.to_string(),
+        )),
+    };
+    let (status, body) = match body {
+        Ok(body) => {
+            let mut reply = route(&dbs, &method, &segments, &query, body.clone());
+            // a long poll of the changes feed waits for a change, re-reading the database until one
+            // arrives or the timeout passes
+            if segments.last().map(String::as_str) == Some("_changes")
+                && query.get("feed").and_then(Value::as_str) == Some("longpoll")
+            {
+                let timeout = query
+                    .get("timeout")
+                    .and_then(Value::as_u64)
+                    .unwrap_or(DEFAULT_LONGPOLL_TIMEOUT_MS);
+                let deadline = Instant::now() + Duration::from_millis(timeout);
+                while reply.0 == StatusCode::OK
+                    && reply.1["results"].as_array().map(Vec::is_empty) == Some(true)
+                    && Instant::now() < deadline
+                {
+                    tokio::time::sleep(LONGPOLL_INTERVAL).await;
+                    reply = route(&dbs, &method, &segments, &query, body.clone());
+                }
+            }
+            reply
+        }
+        Err(reply) => reply,
+    };
+
+    let response = Response::builder()
+        .status(status)
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap_or_default();
+    Ok(response)
+}
+
+fn route(
+    dbs: &Dbs,
+    method: &Method,
+    segments: &[String],
+    query: &Map<String, Value>,
+    body: Value,
+) -> Reply {
+    let mut dbs = dbs.lock().unwrap_or_else(|e| e.into_inner());
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+    match (method.as_str(), segments.as_slice()) {
+        ("GET", []) => (
+            StatusCode::OK,
+            json!({
+                "couchdb": "Welcome",
+                "version": "3.3.3",
+                "vendor": {"name": "couch_rs_test emulator"},
+            }),
+        ),
+        ("GET", ["_up"]) => (StatusCode::OK, json!({"status": "ok"})),
+        // the emulator runs no background tasks
+        ("GET", ["_active_tasks"]) => (StatusCode::OK, json!([])),
+        ("GET", ["_membership"]) => (
+            StatusCode::OK,
+            json!({"all_nodes": [MOCK_NODE], "cluster_nodes": [MOCK_NODE]}),
+        ),
+        ("GET", ["_all_dbs"]) => (StatusCode::OK, json!(dbs.keys().collect::<Vec<_>>())),
+        ("PUT", [name]) if !name.starts_with('_') => {
+            if dbs.contains_key(*name) {
+                return error(
+                    StatusCode::PRECONDITION_FAILED,
+                    "file_exists",
+                    "The database could not be created, the file already exists.",
+                );
+            }
+            dbs.insert(name.to_string(), Db::default());
+            (StatusCode::CREATED, json!({"ok": true}))
+        }
+        (_, [name, rest @ ..]) if !name.starts_with('_') => match dbs.get_mut(*name) {
+            Some(db) => match (method.as_str(), rest) {
+                ("DELETE", []) => {
+                    dbs.remove(*name);
+                    (StatusCode::OK, json!({"ok": true}))
+                }
+                _ => route_db(name, db, method, rest, query, body),
+            },
+            None => not_found("Database does not exist."),
+        },
+        (_, [endpoint, ..]) => error(
+            StatusCode::NOT_IMPLEMENTED,
+            "not_implemented",
+            &format!("{} is not emulated", endpoint),
+        ),
+        (_, []) => method_not_allowed(),
+    }
+}
+
+fn route_db(
+    name: &str,
+    db: &mut Db,
+    method: &Method,
+    rest: &[&str],
+    query: &Map<String, Value>,
+    body: Value,
+) -> Reply {
+    match (method.as_str(), rest) {
+        ("GET" | "HEAD", []) => (StatusCode::OK, info(name, db)),
+        ("POST", []) => {
+            let mut doc = body;
+            write_reply(db.docs.write(&mut doc))
+        }
+        ("POST", ["_bulk_docs"]) => bulk_docs(db, body),
+        ("GET" | "POST", ["_all_docs"]) => all_docs(db, merge(query, body)),
+        ("POST", ["_find"]) => find(db, body),
+        ("GET" | "POST", ["_changes"]) => changes(db, merge(query, body)),
+        ("POST", ["_explain"]) => explain(db, body),
+        ("POST", ["_index"]) => create_index(db, body),
+        ("POST", ["_compact", ..] | ["_view_cleanup"] | ["_ensure_full_commit"]) => {
+            (StatusCode::ACCEPTED, json!({"ok": true}))
+        }
+        (_, ["_local", id]) => local_doc(db, method, id, query, body),
+        (_, ["_design", ddoc]) => doc(db, method, &format!("_design/{}", ddoc), query, body),
+        (_, [id]) if !id.starts_with('_') => doc(db, method, id, query, body),
+        (_, [endpoint, ..]) => error(
+            StatusCode::NOT_IMPLEMENTED,
+            "not_implemented",
+            &format!("{} is not emulated", endpoint),
+        ),
+        _ => not_found("missing"),
+    }
+}
+
+fn info(name: &str, db: &Db) -> Value {
+    json!({
+        "db_name": name,
+        "doc_count": db.docs.live().count(),
+        "doc_del_count": db.docs.deleted_count(),
+        "update_seq": format!("{}-emulated", db.docs.update_seq()),
+        "purge_seq": "0-emulated",
+        "compact_running": false,
+        "disk_format_version": 8,
+        "instance_start_time": "0",
+        "sizes": {"active": 0, "external": 0, "file": 0},
+        "cluster": {"q": 1, "n": 1, "w": 1, "r": 1},
+        "props": {},
+    })
+}
+
+fn doc(db: &mut Db, method: &Method, id: &str, query: &Map<String, Value>, body: Value) -> Reply {
+    match method.as_str() {
+        "GET" | "HEAD" => match db.docs.get(id) {
+            Some(doc) => (StatusCode::OK, doc.clone()),
+            None if db.docs.is_deleted(id) => not_found("deleted"),
+            None => not_found("missing"),
+        },
+        "PUT" => {
+            let mut doc = body;
+            if let Some(fields) = doc.as_object_mut() {
+                fields.insert("_id".to_string(), Value::from(id));
+                if let Some(rev) = query.get("rev") {
+                    fields.insert("_rev".to_string(), rev.clone());
+                }
+            }
+            write_reply(db.docs.write(&mut doc))
+        }
+        "DELETE" => {
+            if db.docs.get(id).is_none() {
+                return not_found("missing");
+            }
+            let mut tombstone = json!({"_id": id, "_deleted": true});
+            if let Some(rev) = query.get("rev") {
+                tombstone["_rev"] = rev.clone();
+            }
+            match db.docs.write(&mut tombstone) {
+                Ok((id, rev)) => (StatusCode::OK, json!({"ok": true, "id": id, "rev": rev})),
+                Err(e) => write_error(e),
+            }
+        }
+        _ => method_not_allowed(),
+    }
+}
+
+fn local_doc(
+    db: &mut Db,
+    method: &Method,
+    id: &str,
+    query: &Map<String, Value>,
+    body: Value,
+) -> Reply {
+    let id = format!("_local/{}", id);
+    match method.as_str() {
+        "GET" | "HEAD" => match db.local.get(&id) {
+            Some(doc) => (StatusCode::OK, doc.clone()),
+            None => not_found("missing"),
+        },
+        "PUT" => {
+            let mut doc = match body {
+                Value::Object(fields) => fields,
+                _ => return write_error(WriteError::BadRequest),
+            };
+            // CouchDB does not check revisions of _local documents, but counts them
+            let generation = db
+                .local
+                .get(&id)
+                .and_then(|d| d["_rev"].as_str())
+                .and_then(|rev| rev.strip_prefix("0-"))
+                .and_then(|n| n.parse::<u64>().ok())
+                .unwrap_or(0)
+                + 1;
+            let rev = format!("0-{}", generation);
+            doc.insert("_id".to_string(), Value::from(id.clone()));
+            doc.insert("_rev".to_string(), Value::from(rev.clone()));
+            db.local.insert(id.clone(), Value::Object(doc));
+            (
+                StatusCode::CREATED,
+                json!({"ok": true, "id": id, "rev": rev}),
+            )
+        }
+        "DELETE" => match db.local.remove(&id) {
+            Some(_) => {
+                let rev = query.get("rev").cloned().unwrap_or(Value::from("0-0"));
+                (StatusCode::OK, json!({"ok": true, "id": id, "rev": rev}))
+            }
+            None => not_found("missing"),
+        },
+        _ => method_not_allowed(),
+    }
+}
+
+fn bulk_docs(db: &mut Db, body: Value) -> Reply {
+    let docs = match body.get("docs").and_then(Value::as_array) {
+        Some(docs) => docs.clone(),
+        None => {
+            return error(
+                StatusCode::BAD_REQUEST,
+                "bad_request",
+                "POST body must include `docs` parameter.",
+            )
+        }
+    };
+
+    let results: Vec<Value> = docs
+        .into_iter()
+        .map(|mut doc| {
+            let id = doc["_id"].clone();
+            match db.docs.write(&mut doc) {
+                Ok((id, rev)) => json!({"ok": true, "id": id, "rev": rev}),
+                Err(e) => {
+                    let (error, reason) = e.describe();
+                    json!({"id": id, "error": error, "reason": reason})
+                }
+            }
+        })
+        .collect();
+    (StatusCode::CREATED, Value::Array(results))
+}
+
+fn changes(db: &Db, params: Map<String, Value>) -> Reply {
+    if params.contains_key("filter") {
+        return error(
+            StatusCode::NOT_IMPLEMENTED,
+            "not_implemented",
+            "filtered changes are not emulated",
+        );
+    }
+    // sequences are returned as `{n}-emulated`; `now` starts after the latest change
+    let since = match params.get("since") {
+        Some(Value::String(since)) if since == "now" => db.docs.update_seq(),
+        Some(Value::String(since)) => match since.split('-').next().map(str::parse::<u64>) {
+            Some(Ok(since)) => since,
+            _ => return error(StatusCode::BAD_REQUEST, "bad_request", "Malformed sequence"),
+        },
+        Some(Value::Number(since)) => since.as_u64().unwrap_or(0),
+        _ => 0,
+    };
+    let include_docs = params.get("include_docs") == Some(&Value::Bool(true));
+    let limit = params
+        .get("limit")
+        .and_then(Value::as_u64)
+        .map(|limit| limit as usize)
+        .unwrap_or(usize::MAX);
+
+    let results: Vec<Value> = db
+        .docs
+        .changes_since(since)
+        .into_iter()
+        .take(limit)
+        .map(|(seq, doc)| {
+            let mut change = json!({
+                "seq": format!("{}-emulated", seq),
+                "id": doc["_id"],
+                "changes": [{"rev": doc["_rev"]}],
+            });
+            if doc.get("_deleted") == Some(&Value::Bool(true)) {
+                change["deleted"] = Value::Bool(true);
+            }
+            if include_docs {
+                change["doc"] = doc.clone();
+            }
+            change
+        })
+        .collect();
+    let last_seq = results
+        .last()
+        .map(|change| change["seq"].clone())
+        .unwrap_or_else(|| Value::from(format!("{}-emulated", since)));
+    let pending = db.docs.changes_since(since).len() - results.len();
+    (
+        StatusCode::OK,
+        json!({"results": results, "last_seq": last_seq, "pending": pending}),
+    )
+}
+
+fn all_docs(db: &Db, params: Map<String, Value>) -> Reply {
+    let include_docs = params.get("include_docs") == Some(&Value::Bool(true));
+    let row = |id: &str, doc: &Value| {
+        let mut row = json!({"id": id, "key": id, "value": {"rev": doc["_rev"]}});
+        if include_docs {
+            row["doc"] = doc.clone();
+        }
+        row
+    };
+
+    let total_rows = db.docs.live().count();
+    let rows: Vec<Value> = match params.get("keys").and_then(Value::as_array) {
+        Some(keys) => keys
+            .iter()
+            .map(|key| match key.as_str().and_then(|id| db.docs.get(id)) {
+                Some(doc) => row(key.as_str().unwrap_or_default(), doc),
+                None => json!({"key": key, "error": "not_found"}),
+            })
+            .collect(),
+        None => {
+            let descending = params.get("descending") == Some(&Value::Bool(true));
+            let start = param(&params, &["start_key", "startkey", "key"]);
+            let end = param(&params, &["end_key", "endkey", "key"]);
+            let inclusive_end = params.get("inclusive_end") != Some(&Value::Bool(false));
+
+            let in_range = |id: &str| {
+                let after_start = match start {
+                    Some(start) if descending => id <= start,
+                    Some(start) => id >= start,
+                    None => true,
+                };
+                let before_end = match (end, inclusive_end) {
+                    (Some(end), true) if descending => id >= end,
+                    (Some(end), false) if descending => id > end,
+                    (Some(end), true) => id <= end,
+                    (Some(end), false) => id < end,
+                    (None, _) => true,
+                };
+                after_start && before_end
+            };
+
+            let docs: Box<dyn Iterator<Item = (&String, &Value)>> = if descending {
+                Box::new(db.docs.live().rev())
+            } else {
+                Box::new(db.docs.live())
+            };
+            docs.filter(|(id, _)| in_range(id))
+                .map(|(id, doc)| row(id, doc))
+                .collect()
+        }
+    };
+
+    let rows = page(rows, &params);
+    (
+        StatusCode::OK,
+        json!({"total_rows": total_rows, "offset": 0, "rows": rows}),
+    )
+}
+
+fn find(db: &Db, body: Value) -> Reply {
+    let selector = match body.get("selector") {
+        Some(selector) if selector.is_object() => selector,
+        _ => {
+            return error(
+                StatusCode::BAD_REQUEST,
+                "bad_request",
+                "Missing required key: selector",
+            )
+        }
+    };
+
+    let mut docs: Vec<Value> = db
+        .docs
+        .live()
+        .filter(|(id, _)| !id.starts_with("_design/"))
+        .map(|(_, doc)| doc)
+        .filter(|doc| selector::matches(doc, selector))
+        .cloned()
+        .collect();
+
+    if let Some(sort) = body.get("sort").and_then(Value::as_array) {
+        let fields: Vec<(String, bool)> = sort
+            .iter()
+            .filter_map(|spec| match spec {
+                Value::String(field) => Some((field.clone(), false)),
+                Value::Object(spec) => spec
+                    .iter()
+                    .next()
+                    .map(|(field, dir)| (field.clone(), dir == "desc")),
+                _ => None,
+            })
+            .collect();
+        docs.sort_by(|a, b| {
+            fields
+                .iter()
+                .map(|(field, descending)| {
+                    let order = compare(lookup(a, field), lookup(b, field));
+                    if *descending {
+                        order.reverse()
+                    } else {
+                        order
+                    }
+                })
+                .find(|order| order.is_ne())
+                .unwrap_or(Ordering::Equal)
+        });
+    }
+
+    // a bookmark holds the number of results of the query returned before it
+    let offset = match body.get("bookmark").and_then(Value::as_str) {
+        None | Some("nil") => 0,
+        Some(bookmark) => match bookmark
+            .strip_prefix(BOOKMARK_PREFIX)
+            .and_then(|offset| offset.parse::<usize>().ok())
+        {
+            Some(offset) => offset,
+            None => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    json!({
+                        "error": "invalid_bookmark",
+                        "reason": format!("Invalid bookmark value: {}", bookmark),
+                    }),
+                )
+            }
+        },
+    };
+    let mut params = body.as_object().cloned().unwrap_or_default();
+    params
+        .entry("limit")
+        .or_insert_with(|| Value::from(DEFAULT_FIND_LIMIT));
+    let skip = params.get("skip").and_then(Value::as_u64).unwrap_or(0) as usize;
+    let mut docs = page(docs.into_iter().skip(offset).collect(), &params);
+    let bookmark = format!("{}{}", BOOKMARK_PREFIX, offset + skip + docs.len());
+
+    if let Some(fields) = body.get("fields").and_then(Value::as_array) {
+        docs = docs
+            .into_iter()
+            .map(|doc| {
+                let mut projected = json!({});
+                for field in fields.iter().filter_map(Value::as_str) {
+                    if let Some(value) = lookup(&doc, field) {
+                        projected[field] = value.clone();
+                    }
+                }
+                projected
+            })
+            .collect();
+    }
+
+    (StatusCode::OK, json!({"docs": docs, "bookmark": bookmark}))
+}
+
+fn create_index(db: &mut Db, body: Value) -> Reply {
+    let fields: Vec<String> = body["index"]["fields"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|spec| match spec {
+            Value::String(field) => Some(field.clone()),
+            Value::Object(spec) => spec.keys().next().cloned(),
+            _ => None,
+        })
+        .collect();
+    if fields.is_empty() {
+        return error(
+            StatusCode::BAD_REQUEST,
+            "bad_request",
+            "Missing required key: index.fields",
+        );
+    }
+    let name = match body["name"].as_str() {
+        Some(name) => name.to_string(),
+        None => format!("emulated-{}", fields.join("-")),
+    };
+    let result = match db.indexes.insert(name.clone(), fields) {
+        Some(_) => "exists",
+        None => "created",
+    };
+    (
+        StatusCode::OK,
+        json!({"result": result, "id": "_design/emulated", "name": name}),
+    )
+}
+
+// the first index all of whose fields the selector constrains, as CouchDB would pick a json index,
+// or a full scan of _all_docs
+fn explain(db: &Db, body: Value) -> Reply {
+    let constrained = selector::fields(&body["selector"]);
+    let index = db
+        .indexes
+        .iter()
+        .find(|(_, fields)| fields.iter().all(|field| constrained.contains(field)));
+    let index = match index {
+        Some((name, fields)) => json!({
+            "ddoc": "_design/emulated",
+            "name": name,
+            "type": "json",
+            "def": {"fields": fields.iter().map(|f| json!({ f: "asc" })).collect::<Vec<_>>()},
+        }),
+        None => json!({
+            "ddoc": null,
+            "name": "_all_docs",
+            "type": "special",
+            "def": {"fields": [{"_id": "asc"}]},
+        }),
+    };
+    (
+        StatusCode::OK,
+        json!({"index": index, "selector": body["selector"]}),
+    )
+}
+
+fn page(rows: Vec<Value>, params: &Map<String, Value>) -> Vec<Value> {
+    let skip = params.get("skip").and_then(Value::as_u64).unwrap_or(0) as usize;
+    let limit = params
+        .get("limit")
+        .and_then(Value::as_u64)
+        .map(|l| l as usize)
+        .unwrap_or(usize::MAX);
+    rows.into_iter().skip(skip).take(limit).collect()
+}
+
+fn param<'a>(params: &'a Map<String, Value>, names: &[&str]) -> Option<&'a str> {
+    names
+        .iter()
+        .find_map(|name| params.get(*name).and_then(Value::as_str))
+}
+
+fn lookup<'a>(doc: &'a Value, field: &str) -> Option<&'a Value> {
+    field
+        .split('.')
+        .try_fold(doc, |value, segment| value.get(segment))
+}
+
+// orders missing values first, then by CouchDB's collation of types; strings compare by code point
+fn compare(a: Option<&Value>, b: Option<&Value>) -> Ordering {
+    fn rank(value: Option<&Value>) -> u8 {
+        match value {
+            None => 0,
+            Some(Value::Null) => 1,
+            Some(Value::Bool(_)) => 2,
+            Some(Value::Number(_)) => 3,
+            Some(Value::String(_)) => 4,
+            Some(Value::Array(_)) => 5,
+            Some(Value::Object(_)) => 6,
+        }
+    }
+
+    match (a, b) {
+        (Some(Value::Bool(a)), Some(Value::Bool(b))) => a.cmp(b),
+        (Some(Value::Number(a)), Some(Value::Number(b))) => a
+            .as_f64()
+            .partial_cmp(&b.as_f64())
+            .unwrap_or(Ordering::Equal),
+        (Some(Value::String(a)), Some(Value::String(b))) => a.cmp(b),
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+fn write_reply(result: Result<(String, String), WriteError>) -> Reply {
+    match result {
+        Ok((id, rev)) => (
+            StatusCode::CREATED,
+            json!({"ok": true, "id": id, "rev": rev}),
+        ),
+        Err(e) => write_error(e),
+    }
+}
+
+fn write_error(e: WriteError) -> Reply {
+    let (code, reason) = e.describe();
+    let status = match e {
+        WriteError::Conflict => StatusCode::CONFLICT,
+        WriteError::BadRequest => StatusCode::BAD_REQUEST,
+    };
+    error(status, code, reason)
+}
+
+fn not_found(reason: &str) -> Reply {
+    error(StatusCode::NOT_FOUND, "not_found", reason)
+}
+
+fn method_not_allowed() -> Reply {
+    error(
+        StatusCode::METHOD_NOT_ALLOWED,
+        "method_not_allowed",
+        "Only GET, HEAD, PUT and DELETE are allowed",
+    )
+}
+
+fn error(status: StatusCode, error: &str, reason: &str) -> Reply {
+    (status, json!({"error": error, "reason": reason}))
+}
+
+// combines query string parameters with those of a JSON body; the body wins
+fn merge(query: &Map<String, Value>, body: Value) -> Map<String, Value> {
+    let mut params = query.clone();
+    if let Value::Object(body) = body {
+        params.extend(body);
+    }
+    params
+}
+
+// query string values are JSON, as CouchDB expects them; anything else is kept as a string
+fn parse_query(query: &str) -> Map<String, Value> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = percent_decode(&value.replace('+', " "));
+            let value = serde_json::from_str(&value).unwrap_or(Value::String(value));
+            (percent_decode(name), value)
+        })
+        .collect()
+}
@@ -0,0 +1,525 @@
+//! Strategies for generating the unique database name of a [TestRepo](crate::TestRepo).
+//!
+//! Every database created by this crate is named from the database name configured in
+//! [TestRepoConfig](crate::TestRepoConfig) plus a unique part chosen by a [NameStrategy]. The default
+//! strategy, [RandomSuffix], appends 12 random lowercase alphanumeric characters. Projects with their own
+//! naming conventions can select another built-in strategy or implement [NameStrategy] themselves and
+//! configure it with [TestRepoConfig::with_name_strategy](crate::TestRepoConfig::with_name_strategy).
+//!
+//! When the `COUCH_TEST_PREFIX` environment variable is set, its value is prepended to every database
+//! name before the strategy is applied. CI systems can use this to namespace the databases of each
+//! pipeline run, say with `ci-1234-`, and delete them by prefix afterwards.
+//!
+//! A shard identifier, set with [TestRepoConfig::with_shard](crate::TestRepoConfig::with_shard) or the
+//! `COUCH_TEST_SHARD` environment variable, is inserted after the configured database name, so that the
+//! databases of test runs split across several machines can be told apart and cleaned up per shard.
+
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use couch_rs::error::CouchError;
+use rand::{distributions::Alphanumeric, rngs::StdRng, Rng, RngCore, SeedableRng};
+
+use crate::compat::StatusCode;
+
+/// Environment variable read for a suffix seed when none is set via [crate::TestRepoConfig::with_seed].
+pub(crate) const SEED_ENV_VAR: &str = "COUCH_TEST_SEED";
+
+/// Environment variable holding a prefix prepended to the name of every database.
+pub(crate) const PREFIX_ENV_VAR: &str = "COUCH_TEST_PREFIX";
+
+/// Environment variable holding the shard identifier embedded into every database name.
+pub(crate) const SHARD_ENV_VAR: &str = "COUCH_TEST_SHARD";
+
+/// Environment variable holding the partition of a test run split with cargo-nextest, used as the
+/// shard identifier when `COUCH_TEST_SHARD` is not set.
+pub(crate) const NEXTEST_PARTITION_ENV_VAR: &str = "NEXTEST_PARTITION";
+
+const SUFFIX_LENGTH: usize = 12;
+
+// longest database name CouchDB accepts
+const MAX_DB_NAME_LENGTH: usize = 238;
+
+// number of databases created so far in this process for each test thread and configured name; mixed
+// into the seed so that repeated databases with the same name do not collide while remaining
+// reproducible when tests run in parallel
+static SEEDED_COUNTS: OnceLock<Mutex<HashMap<(String, String), u64>>> = OnceLock::new();
+
+/// Produces the unique name of a test database from the database name configured in
+/// [TestRepoConfig](crate::TestRepoConfig).
+///
+/// Implementations should draw any randomness from `rng` rather than from their own random source, so
+/// that names remain reproducible when a seed is configured with
+/// [TestRepoConfig::with_seed](crate::TestRepoConfig::with_seed). The returned name must be a valid
+/// CouchDB database name.
+pub trait NameStrategy: Send + Sync {
+    /// Returns the unique database name for the configured `db_name`.
+    fn unique_name(&self, db_name: &str, rng: &mut dyn RngCore) -> String;
+}
+
+/// Appends a dash and 12 random lowercase alphanumeric characters to the configured database name. This
+/// is the default strategy.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RandomSuffix;
+
+impl NameStrategy for RandomSuffix {
+    fn unique_name(&self, db_name: &str, rng: &mut dyn RngCore) -> String {
+        format!("{}-{}", db_name, random_chars(rng, SUFFIX_LENGTH))
+    }
+}
+
+/// The characters a [CharsetSuffix] is drawn from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SuffixCharset {
+    /// Lowercase letters and digits, as used by [RandomSuffix]. This is the default.
+    #[default]
+    Alphanumeric,
+    /// Lowercase hexadecimal digits.
+    Hex,
+    /// Decimal digits.
+    Digits,
+    /// Lowercase letters.
+    Lowercase,
+}
+
+/// Appends a dash and a configurable number of random characters from a [SuffixCharset] to the
+/// configured database name; for example a longer suffix for very large parallel test matrices, or a
+/// hex-only one to match a naming convention. Usually configured with
+/// [TestRepoConfig::with_suffix](crate::TestRepoConfig::with_suffix).
+#[derive(Clone, Copy, Debug)]
+pub struct CharsetSuffix {
+    length: usize,
+    charset: SuffixCharset,
+}
+
+impl CharsetSuffix {
+    /// Create a strategy appending `length` characters from `charset`; a length of 0 is treated as 1.
+    pub fn new(length: usize, charset: SuffixCharset) -> CharsetSuffix {
+        CharsetSuffix {
+            length: length.max(1),
+            charset,
+        }
+    }
+}
+
+impl NameStrategy for CharsetSuffix {
+    fn unique_name(&self, db_name: &str, rng: &mut dyn RngCore) -> String {
+        let chars: &[u8] = match self.charset {
+            // drawn like RandomSuffix, so that both produce the same names from the same seed
+            SuffixCharset::Alphanumeric => {
+                return format!("{}-{}", db_name, random_chars(rng, self.length))
+            }
+            SuffixCharset::Hex => b"0123456789abcdef",
+            SuffixCharset::Digits => b"0123456789",
+            SuffixCharset::Lowercase => b"abcdefghijklmnopqrstuvwxyz",
+        };
+        let suffix: String = (0..self.length)
+            .map(|_| char::from(chars[rng.gen_range(0..chars.len())]))
+            .collect();
+        format!("{}-{}", db_name, suffix)
+    }
+}
+
+/// Appends a dash and a random (version 4) UUID to the configured database name.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UuidSuffix;
+
+impl NameStrategy for UuidSuffix {
+    fn unique_name(&self, db_name: &str, rng: &mut dyn RngCore) -> String {
+        let mut bytes = [0u8; 16];
+        rng.fill_bytes(&mut bytes);
+        format!(
+            "{}-{}",
+            db_name,
+            uuid::Builder::from_random_bytes(bytes).into_uuid()
+        )
+    }
+}
+
+/// Appends a dash and a time-ordered (version 7) UUID to the configured database name. Besides being
+/// unique, the names sort by creation time in `_all_dbs`, which lists databases by name, so leftover
+/// databases are easy to inspect in order; databases created within the same millisecond are ordered
+/// randomly.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UuidV7Suffix;
+
+impl NameStrategy for UuidV7Suffix {
+    fn unique_name(&self, db_name: &str, rng: &mut dyn RngCore) -> String {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        let mut bytes = [0u8; 10];
+        rng.fill_bytes(&mut bytes);
+        format!(
+            "{}-{}",
+            db_name,
+            uuid::Builder::from_unix_timestamp_millis(millis, &bytes).into_uuid()
+        )
+    }
+}
+
+/// Appends the current UNIX time in milliseconds and 4 random characters to the configured database
+/// name, so that leftover databases can be dated at a glance. The random characters keep databases
+/// created within the same millisecond apart.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TimestampSuffix;
+
+impl NameStrategy for TimestampSuffix {
+    fn unique_name(&self, db_name: &str, rng: &mut dyn RngCore) -> String {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        format!("{}-{}-{}", db_name, millis, random_chars(rng, 4))
+    }
+}
+
+/// Embeds the name of the running test, followed by 6 random characters, in the database name.
+///
+/// The test name is taken from the name of the current thread, which the Rust test harness sets to the
+/// test's path (for example `tests::creates_user`). Tests run on a `#[tokio::test]` runtime keep this
+/// name, since the default runtime for tests executes on the test thread. When the thread is unnamed,
+/// or is the main thread, only the random characters are appended.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TestNameSuffix;
+
+impl NameStrategy for TestNameSuffix {
+    fn unique_name(&self, db_name: &str, rng: &mut dyn RngCore) -> String {
+        let suffix = random_chars(rng, 6);
+        match std::thread::current().name() {
+            Some(name) if name != "main" => format!("{}-{}-{}", db_name, sanitize(name), suffix),
+            _ => format!("{}-{}", db_name, suffix),
+        }
+    }
+}
+
+/// Expands to the name of the enclosing function, for use with
+/// [TestRepoConfig::named](crate::TestRepoConfig::named) so that a test's database carries the test's
+/// name without repeating it as a string literal.
+///
+/// ```rust
+/// use couch_rs_test::{test_name, TestRepoConfig};
+///
+/// fn creates_user() {
+///     assert_eq!(test_name!(), "creates_user");
+///     let _cfg = TestRepoConfig::new("http://localhost:5984", "admin", "password", "users")
+///         .named(test_name!());
+/// }
+/// # creates_user();
+/// ```
+#[macro_export]
+macro_rules! test_name {
+    () => {{
+        fn f() {}
+        fn type_name_of<T>(_: T) -> &'static str {
+            ::std::any::type_name::<T>()
+        }
+        let path = type_name_of(f);
+        // strip the trailing `::f` as well as any `::{{closure}}` segments added by async fns
+        let path = path.strip_suffix("::f").unwrap_or(path);
+        let path = path.trim_end_matches("::{{closure}}");
+        path.rsplit("::").next().unwrap_or(path)
+    }};
+}
+
+/// Returns the seed to use for database suffixes, preferring the configured seed over the environment.
+pub(crate) fn resolve_seed(configured: Option<u64>) -> Option<u64> {
+    configured.or_else(|| match std::env::var(SEED_ENV_VAR) {
+        Ok(value) => match value.trim().parse() {
+            Ok(seed) => Some(seed),
+            Err(_) => {
+                log::warn!(
+                    "Ignoring {}={}; seed must be an unsigned integer",
+                    SEED_ENV_VAR,
+                    value
+                );
+                None
+            }
+        },
+        Err(_) => None,
+    })
+}
+
+/// Returns the prefix for all database names set in the environment, or an empty string.
+pub(crate) fn env_prefix() -> String {
+    std::env::var(PREFIX_ENV_VAR)
+        .map(|prefix| prefix.trim().to_string())
+        .unwrap_or_default()
+}
+
+/// Returns the shard identifier set in the environment, if any.
+pub(crate) fn env_shard() -> Option<String> {
+    [SHARD_ENV_VAR, NEXTEST_PARTITION_ENV_VAR]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .map(|shard| shard.trim().to_string())
+        .find(|shard| !shard.is_empty())
+}
+
+/// Creates the random source handed to a [NameStrategy]. With a seed, the source for the n-th database
+/// created with a given name by a given test is always the same. The test is identified by the name
+/// of the current thread, as in [TestNameSuffix], so that the order in which parallel tests create
+/// their databases does not change their names.
+pub(crate) fn name_rng(db_name: &str, seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => {
+            let test_name = std::thread::current()
+                .name()
+                .unwrap_or_default()
+                .to_string();
+            let key = fnv1a(&format!("{}\0{}", test_name, db_name));
+            let nth = {
+                let mut counts = SEEDED_COUNTS
+                    .get_or_init(|| Mutex::new(HashMap::new()))
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner());
+                let count = counts.entry((test_name, db_name.to_string())).or_insert(0);
+                *count += 1;
+                *count
+            };
+
+            StdRng::seed_from_u64(seed ^ key.wrapping_add(nth))
+        }
+        None => StdRng::from_entropy(),
+    }
+}
+
+pub(crate) fn random_chars(rng: &mut dyn RngCore, length: usize) -> String {
+    rng.sample_iter(&Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Checks `name` against the rules CouchDB applies to database names, `^[a-z][a-z0-9_$()+/-]*$` and at
+/// most 238 characters, returning an error naming the problem instead of the server's bare 400
+/// response.
+pub(crate) fn validate_db_name(name: &str) -> Result<(), CouchError> {
+    let invalid = |reason: String| {
+        Err(CouchError::new(
+            format!("Invalid CouchDB database name {:?}: {}", name, reason),
+            StatusCode::BAD_REQUEST,
+        ))
+    };
+
+    match name.chars().next() {
+        None => return invalid("name is empty".to_string()),
+        Some('a'..='z') => {}
+        Some(c) => return invalid(format!("must start with a lowercase letter, found {:?}", c)),
+    }
+
+    if name.len() > MAX_DB_NAME_LENGTH {
+        return invalid(format!(
+            "name is {} characters long; at most {} are allowed",
+            name.len(),
+            MAX_DB_NAME_LENGTH
+        ));
+    }
+
+    match name.chars().find(|c| !is_db_name_char(*c)) {
+        Some(c) => invalid(format!(
+            "{:?} is not allowed; only lowercase letters, digits and _$()+-/ may be used",
+            c
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Rewrites `name` into a valid CouchDB database name: letters are lowercased, other disallowed
+/// characters are replaced by underscores, a leading `db` is added if the name does not start with a
+/// letter and the result is cut to the longest length CouchDB allows.
+pub(crate) fn sanitize_db_name(name: &str) -> String {
+    let sanitized: String = name
+        .chars()
+        .map(|c| c.to_ascii_lowercase())
+        .map(|c| if is_db_name_char(c) { c } else { '_' })
+        .collect();
+
+    let mut sanitized = match sanitized.chars().next() {
+        Some('a'..='z') => sanitized,
+        _ => format!("db{}", sanitized),
+    };
+    sanitized.truncate(MAX_DB_NAME_LENGTH);
+    sanitized
+}
+
+/// Draws the unique name for `db_name` from `strategy`, shortening `db_name`, a sanitized name, as
+/// needed for the unique name to fit the length CouchDB allows.
+pub(crate) fn fitted_unique_name(
+    strategy: &dyn NameStrategy,
+    db_name: &str,
+    rng: &mut dyn RngCore,
+) -> String {
+    let name = strategy.unique_name(db_name, rng);
+    let excess = name.len().saturating_sub(MAX_DB_NAME_LENGTH);
+    if excess == 0 {
+        return name;
+    }
+    let mut shortened = db_name.to_string();
+    shortened.truncate(db_name.len().saturating_sub(excess).max(1));
+    strategy.unique_name(&shortened, rng)
+}
+
+/// Percent-encodes a database name the way couch_rs does when building request paths, so that names
+/// containing for example `/` address the intended database.
+pub(crate) fn encode_db_name(name: &str) -> String {
+    name.bytes()
+        .map(|b| match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' => char::from(b).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Decodes the `%XX` escapes of a path segment, such as a database name encoded by [encode_db_name].
+pub(crate) fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn is_db_name_char(c: char) -> bool {
+    matches!(c, 'a'..='z' | '0'..='9' | '_' | '$' | '(' | ')' | '+' | '/' | '-')
+}
+
+// maps a test path such as `tests::creates_user` onto characters CouchDB allows in database names
+pub(crate) fn sanitize(name: &str) -> String {
+    name.replace("::", "-")
+        .chars()
+        .map(|c| match c.to_ascii_lowercase() {
+            c @ ('a'..='z' | '0'..='9' | '_' | '$' | '(' | ')' | '+' | '-') => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+// stable across platforms and compiler versions, unlike std's DefaultHasher
+pub(crate) fn fnv1a(value: &str) -> u64 {
+    value.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_accepts_valid_names() {
+        assert!(validate_db_name("users").is_ok());
+        assert!(validate_db_name("a").is_ok());
+        assert!(validate_db_name("team/users_2024-(v1)+$").is_ok());
+        assert!(validate_db_name(&"a".repeat(MAX_DB_NAME_LENGTH)).is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_empty_name() {
+        let e = validate_db_name("").unwrap_err();
+        assert_eq!(e.status(), Some(StatusCode::BAD_REQUEST));
+        assert!(e.to_string().contains("name is empty"), "{}", e);
+    }
+
+    #[test]
+    fn validate_rejects_too_long_name() {
+        let e = validate_db_name(&"a".repeat(MAX_DB_NAME_LENGTH + 1)).unwrap_err();
+        assert!(e.to_string().contains("239 characters long"), "{}", e);
+    }
+
+    #[test]
+    fn validate_rejects_uppercase() {
+        let e = validate_db_name("USERS").unwrap_err();
+        assert!(e.to_string().contains("found 'U'"), "{}", e);
+        let e = validate_db_name("usErs").unwrap_err();
+        assert!(e.to_string().contains("'E' is not allowed"), "{}", e);
+    }
+
+    #[test]
+    fn validate_rejects_leading_digit() {
+        let e = validate_db_name("2024_users").unwrap_err();
+        assert!(e.to_string().contains("found '2'"), "{}", e);
+    }
+
+    #[test]
+    fn sanitize_prefixes_empty_name() {
+        assert_eq!(sanitize_db_name(""), "db");
+        assert!(validate_db_name(&sanitize_db_name("")).is_ok());
+    }
+
+    #[test]
+    fn sanitize_truncates_too_long_name() {
+        let sanitized = sanitize_db_name(&"a".repeat(300));
+        assert_eq!(sanitized.len(), MAX_DB_NAME_LENGTH);
+        assert!(validate_db_name(&sanitized).is_ok());
+    }
+
+    #[test]
+    fn fitted_unique_name_shortens_long_names() {
+        let long = sanitize_db_name(&"A".repeat(300));
+        let mut rng = StdRng::seed_from_u64(1);
+        for strategy in [
+            &RandomSuffix as &dyn NameStrategy,
+            &UuidSuffix,
+            &TimestampSuffix,
+        ] {
+            let name = fitted_unique_name(strategy, &long, &mut rng);
+            assert!(validate_db_name(&name).is_ok(), "{}", name);
+            assert!(name.starts_with("aaaa"), "{}", name);
+        }
+    }
+
+    #[test]
+    fn fitted_unique_name_keeps_short_names() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let name = fitted_unique_name(&RandomSuffix, "users", &mut rng);
+        assert_eq!(name.len(), "users-".len() + SUFFIX_LENGTH);
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sanitized_long_name_creates_a_database() {
+        let cfg = crate::TestRepoConfig::new("", "", "", &"Users".repeat(60))
+            .named("naming::tests::sanitized_long_name_creates_a_database")
+            .with_mock()
+            .sanitized();
+        let repo = crate::TestRepo::new(cfg).await.unwrap();
+        assert!(percent_decode(repo.db.name()).len() <= MAX_DB_NAME_LENGTH);
+    }
+
+    #[test]
+    fn sanitize_lowercases_uppercase_only_name() {
+        assert_eq!(sanitize_db_name("USERS"), "users");
+    }
+
+    #[test]
+    fn sanitize_prefixes_leading_digit() {
+        assert_eq!(sanitize_db_name("2024 Users"), "db2024_users");
+    }
+
+    #[test]
+    fn sanitize_replaces_disallowed_characters() {
+        assert_eq!(sanitize_db_name("my.app:users"), "my_app_users");
+        assert_eq!(sanitize_db_name("_users"), "db_users");
+    }
+}
@@ -0,0 +1,204 @@
+use couch_rs::error::CouchError;
+use futures_util::{stream, Stream};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+use crate::{compat::Method, naming::encode_db_name, TestRepo};
+
+/// A page of documents returned by a Mango query, with the bookmark to request the following page.
+#[derive(Clone, Debug)]
+pub struct Page<T> {
+    /// The documents of this page.
+    pub docs: Vec<T>,
+    /// The bookmark CouchDB returned with this page, which requests the page following it.
+    pub bookmark: Option<String>,
+}
+
+/// A page of documents read from `_all_docs`, with the key to request the following page.
+#[derive(Clone, Debug)]
+pub struct KeyPage<T> {
+    /// The documents of this page, in document id order.
+    pub docs: Vec<T>,
+    /// The id of the first document of the following page, to pass as its start key, or `None` if this
+    /// is the last page.
+    pub next_key: Option<String>,
+}
+
+impl TestRepo {
+    /// Runs the Mango query `selector` against the database associated with this instance and streams
+    /// its results in pages of `page_size` documents, deserialized into `T`, following the bookmark
+    /// returned with each page the way paginating application code does. The stream ends after the
+    /// first page holding fewer than `page_size` documents; a query without results yields no page. In
+    /// a shared database, the query only matches documents of this instance's
+    /// [namespace](TestRepo::namespace).
+    ///
+    /// ```rust
+    /// # use futures_util::TryStreamExt;
+    /// # use serde_json::{json, Value};
+    /// # async fn example(repo: &couch_rs_test::TestRepo) -> Result<(), couch_rs::error::CouchError> {
+    /// let pages: Vec<_> = repo
+    ///     .find_paginated::<Value>(json!({"type": "order"}), 25)
+    ///     .try_collect()
+    ///     .await?;
+    /// assert!(pages.iter().all(|page| page.docs.len() <= 25));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn find_paginated<T: DeserializeOwned>(
+        &self,
+        selector: Value,
+        page_size: usize,
+    ) -> impl Stream<Item = Result<Page<T>, CouchError>> + '_ {
+        let page_size = page_size.max(1);
+        // the state is the bookmark to continue from, or None once the last page has been read
+        stream::try_unfold(Some(None), move |next: Option<Option<String>>| {
+            let selector = selector.clone();
+            async move {
+                let bookmark = match next {
+                    Some(bookmark) => bookmark,
+                    None => return Ok(None),
+                };
+                let page: Page<T> = self
+                    .find_page(selector, page_size, bookmark.as_deref())
+                    .await?;
+                if page.docs.is_empty() {
+                    return Ok(None);
+                }
+                // a server returning the bookmark it was given would repeat the page indefinitely
+                let next = match page.docs.len() < page_size || page.bookmark == bookmark {
+                    true => None,
+                    false => Some(page.bookmark.clone()),
+                };
+                Ok(Some((page, next)))
+            }
+        })
+    }
+
+    /// Requests a single page of up to `page_size` results of the Mango query `selector`, continuing
+    /// from `bookmark` if given. Use this to test how code handles a stale or invalid bookmark, which
+    /// CouchDB answers with either an error or an unrelated page.
+    pub async fn find_page<T: DeserializeOwned>(
+        &self,
+        selector: Value,
+        page_size: usize,
+        bookmark: Option<&str>,
+    ) -> Result<Page<T>, CouchError> {
+        let mut query = json!({
+            "selector": self.namespaced_selector(selector),
+            "limit": page_size.max(1),
+        });
+        if let Some(bookmark) = bookmark {
+            query["bookmark"] = Value::from(bookmark);
+        }
+
+        let mut found: Value = self
+            .client
+            .req(
+                Method::POST,
+                &format!("{}/_find", encode_db_name(&self.cfg.db_name)),
+                None,
+            )
+            .body(query.to_string())
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let docs = serde_json::from_value(found["docs"].take())?;
+        Ok(Page {
+            docs,
+            bookmark: found["bookmark"].as_str().map(str::to_string),
+        })
+    }
+
+    /// Reads up to `limit` documents of the database associated with this instance from `_all_docs`,
+    /// starting at the document id `start_key`, or at the first document, deserialized into `T`. This is
+    /// the range query behind typical list endpoints: one more row than `limit` is requested, and its id
+    /// is returned as the key of the following page. Design documents are skipped, so a page holding
+    /// one may come up short of `limit`. In a shared database, only the documents of this instance's
+    /// [namespace](TestRepo::namespace) are read.
+    ///
+    /// ```rust
+    /// # use serde_json::Value;
+    /// # async fn example(repo: &couch_rs_test::TestRepo) -> Result<(), couch_rs::error::CouchError> {
+    /// let first = repo.all_docs_paged::<Value>(10, None).await?;
+    /// if let Some(key) = &first.next_key {
+    ///     let second = repo.all_docs_paged::<Value>(10, Some(key)).await?;
+    ///     assert!(!second.docs.is_empty());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn all_docs_paged<T: DeserializeOwned>(
+        &self,
+        limit: usize,
+        start_key: Option<&str>,
+    ) -> Result<KeyPage<T>, CouchError> {
+        let limit = limit.max(1);
+        let mut query = vec![
+            ("include_docs".to_string(), "true".to_string()),
+            ("limit".to_string(), (limit + 1).to_string()),
+        ];
+        if let Some((start, end)) = self.namespace_range() {
+            let start = start_key
+                .map(|key| self.namespaced_id(key))
+                .unwrap_or(start);
+            query.push(("start_key".to_string(), serde_json::to_string(&start)?));
+            query.push(("end_key".to_string(), serde_json::to_string(&end)?));
+        } else if let Some(key) = start_key {
+            query.push(("start_key".to_string(), serde_json::to_string(key)?));
+        }
+
+        let page: Value = self
+            .client
+            .req(
+                Method::GET,
+                &format!("{}/_all_docs", encode_db_name(&self.cfg.db_name)),
+                None,
+            )
+            .query(&query)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let mut rows = page["rows"].as_array().cloned().unwrap_or_default();
+
+        let next_key = match rows.len() > limit {
+            true => rows
+                .pop()
+                .and_then(|row| row["id"].as_str().map(str::to_string)),
+            false => None,
+        };
+        let docs = rows
+            .into_iter()
+            .filter(|row| {
+                !row["id"]
+                    .as_str()
+                    .map(|id| id.starts_with("_design/"))
+                    .unwrap_or(false)
+            })
+            .filter_map(|mut row| row.get_mut("doc").map(Value::take))
+            .map(serde_json::from_value)
+            .collect::<Result<Vec<T>, _>>()?;
+        Ok(KeyPage { docs, next_key })
+    }
+
+    /// Streams all documents of the database associated with this instance in pages of `limit`
+    /// documents read with [TestRepo::all_docs_paged], following the key of each page to the next.
+    pub fn all_docs_pages<T: DeserializeOwned>(
+        &self,
+        limit: usize,
+    ) -> impl Stream<Item = Result<KeyPage<T>, CouchError>> + '_ {
+        // the state is the key to start the next page at, or None once the last page has been read
+        stream::try_unfold(Some(None), move |next: Option<Option<String>>| async move {
+            let start_key = match next {
+                Some(start_key) => start_key,
+                None => return Ok(None),
+            };
+            let page: KeyPage<T> = self.all_docs_paged(limit, start_key.as_deref()).await?;
+            let next = page.next_key.clone().map(Some);
+            Ok(Some((page, next)))
+        })
+    }
+}
@@ -0,0 +1,97 @@
+use std::{error::Error, fs, path::Path, process::Command};
+
+use crate::{SecretString, TestRepoConfig};
+
+impl TestRepoConfig {
+    /// Read the password from the file at `path` instead of taking it in plain text, for CI setups that
+    /// mount secrets as files, such as Docker or Kubernetes secrets. A trailing line break is removed.
+    /// The file is read right away; an unreadable or empty file is reported as an error.
+    ///
+    /// ```rust,no_run
+    /// use couch_rs_test::TestRepoConfig;
+    ///
+    /// # fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let cfg = TestRepoConfig::new("http://localhost:5984", "admin", "", "users")
+    ///     .with_password_file("/run/secrets/couchdb_password")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_password_file<P: AsRef<Path>>(
+        self,
+        path: P,
+    ) -> Result<TestRepoConfig, Box<dyn Error>> {
+        let password = read_password_file(path.as_ref())?;
+        Ok(self.with_password(password))
+    }
+
+    /// Take the password from the output of the shell command `command`, for example one querying a
+    /// secret manager or a password store, instead of taking it in plain text. The command is run right
+    /// away with `sh -c`, or `cmd /C` on Windows, and a trailing line break is removed from its output. A
+    /// command that fails or prints nothing is reported as an error; its output is never included in
+    /// the error.
+    ///
+    /// ```rust,no_run
+    /// use couch_rs_test::TestRepoConfig;
+    ///
+    /// # fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let cfg = TestRepoConfig::new("http://localhost:5984", "admin", "", "users")
+    ///     .with_password_command("pass show ci/couchdb")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_password_command(self, command: &str) -> Result<TestRepoConfig, Box<dyn Error>> {
+        let password = run_password_command(command)?;
+        Ok(self.with_password(password))
+    }
+
+    pub(crate) fn with_password(self, password: SecretString) -> TestRepoConfig {
+        TestRepoConfig { password, ..self }
+    }
+}
+
+/// Reads a password from the file at `path`.
+pub(crate) fn read_password_file(path: &Path) -> Result<SecretString, Box<dyn Error>> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read password file {}: {}", path.display(), e))?;
+    non_empty(content, || {
+        format!("Password file {} is empty", path.display())
+    })
+}
+
+/// Runs `command` in a shell and takes its output as a password.
+pub(crate) fn run_password_command(command: &str) -> Result<SecretString, Box<dyn Error>> {
+    #[cfg(windows)]
+    let output = Command::new("cmd").args(["/C", command]).output();
+    #[cfg(not(windows))]
+    let output = Command::new("sh").args(["-c", command]).output();
+    let output =
+        output.map_err(|e| format!("Failed to run password command {}: {}", command, e))?;
+    if !output.status.success() {
+        // the output may hold the secret or a prompt echoing it, so only its size is reported
+        return Err(format!(
+            "Password command {} failed with {}, printing {} bytes to stderr",
+            command,
+            output.status,
+            output.stderr.len()
+        )
+        .into());
+    }
+    let stdout = String::from_utf8(output.stdout)
+        .map_err(|_| format!("Password command {} printed invalid UTF-8", command))?;
+    non_empty(stdout, || {
+        format!("Password command {} printed nothing", command)
+    })
+}
+
+// `secret` without its trailing line break, or the error described by `empty` if nothing is left
+fn non_empty<F: FnOnce() -> String>(
+    mut secret: String,
+    empty: F,
+) -> Result<SecretString, Box<dyn Error>> {
+    let len = secret.trim_end_matches(['\r', '\n']).len();
+    secret.truncate(len);
+    match secret.is_empty() {
+        true => Err(empty().into()),
+        false => Ok(SecretString::from(secret)),
+    }
+}
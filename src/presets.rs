@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use couch_rs::error::CouchError;
+use futures_util::stream;
+use serde_json::{json, Value};
+
+use crate::TestRepo;
+
+pub(crate) type TemplateFn = Arc<dyn Fn(usize) -> Value + Send + Sync>;
+
+/// Built-in data set sizes for [TestRepo::seed_preset], so performance-sensitive tests can declare how
+/// much data they need rather than each bringing its own generator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Preset {
+    /// 100 documents.
+    Small,
+    /// 10,000 documents.
+    Medium,
+    /// 100,000 documents.
+    Large,
+    /// The given number of documents.
+    Custom(usize),
+}
+
+impl Preset {
+    /// Number of documents of the preset.
+    pub fn docs(&self) -> usize {
+        match self {
+            Preset::Small => 100,
+            Preset::Medium => 10_000,
+            Preset::Large => 100_000,
+            Preset::Custom(docs) => *docs,
+        }
+    }
+}
+
+impl TestRepo {
+    /// Seeds the database associated with this instance with the number of documents of `preset`,
+    /// generated lazily and pushed like [TestRepo::with_data_stream]. Documents are produced by the
+    /// template set with [TestRepoConfig::with_preset_template](crate::TestRepoConfig::with_preset_template),
+    /// or otherwise look like
+    ///
+    /// ```json
+    /// {"_id": "doc-000042", "n": 42, "name": "document 42", "group": "g2", "even": true}
+    /// ```
+    ///
+    /// Returns the number of documents pushed.
+    pub async fn seed_preset(&self, preset: Preset) -> Result<usize, CouchError> {
+        let template = self.cfg.preset_template.clone();
+        let docs = (0..preset.docs()).map(move |n| match &template {
+            Some(template) => template(n),
+            None => default_doc(n),
+        });
+        self.with_data_stream(stream::iter(docs)).await
+    }
+}
+
+fn default_doc(n: usize) -> Value {
+    json!({
+        "_id": format!("doc-{:06}", n),
+        "n": n,
+        "name": format!("document {}", n),
+        "group": format!("g{}", n % 10),
+        "even": n.is_multiple_of(2),
+    })
+}
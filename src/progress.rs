@@ -0,0 +1,37 @@
+use std::{fmt, sync::Arc};
+
+/// Progress of a seeding operation, reported after each `_bulk_docs` batch to the callback configured
+/// with [TestRepoConfig::with_progress](crate::TestRepoConfig::with_progress).
+///
+/// Its `Display` form reads like `45/120 batches (45000/120000 documents)`, or `45 batches (45000
+/// documents)` when the totals are not known in advance.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SeedProgress {
+    /// Number of batches written so far.
+    pub batches_done: usize,
+    /// Number of batches the operation is split into, unless the documents come from a stream.
+    pub batches_total: Option<usize>,
+    /// Number of documents written so far.
+    pub docs_done: usize,
+    /// Number of documents the operation writes, unless the documents come from a stream.
+    pub docs_total: Option<usize>,
+}
+
+impl fmt::Display for SeedProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.batches_total, self.docs_total) {
+            (Some(batches_total), Some(docs_total)) => write!(
+                f,
+                "{}/{} batches ({}/{} documents)",
+                self.batches_done, batches_total, self.docs_done, docs_total
+            ),
+            _ => write!(
+                f,
+                "{} batches ({} documents)",
+                self.batches_done, self.docs_done
+            ),
+        }
+    }
+}
+
+pub(crate) type ProgressFn = Arc<dyn Fn(&SeedProgress) + Send + Sync>;
@@ -0,0 +1,233 @@
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use http::{header::HeaderName, HeaderMap, HeaderValue, StatusCode};
+use hyper::body::Bytes;
+use serde::{Deserialize, Serialize};
+
+use super::{ProxyRequest, ProxyResponse, State};
+
+/// Placeholder stored in cassettes in place of the unique name of the test database.
+pub(crate) const DB_PLACEHOLDER: &str = "{{db}}";
+
+/// Environment variable overriding the mode chosen by [Cassette::auto]; `record` or `replay`.
+const MODE_ENV_VAR: &str = "COUCH_TEST_VCR";
+
+// response headers kept in a recording; the rest (dates, server, cache tags) only add noise
+const RECORDED_HEADERS: [&str; 3] = ["content-type", "etag", "location"];
+
+/// A file of recorded CouchDB HTTP interactions.
+///
+/// In [Cassette::Record] mode every request passing through the proxy is forwarded to CouchDB and the
+/// request and response are written to the file when the [TestRepo](crate::TestRepo) is dropped. In
+/// [Cassette::Replay] mode no request reaches CouchDB; each request is answered with the response recorded
+/// for the same method and path (preferring an identical body), each recorded interaction being used at
+/// most once. This lets a slow integration test double as a fast replayed test in CI, without a live
+/// server.
+///
+/// The unique name of the test database is stored as a placeholder, so a cassette can be replayed even
+/// though every run generates a new database name. Credentials are never written to the cassette.
+/// Bodies are stored as text, so binary attachments are not supported.
+#[derive(Clone, Debug)]
+pub enum Cassette {
+    /// Forward requests to CouchDB and record them into the file at this path, replacing its contents.
+    Record(PathBuf),
+    /// Answer requests from the recording in the file at this path without contacting CouchDB.
+    Replay(PathBuf),
+}
+
+impl Cassette {
+    /// Choose the mode for the cassette at `path`: replay if the file exists, otherwise record. The
+    /// `COUCH_TEST_VCR` environment variable forces a mode when set to `record` or `replay`, for example
+    /// to refresh recordings after the application's queries changed.
+    pub fn auto<P: AsRef<Path>>(path: P) -> Cassette {
+        let path = path.as_ref().to_path_buf();
+        match std::env::var(MODE_ENV_VAR).as_deref() {
+            Ok("record") => Cassette::Record(path),
+            Ok("replay") => Cassette::Replay(path),
+            _ if path.exists() => Cassette::Replay(path),
+            _ => Cassette::Record(path),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct CassetteFile {
+    interactions: Vec<Interaction>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct Interaction {
+    request: RecordedRequest,
+    response: RecordedResponse,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct RecordedRequest {
+    method: String,
+    path: String,
+    body: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct RecordedResponse {
+    status: u16,
+    headers: BTreeMap<String, String>,
+    body: String,
+}
+
+/// A loaded cassette, shared by all connections of a proxy.
+pub(crate) enum Tape {
+    Recording {
+        path: PathBuf,
+        interactions: Mutex<Vec<Interaction>>,
+    },
+    Replaying {
+        // each interaction is paired with whether it has been replayed already
+        interactions: Mutex<Vec<(bool, Interaction)>>,
+    },
+}
+
+impl Tape {
+    pub(crate) fn load(cassette: Cassette) -> Result<Tape, Box<dyn Error>> {
+        match cassette {
+            Cassette::Record(path) => Ok(Tape::Recording {
+                path,
+                interactions: Mutex::new(vec![]),
+            }),
+            Cassette::Replay(path) => {
+                let file: CassetteFile = serde_json::from_str(&fs::read_to_string(&path)?)?;
+                log::info!(
+                    "Replaying {} interactions from {}",
+                    file.interactions.len(),
+                    path.display()
+                );
+                Ok(Tape::Replaying {
+                    interactions: Mutex::new(
+                        file.interactions.into_iter().map(|i| (false, i)).collect(),
+                    ),
+                })
+            }
+        }
+    }
+
+    pub(crate) fn is_replaying(&self) -> bool {
+        matches!(self, Tape::Replaying { .. })
+    }
+
+    pub(crate) fn record(&self, state: &State, request: &ProxyRequest, response: &ProxyResponse) {
+        if let Tape::Recording { interactions, .. } = self {
+            let headers = response
+                .headers
+                .iter()
+                .filter(|(name, _)| RECORDED_HEADERS.contains(&name.as_str()))
+                .filter_map(|(name, value)| {
+                    Some((name.to_string(), state.normalize(value.to_str().ok()?)))
+                })
+                .collect();
+
+            interactions
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(Interaction {
+                    request: recorded_request(state, request),
+                    response: RecordedResponse {
+                        status: response.status.as_u16(),
+                        headers,
+                        body: state.normalize(&String::from_utf8_lossy(&response.body)),
+                    },
+                });
+        }
+    }
+
+    pub(crate) fn replay(&self, state: &State, request: &ProxyRequest) -> ProxyResponse {
+        let interactions = match self {
+            Tape::Replaying { interactions } => interactions,
+            Tape::Recording { .. } => {
+                return ProxyResponse::error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "cassette is recording",
+                )
+            }
+        };
+
+        let wanted = recorded_request(state, request);
+        let mut interactions = interactions.lock().unwrap_or_else(|e| e.into_inner());
+        let candidates: Vec<usize> = interactions
+            .iter()
+            .enumerate()
+            .filter(|(_, (used, i))| {
+                !*used && i.request.method == wanted.method && i.request.path == wanted.path
+            })
+            .map(|(n, _)| n)
+            .collect();
+        let found = candidates
+            .iter()
+            .copied()
+            .find(|&n| interactions[n].1.request.body == wanted.body)
+            .or_else(|| candidates.first().copied())
+            .map(|n| &mut interactions[n]);
+
+        match found {
+            Some((used, interaction)) => {
+                *used = true;
+                let mut headers = HeaderMap::new();
+                for (name, value) in interaction.response.headers.iter() {
+                    if let (Ok(name), Ok(value)) = (
+                        HeaderName::try_from(name.as_str()),
+                        HeaderValue::try_from(state.denormalize(value)),
+                    ) {
+                        headers.insert(name, value);
+                    }
+                }
+                ProxyResponse {
+                    status: StatusCode::from_u16(interaction.response.status)
+                        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+                    headers,
+                    body: Bytes::from(state.denormalize(&interaction.response.body)),
+                }
+            }
+            None => ProxyResponse::error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!(
+                    "no recorded interaction left for {} {}",
+                    wanted.method, wanted.path
+                ),
+            ),
+        }
+    }
+
+    pub(crate) fn save(&self) -> Result<(), Box<dyn Error>> {
+        if let Tape::Recording { path, interactions } = self {
+            let file = CassetteFile {
+                interactions: interactions
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .clone(),
+            };
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, serde_json::to_string_pretty(&file)?)?;
+            log::info!(
+                "Recorded {} interactions to {}",
+                file.interactions.len(),
+                path.display()
+            );
+        }
+        Ok(())
+    }
+}
+
+fn recorded_request(state: &State, request: &ProxyRequest) -> RecordedRequest {
+    RecordedRequest {
+        method: request.method.to_string(),
+        path: state.normalize(&request.path),
+        body: state.normalize(&String::from_utf8_lossy(&request.body)),
+    }
+}
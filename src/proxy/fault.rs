@@ -0,0 +1,194 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use http::StatusCode;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::naming::{fnv1a, resolve_seed};
+
+/// A failure the proxy can inject into a request.
+#[derive(Clone, Debug)]
+pub enum Fault {
+    /// Delay the request by the given duration before handling it normally.
+    Latency(Duration),
+    /// Answer the request with the given status, such as 500 or 503, without forwarding it to CouchDB.
+    Status(StatusCode),
+    /// Close the connection without sending a response.
+    ConnectionReset,
+    /// Forward the request, but send only the first half of the response body while announcing its full
+    /// length.
+    TruncatedBody,
+}
+
+/// A set of [Fault]s, each injected into a configurable fraction of the requests passing through the
+/// proxy, so that application retry and backoff logic can be tested against a misbehaving server.
+///
+/// For each request, the faults are considered in the order they were added and an independent
+/// random draw decides whether each applies. Any number of [Fault::Latency] faults may apply to the
+/// same request; the first other fault that applies determines the outcome of the request.
+///
+/// The random draws are seeded, so that a chaotic failing run can be replayed exactly: the draw for a
+/// request depends only on the seed, the request's method and path (with the unique database name
+/// abstracted away) and how many identical requests preceded it, not on the order in which concurrent
+/// requests arrive. The seed is taken from [FaultInjection::with_seed], else from the `COUCH_TEST_SEED`
+/// environment variable, else chosen at random and logged when the proxy starts.
+///
+/// Faults are only injected into requests made after [TestRepo::new](crate::TestRepo::new) has set up
+/// the database, and not into the requests destroying it, so that the fixture itself stays reliable.
+#[derive(Clone, Debug, Default)]
+pub struct FaultInjection {
+    faults: Vec<(Fault, f64)>,
+    seed: Option<u64>,
+}
+
+impl FaultInjection {
+    /// Create a fault injection configuration which injects no faults.
+    pub fn new() -> FaultInjection {
+        FaultInjection::default()
+    }
+
+    /// Inject `fault` into the given fraction of requests, between 0.0 (never) and 1.0 (always). A
+    /// probability that is not finite, such as NaN, never injects the fault.
+    pub fn with_fault(mut self, fault: Fault, probability: f64) -> FaultInjection {
+        let probability = match probability.is_finite() {
+            true => probability.clamp(0.0, 1.0),
+            false => 0.0,
+        };
+        self.faults.push((fault, probability));
+        self
+    }
+
+    /// Seed the random draws deciding which requests receive a fault, to replay the faults of an earlier
+    /// run.
+    pub fn with_seed(self, seed: u64) -> FaultInjection {
+        FaultInjection {
+            seed: Some(seed),
+            ..self
+        }
+    }
+}
+
+/// The state of fault injection in a running proxy.
+pub(crate) struct Chaos {
+    faults: Vec<(Fault, f64)>,
+    seed: u64,
+    // number of requests seen so far for each method and path
+    counts: Mutex<HashMap<String, u64>>,
+}
+
+impl Chaos {
+    pub(crate) fn new(config: FaultInjection) -> Chaos {
+        let seed = resolve_seed(config.seed).unwrap_or_else(|| rand::thread_rng().gen());
+        log::info!("Injecting faults with seed {}", seed);
+
+        Chaos {
+            faults: config.faults,
+            seed,
+            counts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Draws the faults applying to the request identified by `key`: the total injected latency and the
+    /// fault deciding the outcome, if any.
+    pub(crate) fn draw(&self, key: &str) -> (Duration, Option<Fault>) {
+        let nth = {
+            let mut counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+            let count = counts.entry(key.to_string()).or_insert(0);
+            *count += 1;
+            *count
+        };
+        let mut rng = StdRng::seed_from_u64(self.seed ^ fnv1a(key).wrapping_add(nth));
+
+        let mut latency = Duration::ZERO;
+        let mut outcome = None;
+
+        for (fault, probability) in self.faults.iter() {
+            if !rng.gen_bool(*probability) {
+                continue;
+            }
+            match fault {
+                Fault::Latency(delay) => latency += *delay,
+                other if outcome.is_none() => outcome = Some(other.clone()),
+                _ => {}
+            }
+        }
+
+        (latency, outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chaos(seed: u64) -> Chaos {
+        Chaos::new(
+            FaultInjection::new()
+                .with_fault(Fault::Latency(Duration::from_millis(10)), 0.5)
+                .with_fault(Fault::Status(StatusCode::SERVICE_UNAVAILABLE), 0.3)
+                .with_fault(Fault::ConnectionReset, 0.2)
+                .with_seed(seed),
+        )
+    }
+
+    // the outcomes of `n` requests for each of a few keys, interleaved
+    fn draws(chaos: &Chaos, n: usize) -> Vec<String> {
+        let keys = ["GET /{db}/alice", "PUT /{db}/bob", "POST /{db}/_find"];
+        (0..n)
+            .flat_map(|_| keys.iter())
+            .map(|key| format!("{:?}", chaos.draw(key)))
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_draws_same_faults() {
+        assert_eq!(draws(&chaos(42), 50), draws(&chaos(42), 50));
+    }
+
+    #[test]
+    fn different_seeds_draw_different_faults() {
+        assert_ne!(draws(&chaos(42), 50), draws(&chaos(43), 50));
+    }
+
+    #[test]
+    fn draws_do_not_depend_on_the_order_of_other_requests() {
+        let interleaved = chaos(42);
+        let alone = chaos(42);
+        let mut of_alice = vec![];
+        for _ in 0..20 {
+            interleaved.draw("PUT /{db}/bob");
+            of_alice.push(format!("{:?}", interleaved.draw("GET /{db}/alice")));
+        }
+        let expected: Vec<String> = (0..20)
+            .map(|_| format!("{:?}", alone.draw("GET /{db}/alice")))
+            .collect();
+        assert_eq!(of_alice, expected);
+    }
+
+    #[test]
+    fn certain_fault_always_applies() {
+        let chaos = Chaos::new(
+            FaultInjection::new()
+                .with_fault(Fault::Latency(Duration::from_millis(5)), 1.0)
+                .with_fault(Fault::Latency(Duration::from_millis(7)), 1.0)
+                .with_fault(Fault::TruncatedBody, 1.0)
+                .with_fault(Fault::ConnectionReset, 1.0),
+        );
+        for _ in 0..10 {
+            let (latency, outcome) = chaos.draw("GET /{db}/alice");
+            assert_eq!(latency, Duration::from_millis(12));
+            assert!(matches!(outcome, Some(Fault::TruncatedBody)));
+        }
+    }
+
+    #[test]
+    fn non_finite_fault_never_applies() {
+        let chaos = Chaos::new(
+            FaultInjection::new()
+                .with_fault(Fault::TruncatedBody, f64::NAN)
+                .with_fault(Fault::ConnectionReset, f64::INFINITY),
+        );
+        for _ in 0..10 {
+            assert!(chaos.draw("GET /{db}/alice").1.is_none());
+        }
+    }
+}
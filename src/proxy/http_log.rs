@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+use http::{Method, StatusCode};
+use hyper::body::Bytes;
+use serde_json::Value;
+
+use crate::secret;
+
+// the number of characters of a body included in the log, beyond which it is truncated
+const MAX_LOGGED_BODY: usize = 500;
+
+/// Logs a request made with `method` to `path` at debug level.
+pub(crate) fn log_request(method: &Method, path: &str, body: &Bytes) {
+    log::debug!(
+        "--> {} {}{}",
+        method,
+        secret::redact(path),
+        describe_body(body)
+    );
+}
+
+/// Logs the response to a request made with `method` to `path`, received after `elapsed`, at debug
+/// level.
+pub(crate) fn log_response(
+    method: &Method,
+    path: &str,
+    status: StatusCode,
+    elapsed: Duration,
+    body: &Bytes,
+) {
+    log::debug!(
+        "<-- {} {} {} ({} ms){}",
+        status.as_u16(),
+        method,
+        secret::redact(path),
+        elapsed.as_millis(),
+        describe_body(body)
+    );
+}
+
+// the body as logged: credentials redacted, truncated, and preceded by a separator unless it is empty
+fn describe_body(body: &Bytes) -> String {
+    if body.is_empty() {
+        return String::new();
+    }
+    let text = match serde_json::from_slice::<Value>(body) {
+        Ok(mut json) => {
+            secret::redact_json(&mut json);
+            json.to_string()
+        }
+        Err(_) => String::from_utf8_lossy(body).into_owned(),
+    };
+    let text = secret::redact(&text);
+
+    match text.char_indices().nth(MAX_LOGGED_BODY) {
+        Some((end, _)) => format!(": {}... ({} bytes in total)", &text[..end], body.len()),
+        None => format!(": {}", text.trim_end()),
+    }
+}
@@ -0,0 +1,162 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use http::Method;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::stats::endpoint;
+use crate::naming::{fnv1a, resolve_seed};
+
+/// An artificial delay the proxy adds to a request before handling it.
+#[derive(Clone, Debug)]
+pub enum Delay {
+    /// Delay every request by the same duration.
+    Fixed(Duration),
+    /// Delay each request by a duration drawn uniformly between `min` and `max`.
+    Uniform {
+        /// The shortest delay.
+        min: Duration,
+        /// The longest delay.
+        max: Duration,
+    },
+    /// Delay each request by a duration drawn from an exponential distribution with the given mean, so
+    /// that most requests are fast and a few are much slower, like a database under load.
+    Exponential {
+        /// The average delay.
+        mean: Duration,
+    },
+}
+
+impl Delay {
+    fn sample(&self, rng: &mut StdRng) -> Duration {
+        match self {
+            Delay::Fixed(delay) => *delay,
+            Delay::Uniform { min, max } if min < max => rng.gen_range(*min..=*max),
+            Delay::Uniform { min, .. } => *min,
+            Delay::Exponential { mean } => {
+                // inverse transform sampling; 1 - u lies in (0, 1], so the logarithm is finite
+                let u: f64 = rng.gen();
+                mean.mul_f64(-(1.0 - u).ln())
+            }
+        }
+    }
+}
+
+/// Artificial delays added to the requests passing through the proxy, so that timeouts, progress
+/// indicators and other behavior under a slow database can be tested without changing application
+/// code.
+///
+/// Delays are configured per operation, identified by method and endpoint the way
+/// [RequestStats](super::RequestStats) names them: `""` for the database itself, `"_find"`,
+/// `"_bulk_docs"` or `"_design/app/_view/by_name"` for special endpoints, and `"{doc}"` for any regular
+/// document. The first matching operation decides the delay of a request; requests matching none are
+/// delayed by the default delay, if one is set.
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use couch_rs_test::proxy::{Delay, Latency, ProxyConfig};
+/// use http::Method;
+///
+/// let proxy = ProxyConfig::new().with_latency(
+///     Latency::new()
+///         .with_operation_delay(Method::POST, "_find", Delay::Fixed(Duration::from_secs(2)))
+///         .with_default_delay(Delay::Uniform {
+///             min: Duration::from_millis(10),
+///             max: Duration::from_millis(50),
+///         }),
+/// );
+/// ```
+///
+/// Random delays are seeded like [FaultInjection](super::FaultInjection): with
+/// [Latency::with_seed], else the `COUCH_TEST_SEED` environment variable, else at random. As with
+/// faults, delays only apply to requests made after the test database has been set up.
+#[derive(Clone, Debug, Default)]
+pub struct Latency {
+    operations: Vec<(Option<Method>, String, Delay)>,
+    default: Option<Delay>,
+    seed: Option<u64>,
+}
+
+impl Latency {
+    /// Create a latency configuration which delays no request.
+    pub fn new() -> Latency {
+        Latency::default()
+    }
+
+    /// Delay requests made with `method` to `endpoint` by `delay`.
+    pub fn with_operation_delay(mut self, method: Method, endpoint: &str, delay: Delay) -> Latency {
+        self.operations
+            .push((Some(method), endpoint.to_string(), delay));
+        self
+    }
+
+    /// Delay requests made with any method to `endpoint` by `delay`.
+    pub fn with_endpoint_delay(mut self, endpoint: &str, delay: Delay) -> Latency {
+        self.operations.push((None, endpoint.to_string(), delay));
+        self
+    }
+
+    /// Delay requests matching no configured operation by `delay`.
+    pub fn with_default_delay(self, delay: Delay) -> Latency {
+        Latency {
+            default: Some(delay),
+            ..self
+        }
+    }
+
+    /// Seed the random draws of the delays, to replay the delays of an earlier run.
+    pub fn with_seed(self, seed: u64) -> Latency {
+        Latency {
+            seed: Some(seed),
+            ..self
+        }
+    }
+}
+
+/// The state of latency injection in a running proxy.
+pub(crate) struct Slowdown {
+    config: Latency,
+    seed: u64,
+    // number of requests seen so far for each method and path
+    counts: Mutex<HashMap<String, u64>>,
+}
+
+impl Slowdown {
+    pub(crate) fn new(config: Latency) -> Slowdown {
+        let seed = resolve_seed(config.seed).unwrap_or_else(|| rand::thread_rng().gen());
+        log::info!("Injecting latency with seed {}", seed);
+
+        Slowdown {
+            config,
+            seed,
+            counts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Draws the delay of a request made with `method` to `path`, in which the test database name has
+    /// been normalized.
+    pub(crate) fn draw(&self, method: &Method, path: &str) -> Duration {
+        let endpoint = endpoint(path);
+        let delay = self
+            .config
+            .operations
+            .iter()
+            .find(|(m, e, _)| (m.is_none() || m.as_ref() == Some(method)) && *e == endpoint)
+            .map(|(_, _, delay)| delay)
+            .or(self.config.default.as_ref());
+        let delay = match delay {
+            Some(delay) => delay,
+            None => return Duration::ZERO,
+        };
+
+        let key = format!("{} {}", method, path);
+        let nth = {
+            let mut counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+            let count = counts.entry(key.clone()).or_insert(0);
+            *count += 1;
+            *count
+        };
+        let mut rng = StdRng::seed_from_u64(self.seed ^ fnv1a(&key).wrapping_add(nth));
+        delay.sample(&mut rng)
+    }
+}
@@ -0,0 +1,586 @@
+//! A local HTTP proxy placed between a [TestRepo](crate::TestRepo) and CouchDB.
+//!
+//! When a [ProxyConfig] is set with [TestRepoConfig::with_proxy](crate::TestRepoConfig::with_proxy), the
+//! test repository starts a proxy on a free port of the loopback interface and connects its
+//! [couch_rs::Client](https://docs.rs/couch_rs/latest/couch_rs/struct.Client.html) to the proxy instead
+//! of the configured uri. Every request made through [TestRepo::db](crate::TestRepo#structfield.db),
+//! including the creation and destruction of the test database, then passes through the proxy, which
+//! can record it to or replay it from a [Cassette], inject faults into it to test application
+//! resilience with [FaultInjection] and [RateLimit], or slow it down with [Latency]. The proxy also
+//! counts the requests made during a test, see
+//! [TestRepo::request_stats](crate::TestRepo::request_stats), and records the documents they write, see
+//! [TestRepo::written_ids](crate::TestRepo::written_ids).
+//!
+//! This module is only available with the `proxy` feature.
+
+mod cassette;
+mod fault;
+mod http_log;
+mod latency;
+mod rate_limit;
+mod spy;
+mod stats;
+
+use std::{
+    collections::BTreeSet,
+    convert::Infallible,
+    error::Error,
+    net::{Ipv4Addr, SocketAddr, TcpListener},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use http::{header, HeaderMap, Method, Request, Response, StatusCode};
+use hyper::{
+    body::Bytes,
+    service::{make_service_fn, service_fn},
+    Body, Server,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{naming::encode_db_name, secret, TestRepo};
+
+pub use cassette::Cassette;
+pub use fault::{Fault, FaultInjection};
+pub use latency::{Delay, Latency};
+pub use rate_limit::RateLimit;
+pub use stats::RequestStats;
+
+// headers describing the connection or encoding between two hops, which the proxy must not copy
+const HOP_HEADERS: [header::HeaderName; 5] = [
+    header::HOST,
+    header::CONNECTION,
+    header::CONTENT_LENGTH,
+    header::TRANSFER_ENCODING,
+    header::ACCEPT_ENCODING,
+];
+
+/// Configuration of the proxy started by a [TestRepo](crate::TestRepo).
+#[derive(Clone, Debug, Default)]
+pub struct ProxyConfig {
+    cassette: Option<Cassette>,
+    faults: Option<FaultInjection>,
+    latency: Option<Latency>,
+    rate_limit: Option<RateLimit>,
+    http_log: bool,
+}
+
+impl ProxyConfig {
+    /// Create a configuration for a proxy that forwards every request to CouchDB unchanged.
+    pub fn new() -> ProxyConfig {
+        ProxyConfig::default()
+    }
+
+    /// Record all HTTP interactions with CouchDB into, or replay them from, the given [Cassette].
+    pub fn with_cassette(self, cassette: Cassette) -> ProxyConfig {
+        ProxyConfig {
+            cassette: Some(cassette),
+            ..self
+        }
+    }
+
+    /// Inject the faults configured in `faults` into requests passing through the proxy.
+    pub fn with_faults(self, faults: FaultInjection) -> ProxyConfig {
+        ProxyConfig {
+            faults: Some(faults),
+            ..self
+        }
+    }
+
+    /// Delay requests passing through the proxy as configured in `latency`.
+    pub fn with_latency(self, latency: Latency) -> ProxyConfig {
+        ProxyConfig {
+            latency: Some(latency),
+            ..self
+        }
+    }
+
+    /// Answer requests exceeding `rate_limit` with `429 Too Many Requests`.
+    pub fn with_rate_limit(self, rate_limit: RateLimit) -> ProxyConfig {
+        ProxyConfig {
+            rate_limit: Some(rate_limit),
+            ..self
+        }
+    }
+
+    /// Log every request and response at debug level, as configured by
+    /// [TestRepoConfig::with_http_log](crate::TestRepoConfig::with_http_log).
+    pub(crate) fn with_http_log(self) -> ProxyConfig {
+        ProxyConfig {
+            http_log: true,
+            ..self
+        }
+    }
+}
+
+impl TestRepo {
+    /// Returns the number of HTTP requests made to CouchDB through this instance's proxy, by method and
+    /// endpoint, so that tests can assert for example that a code path issued exactly one `_bulk_docs`
+    /// call instead of a request per document. Requests made while setting up the test database are not
+    /// counted.
+    ///
+    /// Requests are only counted when a proxy was configured with
+    /// [TestRepoConfig::with_proxy](crate::TestRepoConfig::with_proxy); otherwise the returned stats are
+    /// empty.
+    pub fn request_stats(&self) -> RequestStats {
+        self.proxy.as_ref().map(Proxy::stats).unwrap_or_default()
+    }
+
+    /// Discards the requests counted so far by [TestRepo::request_stats], typically after seeding the
+    /// database and before exercising the code under test.
+    pub fn reset_request_stats(&self) {
+        if let Some(proxy) = &self.proxy {
+            proxy.reset_stats();
+        }
+    }
+
+    /// Returns the ids of the documents written to the database associated with this instance through
+    /// its proxy, sorted and without duplicates, so that tests can assert that the code under test
+    /// touched exactly the documents it should have. Creations, updates and deletions of documents,
+    /// design documents and attachments count, whether made one by one or with `_bulk_docs`, as do
+    /// purges; failed writes and writes made while setting up the test database do not.
+    ///
+    /// ```rust
+    /// # async fn example(repo: &couch_rs_test::TestRepo) {
+    /// // seeded documents are written through the proxy as well
+    /// repo.reset_written_ids();
+    /// // ... exercise the code under test ...
+    /// assert_eq!(repo.written_ids(), ["order-17", "stock-apples"]);
+    /// # }
+    /// ```
+    ///
+    /// Writes are only recorded when a proxy was configured with
+    /// [TestRepoConfig::with_proxy](crate::TestRepoConfig::with_proxy); otherwise no ids are returned.
+    pub fn written_ids(&self) -> Vec<String> {
+        self.proxy
+            .as_ref()
+            .map(Proxy::written_ids)
+            .unwrap_or_default()
+    }
+
+    /// Discards the writes recorded so far by [TestRepo::written_ids], typically after seeding the
+    /// database and before exercising the code under test.
+    pub fn reset_written_ids(&self) {
+        if let Some(proxy) = &self.proxy {
+            proxy.reset_written_ids();
+        }
+    }
+}
+
+/// A request received by the proxy, with its body fully read.
+pub(crate) struct ProxyRequest {
+    pub method: Method,
+    pub path: String,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+/// A response returned by the proxy, with its body fully read.
+pub(crate) struct ProxyResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl ProxyResponse {
+    /// A JSON error response in the format CouchDB uses, for failures inside the proxy itself.
+    pub(crate) fn error(status: StatusCode, reason: &str) -> ProxyResponse {
+        ProxyResponse::couch_error(status, "proxy_error", reason)
+    }
+
+    /// A JSON error response with the given CouchDB error name.
+    pub(crate) fn couch_error(status: StatusCode, error: &str, reason: &str) -> ProxyResponse {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/json"),
+        );
+        ProxyResponse {
+            status,
+            headers,
+            body: Bytes::from(serde_json::json!({"error": error, "reason": reason}).to_string()),
+        }
+    }
+}
+
+struct State {
+    upstream: String,
+    client: reqwest::Client,
+    db_name: Mutex<Option<String>>,
+    tape: Option<cassette::Tape>,
+    chaos: Option<fault::Chaos>,
+    slowdown: Option<latency::Slowdown>,
+    bucket: Option<rate_limit::Bucket>,
+    armed: AtomicBool,
+    stats: Mutex<RequestStats>,
+    writes: Mutex<BTreeSet<String>>,
+    http_log: bool,
+}
+
+impl State {
+    // replaces the unique database name with a placeholder, so recordings are independent of the name
+    // generated for a particular run
+    fn normalize(&self, text: &str) -> String {
+        match self
+            .db_name
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_deref()
+        {
+            Some(name) => text
+                .replace(&encode_db_name(name), cassette::DB_PLACEHOLDER)
+                .replace(name, cassette::DB_PLACEHOLDER),
+            None => text.to_string(),
+        }
+    }
+
+    fn denormalize(&self, text: &str) -> String {
+        match self
+            .db_name
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_deref()
+        {
+            Some(name) => text.replace(cassette::DB_PLACEHOLDER, name),
+            None => text.to_string(),
+        }
+    }
+}
+
+/// A running proxy; it is shut down, and any recording is written, when this is dropped.
+pub(crate) struct Proxy {
+    uri: String,
+    state: Arc<State>,
+    shutdown: CancellationToken,
+}
+
+impl Proxy {
+    /// Starts a proxy forwarding to `upstream` on a free port of the loopback interface.
+    pub(crate) async fn start(
+        config: ProxyConfig,
+        upstream: &str,
+    ) -> Result<Proxy, Box<dyn Error>> {
+        let tape = match config.cassette {
+            Some(cassette) => Some(cassette::Tape::load(cassette)?),
+            None => None,
+        };
+
+        let state = Arc::new(State {
+            upstream: upstream.trim_end_matches('/').to_string(),
+            client: reqwest::Client::builder().build()?,
+            db_name: Mutex::new(None),
+            tape,
+            chaos: config.faults.map(fault::Chaos::new),
+            slowdown: config.latency.map(latency::Slowdown::new),
+            bucket: config.rate_limit.map(rate_limit::Bucket::new),
+            armed: AtomicBool::new(false),
+            stats: Mutex::new(RequestStats::default()),
+            writes: Mutex::new(BTreeSet::new()),
+            http_log: config.http_log,
+        });
+
+        let listener = TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)))?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+
+        let service_state = state.clone();
+        let server = Server::from_tcp(listener)?.serve(make_service_fn(move |_| {
+            let state = service_state.clone();
+            async move { Ok::<_, Infallible>(service_fn(move |req| handle(state.clone(), req))) }
+        }));
+
+        let shutdown = CancellationToken::new();
+        let cancelled = shutdown.clone();
+        tokio::spawn(async move {
+            if let Err(e) = server
+                .with_graceful_shutdown(cancelled.cancelled_owned())
+                .await
+            {
+                log::error!("Test proxy on {} failed: {}", addr, e);
+            }
+        });
+
+        log::info!("Started test proxy on {} for {}", addr, secret::redact(upstream));
+
+        Ok(Proxy {
+            uri: format!("http://{}", addr),
+            state,
+            shutdown,
+        })
+    }
+
+    /// The uri clients should connect to instead of CouchDB.
+    pub(crate) fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    /// The CouchDB uri requests are forwarded to, or `None` when replaying a cassette, in which case no
+    /// server is involved.
+    pub(crate) fn upstream(&self) -> Option<&str> {
+        match &self.state.tape {
+            Some(tape) if tape.is_replaying() => None,
+            _ => Some(&self.state.upstream),
+        }
+    }
+
+    /// Turns fault injection, latency and rate limiting on or off; the test repository only enables them around
+    /// the test itself.
+    pub(crate) fn set_armed(&self, enabled: bool) {
+        self.state.armed.store(enabled, Ordering::SeqCst);
+    }
+
+    /// The requests counted since the test database was set up.
+    pub(crate) fn stats(&self) -> RequestStats {
+        self.state
+            .stats
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Discards the requests counted so far.
+    pub(crate) fn reset_stats(&self) {
+        *self.state.stats.lock().unwrap_or_else(|e| e.into_inner()) = RequestStats::default();
+    }
+
+    /// The ids of the documents written since the test database was set up, in id order.
+    pub(crate) fn written_ids(&self) -> Vec<String> {
+        self.state
+            .writes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Discards the written documents recorded so far.
+    pub(crate) fn reset_written_ids(&self) {
+        self.state
+            .writes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+
+    /// Tells the proxy the unique name of the test database, which recordings abstract over.
+    pub(crate) fn set_db_name(&self, name: &str) {
+        *self.state.db_name.lock().unwrap_or_else(|e| e.into_inner()) = Some(name.to_string());
+    }
+}
+
+impl Drop for Proxy {
+    fn drop(&mut self) {
+        self.shutdown.cancel();
+
+        if let Some(tape) = &self.state.tape {
+            if let Err(e) = tape.save() {
+                log::error!("Failed to write cassette: {}", e);
+            }
+        }
+    }
+}
+
+/// How the proxy answers a request.
+enum Outcome {
+    Respond(ProxyResponse),
+    Truncate(ProxyResponse),
+    Reset,
+}
+
+async fn handle(
+    state: Arc<State>,
+    req: Request<Body>,
+) -> Result<Response<Body>, Box<dyn Error + Send + Sync>> {
+    let (parts, body) = req.into_parts();
+    let outcome = match hyper::body::to_bytes(body).await {
+        Ok(body) => {
+            let request = ProxyRequest {
+                method: parts.method,
+                path: parts
+                    .uri
+                    .path_and_query()
+                    .map(|p| p.as_str().to_string())
+                    .unwrap_or_else(|| "/".to_string()),
+                headers: parts.headers,
+                body,
+            };
+            if !state.http_log {
+                process(&state, request).await
+            } else {
+                let (method, path) = (request.method.clone(), request.path.clone());
+                http_log::log_request(&method, &path, &request.body);
+                let started = Instant::now();
+                let outcome = process(&state, request).await;
+                match &outcome {
+                    Outcome::Respond(response) | Outcome::Truncate(response) => {
+                        http_log::log_response(
+                            &method,
+                            &path,
+                            response.status,
+                            started.elapsed(),
+                            &response.body,
+                        )
+                    }
+                    Outcome::Reset => log::debug!("<-- connection reset {} {}", method, path),
+                }
+                outcome
+            }
+        }
+        Err(e) => Outcome::Respond(ProxyResponse::error(
+            StatusCode::BAD_REQUEST,
+            &e.to_string(),
+        )),
+    };
+
+    let (response, truncate) = match outcome {
+        Outcome::Respond(response) => (response, false),
+        Outcome::Truncate(response) => (response, true),
+        // hyper closes the connection without a response when the service fails
+        Outcome::Reset => return Err("injected connection reset".into()),
+    };
+
+    let mut builder = Response::builder().status(response.status);
+    for (name, value) in response.headers.iter() {
+        builder = builder.header(name, value);
+    }
+
+    if !truncate {
+        return Ok(builder.body(Body::from(response.body))?);
+    }
+
+    // announce the full length but stream only half of the body; a body of unknown size is needed so
+    // that hyper accepts the announced length
+    builder = builder.header(header::CONTENT_LENGTH, response.body.len());
+    let (mut sender, body) = Body::channel();
+    let half = response.body.slice(..response.body.len() / 2);
+    tokio::spawn(async move {
+        let _ = sender.send_data(half).await;
+        sender.abort();
+    });
+    Ok(builder.body(body)?)
+}
+
+async fn process(state: &State, request: ProxyRequest) -> Outcome {
+    if state.armed.load(Ordering::SeqCst) {
+        state
+            .stats
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .record(&request.method, &state.normalize(&request.path));
+    }
+
+    if let Some(bucket) = state
+        .bucket
+        .as_ref()
+        .filter(|_| state.armed.load(Ordering::SeqCst))
+    {
+        if let Err(wait) = bucket.try_acquire() {
+            let mut response = ProxyResponse::couch_error(
+                StatusCode::TOO_MANY_REQUESTS,
+                "too_many_requests",
+                "You've exceeded your rate limit allowance. Please try again later.",
+            );
+            // Retry-After is given in whole seconds, rounded up
+            let retry_after = wait.as_secs().saturating_add(u64::from(wait.subsec_nanos() > 0));
+            response.headers.insert(
+                header::RETRY_AFTER,
+                header::HeaderValue::from(retry_after.max(1)),
+            );
+            return Outcome::Respond(response);
+        }
+    }
+
+    if let Some(slowdown) = state
+        .slowdown
+        .as_ref()
+        .filter(|_| state.armed.load(Ordering::SeqCst))
+    {
+        let delay = slowdown.draw(&request.method, &state.normalize(&request.path));
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    let mut truncate = false;
+    if let Some(chaos) = state
+        .chaos
+        .as_ref()
+        .filter(|_| state.armed.load(Ordering::SeqCst))
+    {
+        let (latency, fault) = chaos.draw(&format!(
+            "{} {}",
+            request.method,
+            state.normalize(&request.path)
+        ));
+        if !latency.is_zero() {
+            tokio::time::sleep(latency).await;
+        }
+        match fault {
+            Some(Fault::Status(status)) => {
+                return Outcome::Respond(ProxyResponse::error(status, "injected fault"))
+            }
+            Some(Fault::ConnectionReset) => return Outcome::Reset,
+            Some(Fault::TruncatedBody) => truncate = true,
+            Some(Fault::Latency(_)) | None => {}
+        }
+    }
+
+    let response = match &state.tape {
+        Some(tape) if tape.is_replaying() => tape.replay(state, &request),
+        Some(tape) => {
+            let response = forward(state, &request).await;
+            tape.record(state, &request, &response);
+            response
+        }
+        None => forward(state, &request).await,
+    };
+
+    if state.armed.load(Ordering::SeqCst) {
+        let written = spy::written_ids(&request, &state.normalize(&request.path), &response);
+        state
+            .writes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .extend(written);
+    }
+
+    if truncate {
+        Outcome::Truncate(response)
+    } else {
+        Outcome::Respond(response)
+    }
+}
+
+async fn forward(state: &State, request: &ProxyRequest) -> ProxyResponse {
+    let url = format!("{}{}", state.upstream, request.path);
+    let mut builder = state.client.request(request.method.clone(), &url);
+    for (name, value) in request.headers.iter() {
+        if !HOP_HEADERS.contains(name) {
+            builder = builder.header(name, value);
+        }
+    }
+
+    let result = async {
+        let response = builder.body(request.body.clone()).send().await?;
+        let status = response.status();
+        let mut headers = response.headers().clone();
+        for name in HOP_HEADERS.iter().chain([&header::CONTENT_ENCODING]) {
+            headers.remove(name);
+        }
+        let body = response.bytes().await?;
+        Ok::<_, reqwest::Error>(ProxyResponse {
+            status,
+            headers,
+            body,
+        })
+    };
+
+    match result.await {
+        Ok(response) => response,
+        Err(e) => ProxyResponse::error(StatusCode::BAD_GATEWAY, &secret::redact(&e.to_string())),
+    }
+}
@@ -0,0 +1,143 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// A request rate above which the proxy answers with `429 Too Many Requests` and a `Retry-After`
+/// header, resembling the behavior of hosted CouchDB services such as Cloudant.
+///
+/// The limit is enforced as a token bucket: up to `burst` requests may be made at once, after which
+/// requests are admitted at `requests_per_second`. Like [FaultInjection](super::FaultInjection), the
+/// limit only applies to requests made after the test database has been set up.
+#[derive(Clone, Debug)]
+pub struct RateLimit {
+    requests_per_second: f64,
+    burst: u32,
+}
+
+impl RateLimit {
+    /// Create a rate limit admitting `requests_per_second` on average, with bursts of up to `burst`
+    /// requests.
+    pub fn new(requests_per_second: f64, burst: u32) -> RateLimit {
+        RateLimit {
+            requests_per_second: requests_per_second.max(f64::MIN_POSITIVE),
+            burst: burst.max(1),
+        }
+    }
+}
+
+/// The state of a rate limit in a running proxy.
+pub(crate) struct Bucket {
+    limit: RateLimit,
+    // available tokens and the time they were last topped up
+    tokens: Mutex<(f64, Instant)>,
+}
+
+impl Bucket {
+    pub(crate) fn new(limit: RateLimit) -> Bucket {
+        let burst = f64::from(limit.burst);
+        Bucket {
+            limit,
+            tokens: Mutex::new((burst, Instant::now())),
+        }
+    }
+
+    /// Takes a token for one request, or returns how long to wait until a token is available.
+    pub(crate) fn try_acquire(&self) -> Result<(), Duration> {
+        self.try_acquire_at(Instant::now())
+    }
+
+    // takes a token for a request made at `now`
+    fn try_acquire_at(&self, now: Instant) -> Result<(), Duration> {
+        let mut tokens = self.tokens.lock().unwrap_or_else(|e| e.into_inner());
+        let (available, last) = *tokens;
+        let now = now.max(last);
+
+        let refilled = (available
+            + now.duration_since(last).as_secs_f64() * self.limit.requests_per_second)
+            .min(f64::from(self.limit.burst));
+
+        if refilled >= 1.0 {
+            *tokens = (refilled - 1.0, now);
+            Ok(())
+        } else {
+            *tokens = (refilled, now);
+            // a rate too low to earn a token in any representable time never admits another request
+            Err(
+                Duration::try_from_secs_f64((1.0 - refilled) / self.limit.requests_per_second)
+                    .unwrap_or(Duration::MAX),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a bucket and the time it was created at
+    fn bucket(requests_per_second: f64, burst: u32) -> (Bucket, Instant) {
+        let bucket = Bucket::new(RateLimit::new(requests_per_second, burst));
+        let start = bucket.tokens.lock().unwrap().1;
+        (bucket, start)
+    }
+
+    #[test]
+    fn admits_a_full_burst_at_once() {
+        let (bucket, start) = bucket(1.0, 3);
+        for _ in 0..3 {
+            assert_eq!(bucket.try_acquire_at(start), Ok(()));
+        }
+        assert_eq!(bucket.try_acquire_at(start), Err(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn refills_at_the_configured_rate() {
+        let (bucket, start) = bucket(2.0, 1);
+        assert_eq!(bucket.try_acquire_at(start), Ok(()));
+
+        let quarter = start + Duration::from_millis(250);
+        assert_eq!(
+            bucket.try_acquire_at(quarter),
+            Err(Duration::from_millis(250))
+        );
+        assert_eq!(
+            bucket.try_acquire_at(start + Duration::from_millis(500)),
+            Ok(())
+        );
+        assert!(bucket
+            .try_acquire_at(start + Duration::from_millis(500))
+            .is_err());
+    }
+
+    #[test]
+    fn refill_is_capped_at_the_burst() {
+        let (bucket, start) = bucket(10.0, 2);
+        let later = start + Duration::from_secs(60);
+        assert_eq!(bucket.try_acquire_at(later), Ok(()));
+        assert_eq!(bucket.try_acquire_at(later), Ok(()));
+        assert!(bucket.try_acquire_at(later).is_err());
+    }
+
+    #[test]
+    fn rejected_requests_do_not_take_tokens() {
+        let (bucket, start) = bucket(1.0, 1);
+        assert_eq!(bucket.try_acquire_at(start), Ok(()));
+        for ms in [100, 200, 300] {
+            assert!(bucket
+                .try_acquire_at(start + Duration::from_millis(ms))
+                .is_err());
+        }
+        assert_eq!(
+            bucket.try_acquire_at(start + Duration::from_secs(1)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn new_clamps_rate_and_burst() {
+        let (bucket, start) = bucket(0.0, 0);
+        assert_eq!(bucket.try_acquire_at(start), Ok(()));
+        assert!(bucket.try_acquire_at(start).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+use http::Method;
+use serde_json::Value;
+
+use super::{cassette::DB_PLACEHOLDER, ProxyRequest, ProxyResponse};
+
+/// Returns the ids of the documents of the test database written by `request`, judging by the
+/// successful `response` CouchDB gave, with the test database name normalized in `path`. Creations,
+/// updates and deletions of documents, design and local documents and attachments are all writes, as
+/// are `_bulk_docs`, `_purge` and the creation of Mango indexes.
+pub(crate) fn written_ids(
+    request: &ProxyRequest,
+    path: &str,
+    response: &ProxyResponse,
+) -> Vec<String> {
+    if matches!(request.method, Method::GET | Method::HEAD) || !response.status.is_success() {
+        return vec![];
+    }
+    // requests to siblings, whose names extend the test database name, are not writes to it
+    let endpoint = match path.strip_prefix(&format!("/{}", DB_PLACEHOLDER)) {
+        Some(rest) if rest.is_empty() || rest.starts_with(['/', '?']) => rest
+            .trim_start_matches('/')
+            .split('?')
+            .next()
+            .unwrap_or_default(),
+        _ => return vec![],
+    };
+    let body: Value = match serde_json::from_slice(&response.body) {
+        Ok(body) => body,
+        Err(_) => return vec![],
+    };
+
+    match endpoint {
+        "_bulk_docs" => {
+            let request: Value = serde_json::from_slice(&request.body).unwrap_or_default();
+            // documents written with new_edits=false, as replication does, are not listed in the
+            // response
+            if request["new_edits"] == Value::Bool(false) {
+                return ids(request["docs"].as_array(), |doc| doc["_id"].as_str());
+            }
+            ids(body.as_array(), |result| match result["error"].is_null() {
+                true => result["id"].as_str(),
+                false => None,
+            })
+        }
+        "_purge" => body["purged"]
+            .as_object()
+            .map(|purged| purged.keys().cloned().collect())
+            .unwrap_or_default(),
+        "_index" if body["result"] == "created" => body["id"]
+            .as_str()
+            .map(str::to_string)
+            .into_iter()
+            .collect(),
+        _ if body["ok"] == Value::Bool(true) => body["id"]
+            .as_str()
+            .map(str::to_string)
+            .into_iter()
+            .collect(),
+        _ => vec![],
+    }
+}
+
+// the ids picked from `entries` by `id`
+fn ids<'a, F>(entries: Option<&'a Vec<Value>>, id: F) -> Vec<String>
+where
+    F: Fn(&'a Value) -> Option<&'a str>,
+{
+    entries
+        .map(|entries| entries.iter().filter_map(id).map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use http::{HeaderMap, StatusCode};
+    use serde_json::json;
+
+    use super::*;
+
+    // the ids written by a request with `method` to `rest` of the test database, answered with `status`
+    fn written(
+        method: Method,
+        rest: &str,
+        body: Value,
+        status: u16,
+        response: Value,
+    ) -> Vec<String> {
+        let path = format!("/{}{}", DB_PLACEHOLDER, rest);
+        let request = ProxyRequest {
+            method,
+            path: path.clone(),
+            headers: HeaderMap::new(),
+            body: body.to_string().into(),
+        };
+        let response = ProxyResponse {
+            status: StatusCode::from_u16(status).unwrap(),
+            headers: HeaderMap::new(),
+            body: response.to_string().into(),
+        };
+        written_ids(&request, &path, &response)
+    }
+
+    #[test]
+    fn bulk_docs_writes_the_successful_documents() {
+        let ids = written(
+            Method::POST,
+            "/_bulk_docs",
+            json!({"docs": [{"_id": "alice"}, {"_id": "bob"}, {"name": "carol"}]}),
+            201,
+            json!([
+                {"ok": true, "id": "alice", "rev": "1-a"},
+                {"id": "bob", "error": "conflict", "reason": "Document update conflict."},
+                {"ok": true, "id": "9f1c", "rev": "1-c"}
+            ]),
+        );
+        assert_eq!(ids, ["alice", "9f1c"]);
+    }
+
+    #[test]
+    fn bulk_docs_without_new_edits_writes_the_requested_documents() {
+        let ids = written(
+            Method::POST,
+            "/_bulk_docs",
+            json!({"new_edits": false, "docs": [{"_id": "alice", "_rev": "3-a"}]}),
+            201,
+            json!([]),
+        );
+        assert_eq!(ids, ["alice"]);
+    }
+
+    #[test]
+    fn put_writes_the_document() {
+        let ids = written(
+            Method::PUT,
+            "/alice",
+            json!({"name": "Alice"}),
+            201,
+            json!({"ok": true, "id": "alice", "rev": "1-a"}),
+        );
+        assert_eq!(ids, ["alice"]);
+
+        let ids = written(
+            Method::PUT,
+            "/_design/app",
+            json!({"views": {}}),
+            201,
+            json!({"ok": true, "id": "_design/app", "rev": "1-a"}),
+        );
+        assert_eq!(ids, ["_design/app"]);
+    }
+
+    #[test]
+    fn post_writes_the_document_with_its_generated_id() {
+        let ids = written(
+            Method::POST,
+            "",
+            json!({"name": "Alice"}),
+            201,
+            json!({"ok": true, "id": "9f1c", "rev": "1-a"}),
+        );
+        assert_eq!(ids, ["9f1c"]);
+    }
+
+    #[test]
+    fn failed_and_read_requests_write_nothing() {
+        let conflict = json!({"error": "conflict", "reason": "Document update conflict."});
+        assert!(written(Method::PUT, "/alice", json!({}), 409, conflict).is_empty());
+        let doc = json!({"_id": "alice", "_rev": "1-a", "ok": true, "id": "alice"});
+        assert!(written(Method::GET, "/alice", json!({}), 200, doc).is_empty());
+        let found = json!({"docs": []});
+        assert!(written(Method::POST, "/_find", json!({"selector": {}}), 200, found).is_empty());
+    }
+
+    #[test]
+    fn requests_to_siblings_write_nothing() {
+        let ids = written(
+            Method::PUT,
+            "-archive/alice",
+            json!({}),
+            201,
+            json!({"ok": true, "id": "alice", "rev": "1-a"}),
+        );
+        assert!(ids.is_empty());
+    }
+}
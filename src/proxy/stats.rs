@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+
+use http::Method;
+
+use super::cassette::DB_PLACEHOLDER;
+
+/// Counts of the HTTP requests made to CouchDB through the proxy, by method and endpoint, as returned
+/// by [TestRepo::request_stats](crate::TestRepo::request_stats).
+///
+/// Endpoints within the test database are named relative to it: `""` for the database itself, the
+/// path of special endpoints such as `"_bulk_docs"`, `"_find"` or `"_design/app/_view/by_name"`, and
+/// `"{doc}"` for any regular document. Endpoints outside of the test database keep their absolute
+/// path, for example `"/_all_dbs"`. Query strings are ignored.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RequestStats {
+    counts: BTreeMap<(String, String), usize>,
+}
+
+impl RequestStats {
+    /// The number of requests made with `method` to `endpoint`.
+    pub fn count(&self, method: Method, endpoint: &str) -> usize {
+        self.counts
+            .get(&(method.to_string(), endpoint.to_string()))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// The total number of requests made.
+    pub fn total(&self) -> usize {
+        self.counts.values().sum()
+    }
+
+    /// Iterates over the method, endpoint and number of requests of every endpoint called at least
+    /// once, ordered by method and endpoint.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str, usize)> {
+        self.counts
+            .iter()
+            .map(|((method, endpoint), count)| (method.as_str(), endpoint.as_str(), *count))
+    }
+
+    /// Counts a request to `path`, in which the test database name has been normalized.
+    pub(crate) fn record(&mut self, method: &Method, path: &str) {
+        *self
+            .counts
+            .entry((method.to_string(), endpoint(path)))
+            .or_default() += 1;
+    }
+}
+
+/// The endpoint `path`, in which the test database name has been normalized, is counted under.
+pub(super) fn endpoint(path: &str) -> String {
+    let path = path.split('?').next().unwrap_or_default();
+    let db_root = format!("/{}", DB_PLACEHOLDER);
+
+    match path.strip_prefix(&db_root) {
+        Some("") | Some("/") => String::new(),
+        Some(rest) if rest.starts_with('/') => {
+            let rest = &rest[1..];
+            if rest.starts_with('_') {
+                rest.to_string()
+            } else {
+                "{doc}".to_string()
+            }
+        }
+        _ => path.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db_path(rest: &str) -> String {
+        format!("/{}{}", DB_PLACEHOLDER, rest)
+    }
+
+    #[test]
+    fn database_itself_is_the_empty_endpoint() {
+        assert_eq!(endpoint(&db_path("")), "");
+        assert_eq!(endpoint(&db_path("/")), "");
+    }
+
+    #[test]
+    fn document_ids_are_collapsed() {
+        assert_eq!(endpoint(&db_path("/alice")), "{doc}");
+        assert_eq!(endpoint(&db_path("/org.couchdb.user%3Abob")), "{doc}");
+        assert_eq!(endpoint(&db_path("/alice/photo.png")), "{doc}");
+    }
+
+    #[test]
+    fn special_endpoints_keep_their_path() {
+        assert_eq!(endpoint(&db_path("/_bulk_docs")), "_bulk_docs");
+        assert_eq!(endpoint(&db_path("/_find")), "_find");
+        assert_eq!(
+            endpoint(&db_path("/_design/app/_view/by_name")),
+            "_design/app/_view/by_name"
+        );
+    }
+
+    #[test]
+    fn query_strings_are_ignored() {
+        assert_eq!(endpoint(&db_path("/alice?rev=1-abc")), "{doc}");
+        assert_eq!(
+            endpoint(&db_path("/_design/app/_view/by_name?key=%22a%22&limit=10")),
+            "_design/app/_view/by_name"
+        );
+        assert_eq!(endpoint(&db_path("?n=1")), "");
+    }
+
+    #[test]
+    fn paths_outside_the_database_are_absolute() {
+        assert_eq!(endpoint("/_all_dbs"), "/_all_dbs");
+        assert_eq!(endpoint("/_session?basic=true"), "/_session");
+        assert_eq!(endpoint(&db_path("_other/alice")), db_path("_other/alice"));
+    }
+
+    #[test]
+    fn record_counts_by_method_and_endpoint() {
+        let mut stats = RequestStats::default();
+        stats.record(&Method::GET, &db_path("/alice"));
+        stats.record(&Method::GET, &db_path("/bob"));
+        stats.record(&Method::POST, &db_path("/_find"));
+        assert_eq!(stats.count(Method::GET, "{doc}"), 2);
+        assert_eq!(stats.count(Method::POST, "_find"), 1);
+        assert_eq!(stats.count(Method::PUT, "{doc}"), 0);
+        assert_eq!(stats.total(), 3);
+    }
+}
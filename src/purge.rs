@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+
+use couch_rs::error::CouchError;
+use serde_json::{json, Value};
+
+use crate::{
+    compat::{Method, StatusCode},
+    naming::encode_db_name,
+    TestRepo,
+};
+
+impl TestRepo {
+    /// Purges the given revisions of a document from the database associated with this instance through
+    /// the `_purge` endpoint, returning the revisions CouchDB reported as purged. Unlike a deletion, a
+    /// purge leaves no tombstone behind, which is what data-erasure flows such as GDPR deletion rely on.
+    pub async fn purge(&self, doc_id: &str, revs: &[&str]) -> Result<Vec<String>, CouchError> {
+        let response: Value = self
+            .client
+            .req(
+                Method::POST,
+                &format!("{}/_purge", encode_db_name(&self.cfg.db_name)),
+                None,
+            )
+            .body(serde_json::to_string(&json!({ doc_id: revs }))?)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let mut purged: HashMap<String, Vec<String>> =
+            serde_json::from_value(response["purged"].clone())?;
+        Ok(purged.remove(doc_id).unwrap_or_default())
+    }
+
+    /// Returns the purge sequence of the database associated with this instance, the number of purge
+    /// requests applied to it. CouchDB 2 and later report the sequence as an opaque string whose numeric
+    /// prefix is this count.
+    pub async fn purge_seq(&self) -> Result<u64, CouchError> {
+        let info: Value = self
+            .client
+            .req(Method::GET, &encode_db_name(&self.cfg.db_name), None)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let seq = match &info["purge_seq"] {
+            Value::Number(n) => n.as_u64(),
+            Value::String(s) => s.split('-').next().and_then(|n| n.parse().ok()),
+            _ => None,
+        };
+        seq.ok_or_else(|| {
+            CouchError::new(
+                format!(
+                    "Unexpected purge_seq in database info: {}",
+                    info["purge_seq"]
+                ),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })
+    }
+
+    /// Asserts that the document with the given id has been purged from the database associated with this
+    /// instance: it can neither be read nor does it appear, even as a deletion, in the changes feed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the document is still present in either form, or if CouchDB cannot be queried.
+    pub async fn assert_purged(&self, doc_id: &str) {
+        match self.db.get::<Value>(doc_id).await {
+            Err(e) if e.is_not_found() => {}
+            Err(e) => panic!("Failed to read document {}: {}", doc_id, e),
+            Ok(_) => panic!(
+                "Document {} still exists in database {}",
+                doc_id,
+                self.db.name()
+            ),
+        }
+
+        let changes: Value = self
+            .client
+            .req(
+                Method::POST,
+                &format!("{}/_changes", encode_db_name(&self.cfg.db_name)),
+                None,
+            )
+            .query(&[("filter", "_doc_ids")])
+            .body(json!({ "doc_ids": [doc_id] }).to_string())
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .unwrap_or_else(|e| panic!("Failed to read changes feed: {}", e))
+            .json()
+            .await
+            .unwrap_or_else(|e| panic!("Failed to read changes feed: {}", e));
+
+        let listed = changes["results"]
+            .as_array()
+            .map(|results| results.iter().any(|r| r["id"] == doc_id))
+            .unwrap_or(false);
+        assert!(
+            !listed,
+            "Document {} was deleted but not purged; it still appears in the changes feed of database {}",
+            doc_id,
+            self.db.name()
+        );
+    }
+}
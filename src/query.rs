@@ -0,0 +1,183 @@
+use couch_rs::{error::CouchError, types::query::QueryParams};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{compat::Method, naming::encode_db_name, trace::trace_event, TestRepo};
+
+impl TestRepo {
+    /// Queries a view in the database associated with this instance and deserializes every returned row
+    /// into `T`. Each row is the JSON object returned by CouchDB for the view, with `id`, `key`, `value`
+    /// and (when `include_docs` is set in `params`) `doc` fields, so `T` is typically a small struct
+    /// naming the fields the test cares about.
+    ///
+    /// The query is executed via
+    /// [couch_rs::database::query_raw](https://docs.rs/couch_rs/latest/couch_rs/database/struct.Database.html#method.query_raw).
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "couch_rs_test::query_view", skip(self, params), fields(db = %self.db.name()))
+    )]
+    pub async fn query_view_typed<T: DeserializeOwned>(
+        &self,
+        ddoc: &str,
+        view: &str,
+        params: Option<QueryParams<Value>>,
+    ) -> Result<Vec<T>, CouchError> {
+        let result = self.db.query_raw(ddoc, view, params).await?;
+        trace_event!(rows = result.rows.len(), "queried view");
+
+        result
+            .rows
+            .into_iter()
+            .map(|row| Ok(serde_json::from_value(serde_json::to_value(row)?)?))
+            .collect()
+    }
+
+    /// Asserts that a view in the database associated with this instance returns exactly the `expected`
+    /// rows, given as `(key, value)` pairs. This is tailored to testing map/reduce functions shipped in
+    /// design documents.
+    ///
+    /// Rows are compared irrespective of order, since map rows with equal keys are ordered by the id of
+    /// the emitting document. On a mismatch, the panic message lists the missing rows (`-`) and the
+    /// unexpected rows (`+`).
+    ///
+    /// ```rust
+    /// # use serde_json::json;
+    /// # async fn example(repo: &couch_rs_test::TestRepo) {
+    /// repo.assert_view_rows(
+    ///     "orders",
+    ///     "total_by_customer",
+    ///     None,
+    ///     &[(json!("alice"), json!(30)), (json!("bob"), json!(12))],
+    /// )
+    /// .await;
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rows differ or the view cannot be queried.
+    pub async fn assert_view_rows<K: Serialize, V: Serialize>(
+        &self,
+        ddoc: &str,
+        view: &str,
+        params: Option<QueryParams<Value>>,
+        expected: &[(K, V)],
+    ) {
+        let actual: Vec<(Value, Value)> = self
+            .query_view_typed::<KeyValue>(ddoc, view, params)
+            .await
+            .unwrap_or_else(|e| panic!("Failed to query view {}/{}: {}", ddoc, view, e))
+            .into_iter()
+            .map(|row| (row.key, row.value))
+            .collect();
+        let expected: Vec<(Value, Value)> = expected
+            .iter()
+            .map(|(key, value)| {
+                (
+                    serde_json::to_value(key).expect("expected key must serialize to JSON"),
+                    serde_json::to_value(value).expect("expected value must serialize to JSON"),
+                )
+            })
+            .collect();
+
+        let mut unexpected = actual.clone();
+        let mut missing = vec![];
+        for row in expected.iter() {
+            match unexpected.iter().position(|r| r == row) {
+                Some(n) => {
+                    unexpected.remove(n);
+                }
+                None => missing.push(row),
+            }
+        }
+        if missing.is_empty() && unexpected.is_empty() {
+            return;
+        }
+
+        let mut diff = String::new();
+        for (key, value) in sorted(missing.into_iter().cloned().collect()) {
+            diff.push_str(&format!("\n  - {} => {}", key, value));
+        }
+        for (key, value) in sorted(unexpected) {
+            diff.push_str(&format!("\n  + {} => {}", key, value));
+        }
+        panic!(
+            "View {}/{} in database {} returned {} rows, expected {}:{}",
+            ddoc,
+            view,
+            self.db.name(),
+            actual.len(),
+            expected.len(),
+            diff
+        );
+    }
+
+    /// Asserts that a Mango query with `selector` would be served by the index named `index_name`, as
+    /// reported by the `_explain` endpoint, rather than by another index or a full scan of `_all_docs`.
+    /// This catches missing-index regressions that otherwise only show up as slow queries in production.
+    ///
+    /// ```rust
+    /// # use serde_json::json;
+    /// # async fn example(repo: &couch_rs_test::TestRepo) {
+    /// repo.assert_uses_index(json!({"email": "alice@example.com"}), "by-email")
+    ///     .await;
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the query would use a different index or none, or if the query cannot be explained.
+    pub async fn assert_uses_index(&self, selector: Value, index_name: &str) {
+        let explained = self
+            .explain(&selector)
+            .await
+            .unwrap_or_else(|e| panic!("Failed to explain query {}: {}", selector, e));
+        let index = &explained["index"];
+
+        if index["type"] == "special" {
+            panic!(
+                "Query {} on database {} falls back to a full scan instead of using index {}",
+                selector,
+                self.db.name(),
+                index_name
+            );
+        }
+        if index["name"] != index_name {
+            panic!(
+                "Query {} on database {} uses index {} (design document {}) instead of {}",
+                selector,
+                self.db.name(),
+                index["name"],
+                index["ddoc"],
+                index_name
+            );
+        }
+    }
+
+    // returns the query plan CouchDB chose for `selector`
+    pub(crate) async fn explain(&self, selector: &Value) -> Result<Value, CouchError> {
+        let path = format!("{}/_explain", encode_db_name(&self.cfg.db_name));
+        let explained = self
+            .client
+            .req(Method::POST, &path, None)
+            .body(json!({ "selector": selector }).to_string())
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(explained)
+    }
+}
+
+#[derive(Deserialize)]
+struct KeyValue {
+    key: Value,
+    value: Value,
+}
+
+// orders rows by their JSON text, which is stable across runs
+fn sorted(mut rows: Vec<(Value, Value)>) -> Vec<(Value, Value)> {
+    rows.sort_by_cached_key(|(key, value)| (key.to_string(), value.to_string()));
+    rows
+}
@@ -0,0 +1,301 @@
+use std::{
+    collections::HashMap,
+    fs,
+    future::Future,
+    io::Write,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, Once, OnceLock,
+    },
+    thread::JoinHandle,
+};
+
+use serde::Serialize;
+
+use crate::{marker::now_secs, summary, TestRepo, TestRepoConfig};
+
+/// Environment variable selecting where the [LeakReport] is written when the process exits: `stderr`,
+/// or the path of a JSON file.
+const LEAK_REPORT_ENV_VAR: &str = "COUCH_TEST_LEAK_REPORT";
+
+// test databases created by this process and not yet destroyed, keyed by their unique name
+static LIVE: OnceLock<Mutex<HashMap<String, Entry>>> = OnceLock::new();
+static CREATED: AtomicUsize = AtomicUsize::new(0);
+static DESTROYED: AtomicUsize = AtomicUsize::new(0);
+
+static INSTALL_FALLBACK: Once = Once::new();
+static INSTALL_SIGNAL_HANDLER: Once = Once::new();
+
+struct Entry {
+    // the configuration needed to destroy the database directly on the server
+    cfg: TestRepoConfig,
+    created_at: u64,
+    created_by: Option<String>,
+    // shared databases are kept until the process exits by design, so they are not reported as leaked
+    shared: bool,
+}
+
+/// Test databases created by this process that have not been destroyed, as returned by [leak_report].
+#[derive(Serialize, Clone, Debug)]
+pub struct LeakReport {
+    /// Number of test databases created by this process.
+    pub created: usize,
+    /// Number of test databases destroyed by this process.
+    pub destroyed: usize,
+    /// The databases created but not destroyed, oldest first.
+    pub leaked: Vec<LeakedDatabase>,
+}
+
+/// A test database that has not been destroyed; see [LeakReport].
+#[derive(Serialize, Clone, Debug)]
+pub struct LeakedDatabase {
+    /// Unique name of the database.
+    pub name: String,
+    /// Creation time of the database, in seconds since the UNIX epoch.
+    pub created_at: u64,
+    /// Name of the thread that created the database, which the test harness sets to the test's name.
+    pub created_by: Option<String>,
+}
+
+fn live() -> &'static Mutex<HashMap<String, Entry>> {
+    LIVE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Records a newly created test database, reachable on the server at `server_uri`.
+pub(crate) fn register(cfg: &TestRepoConfig, server_uri: String) {
+    insert(cfg, server_uri, false);
+}
+
+/// Records a newly created database shared by the tests of this process, reachable on the server at
+/// `server_uri`, to be destroyed when the process exits.
+pub(crate) fn register_shared(cfg: &TestRepoConfig, server_uri: String) {
+    insert(cfg, server_uri, true);
+}
+
+fn insert(cfg: &TestRepoConfig, server_uri: String, shared: bool) {
+    INSTALL_FALLBACK.call_once(install_fallback);
+
+    CREATED.fetch_add(1, Ordering::SeqCst);
+    live().lock().unwrap_or_else(|e| e.into_inner()).insert(
+        cfg.db_name.clone(),
+        Entry {
+            cfg: cfg.clone().with_uri(server_uri),
+            created_at: now_secs(),
+            created_by: std::thread::current().name().map(str::to_string),
+            shared,
+        },
+    );
+}
+
+/// Records a test database created on the same server as the registered database `primary`, returning
+/// whether it was recorded; it is not when `primary` is not, as for emulated or stubbed databases.
+pub(crate) fn register_sibling(primary: &str, db_name: &str) -> bool {
+    let mut live = live().lock().unwrap_or_else(|e| e.into_inner());
+    let cfg = match live.get(primary) {
+        Some(entry) => entry.cfg.clone().with_name(db_name.to_string()),
+        None => return false,
+    };
+
+    CREATED.fetch_add(1, Ordering::SeqCst);
+    live.insert(
+        db_name.to_string(),
+        Entry {
+            cfg,
+            created_at: now_secs(),
+            created_by: std::thread::current().name().map(str::to_string),
+            shared: false,
+        },
+    );
+    true
+}
+
+/// Forgets a test database once it has been destroyed.
+pub(crate) fn unregister(db_name: &str) {
+    if live()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .remove(db_name)
+        .is_some()
+    {
+        DESTROYED.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Reports the test databases created by this process that have not been destroyed yet, to catch tests
+/// that leak a [TestRepo], for example by `mem::forget` or by holding it in a static.
+///
+/// Setting the `COUCH_TEST_LEAK_REPORT` environment variable writes this report when the process exits,
+/// before [teardown_all] destroys the leaked databases: to standard error when set to `stderr`, and as
+/// JSON to the file it names otherwise.
+pub fn leak_report() -> LeakReport {
+    let mut leaked: Vec<LeakedDatabase> = live()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .filter(|(_, entry)| !entry.shared)
+        .map(|(name, entry)| LeakedDatabase {
+            name: name.clone(),
+            created_at: entry.created_at,
+            created_by: entry.created_by.clone(),
+        })
+        .collect();
+    leaked.sort_by(|a, b| (a.created_at, &a.name).cmp(&(b.created_at, &b.name)));
+
+    LeakReport {
+        created: CREATED.load(Ordering::SeqCst),
+        destroyed: DESTROYED.load(Ordering::SeqCst),
+        leaked,
+    }
+}
+
+fn write_leak_report() {
+    let target = match std::env::var(LEAK_REPORT_ENV_VAR) {
+        Ok(target) if !target.is_empty() => target,
+        _ => return,
+    };
+    let report = leak_report();
+
+    let written = if target == "stderr" {
+        let mut out = std::io::stderr().lock();
+        let mut result = writeln!(
+            out,
+            "couch_rs_test: {} test databases created, {} destroyed, {} leaked",
+            report.created,
+            report.destroyed,
+            report.leaked.len()
+        );
+        for db in report.leaked.iter() {
+            result = result.and_then(|_| {
+                writeln!(
+                    out,
+                    "  {} (created by {})",
+                    db.name,
+                    db.created_by.as_deref().unwrap_or("unnamed thread")
+                )
+            });
+        }
+        result.map_err(|e| e.to_string())
+    } else {
+        serde_json::to_string_pretty(&report)
+            .map_err(|e| e.to_string())
+            .and_then(|json| fs::write(&target, json).map_err(|e| e.to_string()))
+    };
+    if let Err(e) = written {
+        log::error!("Failed to write leak report to {}: {}", target, e);
+    }
+}
+
+/// Destroys every test database created by this process that has not been destroyed yet, returning the
+/// number of databases destroyed.
+///
+/// Databases are normally destroyed when their [TestRepo] is dropped; this is a safety net for repos
+/// that are never dropped, such as those held in statics. It runs automatically when the process exits
+/// through `std::process::exit`, as the test harness does after failed tests, and on panics when
+/// compiled with `panic = "abort"`. It may also be called directly, for example from a custom test
+/// harness; it blocks the calling thread until every database has been destroyed, and must not be called
+/// while tests using a [TestRepo] are still running.
+pub fn teardown_all() -> usize {
+    let pending: Vec<TestRepoConfig> = live()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .values()
+        .map(|entry| entry.cfg.clone())
+        .collect();
+    if pending.is_empty() {
+        return 0;
+    }
+
+    log::warn!("Destroying {} remaining test databases", pending.len());
+    let count = pending.len();
+    let _ = run_on_thread(async move {
+        for cfg in pending {
+            TestRepo::drop(cfg).await;
+        }
+    })
+    .join();
+
+    count - live().lock().unwrap_or_else(|e| e.into_inner()).len()
+}
+
+/// Runs `future` to completion on a new thread with its own runtime, independent of any runtime the
+/// caller may be on.
+pub(crate) fn run_on_thread<F>(future: F) -> JoinHandle<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    std::thread::spawn(move || {
+        match tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        {
+            Ok(runtime) => runtime.block_on(future),
+            Err(e) => log::error!("Failed to start a runtime for test database cleanup: {}", e),
+        }
+    })
+}
+
+/// Destroys all remaining test databases and exits when the process receives SIGINT or SIGTERM (Ctrl-C on
+/// platforms without signals). Only the first call installs the handler.
+pub(crate) fn install_signal_handler() {
+    INSTALL_SIGNAL_HANDLER.call_once(|| {
+        let _ = run_on_thread(async {
+            match wait_for_signal().await {
+                Ok(exit_code) => {
+                    log::warn!("Interrupted; destroying remaining test databases");
+                    teardown_all();
+                    std::process::exit(exit_code);
+                }
+                Err(e) => log::error!(
+                    "Failed to install signal handler for test database cleanup: {}",
+                    e
+                ),
+            }
+        });
+    });
+}
+
+// resolves with the conventional exit code for the signal received
+#[cfg(unix)]
+async fn wait_for_signal() -> std::io::Result<i32> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        _ = interrupt.recv() => Ok(130),
+        _ = terminate.recv() => Ok(143),
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() -> std::io::Result<i32> {
+    tokio::signal::ctrl_c().await?;
+    Ok(130)
+}
+
+fn install_fallback() {
+    // the test harness exits through `process::exit` when tests failed, which skips destructors of
+    // statics but runs `atexit` handlers
+    unsafe {
+        libc::atexit(teardown_at_exit);
+    }
+
+    // an aborting panic skips all destructors; unwinding panics are left to Drop
+    if cfg!(panic = "abort") {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            previous(info);
+            teardown_all();
+        }));
+    }
+}
+
+extern "C" fn teardown_at_exit() {
+    // unwinding out of an `extern "C"` function aborts the process
+    let _ = std::panic::catch_unwind(|| {
+        let leaked = leak_report().leaked;
+        write_leak_report();
+        teardown_all();
+        summary::write_summary(leaked);
+    });
+}
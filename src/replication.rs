@@ -0,0 +1,392 @@
+use std::{fmt, time::Duration};
+
+use couch_rs::{error::CouchError, Client};
+use serde_json::{json, Value};
+use tokio::time::Instant;
+
+use crate::{
+    compat::{Method, StatusCode},
+    naming::{encode_db_name, percent_decode, random_chars},
+    rt,
+    trace::trace_event,
+    wait::{timeout_error, POLL_INTERVAL},
+    TestRepo,
+};
+
+const REPLICATOR_DB: &str = "_replicator";
+
+/// The state of a replication job, as reported by the CouchDB scheduler and awaited with
+/// [TestRepo::await_replication_state].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplicationState {
+    /// The replication document has been added but not yet processed.
+    Initializing,
+    /// The job is waiting for the scheduler to run it.
+    Pending,
+    /// The job is running.
+    Running,
+    /// The job failed and is backing off before it is retried.
+    Crashing,
+    /// A one-shot replication finished; continuous replications never do.
+    Completed,
+    /// The replication document is invalid, or the job exhausted its retries; it is not retried.
+    Failed,
+    /// The replication document could not be turned into a job, for example because its endpoints
+    /// cannot be resolved; it is retried.
+    Error,
+}
+
+impl ReplicationState {
+    // reads a state as reported by _scheduler/docs
+    fn parse(state: &str) -> Option<ReplicationState> {
+        match state {
+            "initializing" => Some(ReplicationState::Initializing),
+            "pending" => Some(ReplicationState::Pending),
+            "running" => Some(ReplicationState::Running),
+            "crashing" => Some(ReplicationState::Crashing),
+            "completed" => Some(ReplicationState::Completed),
+            "failed" => Some(ReplicationState::Failed),
+            "error" => Some(ReplicationState::Error),
+            _ => None,
+        }
+    }
+
+    // whether the job never leaves this state
+    fn is_final(&self) -> bool {
+        matches!(self, ReplicationState::Completed | ReplicationState::Failed)
+    }
+}
+
+// as named by CouchDB
+impl fmt::Display for ReplicationState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ReplicationState::Initializing => "initializing",
+            ReplicationState::Pending => "pending",
+            ReplicationState::Running => "running",
+            ReplicationState::Crashing => "crashing",
+            ReplicationState::Completed => "completed",
+            ReplicationState::Failed => "failed",
+            ReplicationState::Error => "error",
+        })
+    }
+}
+
+/// A continuous replication started by [TestRepo::start_continuous_replication]. The replication runs
+/// until [ReplicationHandle::cancel] is called or the handle is dropped, which removes its job from the
+/// scheduler, so live sync flows can be tested without leaking replication jobs. Replications involving
+/// the database of a [TestRepo] are also removed when its database is destroyed.
+pub struct ReplicationHandle {
+    client: Client,
+    id: String,
+    // cleared once the replication has been cancelled
+    active: bool,
+}
+
+impl ReplicationHandle {
+    /// The id of the `_replicator` document defining the replication, under which the scheduler reports
+    /// its state at `_scheduler/docs/_replicator/{id}`.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Cancels the replication by deleting its `_replicator` document, and waits for the deletion.
+    /// Documents replicated so far stay in the target database.
+    pub async fn cancel(mut self) -> Result<(), CouchError> {
+        self.active = false;
+        delete_replication(&self.client, &self.id).await
+    }
+}
+
+impl Drop for ReplicationHandle {
+    fn drop(&mut self) {
+        if !self.active {
+            return;
+        }
+        // the handle may be dropped outside of an async context, so the deletion is not awaited
+        let client = self.client.clone();
+        let id = self.id.clone();
+        rt::spawn(async move {
+            if let Err(e) = delete_replication(&client, &id).await {
+                log::error!("Failed to cancel replication {}: {}", id, e);
+            }
+        });
+    }
+}
+
+impl TestRepo {
+    /// Starts a continuous replication from `source` to `target` through the `_replicator` database
+    /// and returns a [ReplicationHandle] that cancels it. Each of `source` and `target` is either the
+    /// url of a database, with any credentials embedded, or the name of a database on the configured
+    /// server, such as `repo.db.name()` or a database created with [TestRepo::create_sibling]. Names are
+    /// turned into urls of the configured uri with the configured credentials, so the server must be
+    /// able to reach itself at that uri. The target database must exist.
+    ///
+    /// ```rust
+    /// # async fn example(repo: &couch_rs_test::TestRepo) -> Result<(), couch_rs::error::CouchError> {
+    /// let mirror = repo.create_sibling("mirror").await?;
+    /// let replication = repo.start_continuous_replication(repo.db.name(), mirror.name()).await?;
+    /// // exercise code relying on the live sync
+    /// replication.cancel().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn start_continuous_replication(
+        &self,
+        source: &str,
+        target: &str,
+    ) -> Result<ReplicationHandle, CouchError> {
+        let id = format!(
+            "couch_rs_test-{}",
+            random_chars(&mut rand::thread_rng(), 16)
+        );
+        let doc = json!({
+            "source": self.endpoint(source),
+            "target": self.endpoint(target),
+            "continuous": true,
+        });
+        self.client
+            .req(
+                Method::PUT,
+                &format!("{}/{}", REPLICATOR_DB, encode_db_name(&id)),
+                None,
+            )
+            .body(doc.to_string())
+            .send()
+            .await?
+            .error_for_status()?;
+        log::info!(
+            "Started continuous replication {} from {} to {}",
+            id,
+            percent_decode(source),
+            percent_decode(target)
+        );
+
+        Ok(ReplicationHandle {
+            client: self.client.clone(),
+            id,
+            active: true,
+        })
+    }
+
+    /// Polls the CouchDB scheduler until the replication job `job_id` is in `state`, so that tests of
+    /// live sync can wait for a replication to be running, crashing or completed instead of sleeping.
+    /// `job_id` is either the id of the `_replicator` document defining the replication, such as
+    /// [ReplicationHandle::id], or the replication id the scheduler assigned to the job.
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// use couch_rs_test::ReplicationState;
+    ///
+    /// # async fn example(repo: &couch_rs_test::TestRepo) -> Result<(), couch_rs::error::CouchError> {
+    /// let mirror = repo.create_sibling("mirror").await?;
+    /// let replication = repo.start_continuous_replication(repo.db.name(), mirror.name()).await?;
+    /// let timeout = Duration::from_secs(10);
+    /// repo.await_replication_state(replication.id(), ReplicationState::Running, timeout)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Jobs are looked up in `_scheduler/jobs`, which lists the jobs the scheduler holds, and otherwise
+    /// in `_scheduler/docs/_replicator`, which also reports jobs that completed, failed or are not yet
+    /// scheduled. If the job is not in `state` when `timeout` elapses, an error with status
+    /// `REQUEST_TIMEOUT` is returned naming the last state seen; if it reaches a final state other than
+    /// `state`, an error is returned right away, with the reason CouchDB gives.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "couch_rs_test::await_replication_state", skip(self), fields(db = %self.db.name()))
+    )]
+    pub async fn await_replication_state(
+        &self,
+        job_id: &str,
+        state: ReplicationState,
+        timeout: Duration,
+    ) -> Result<(), CouchError> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let (current, reason) = self.replication_state(job_id).await?;
+            if current == Some(state) {
+                trace_event!(%state, "replication state reached");
+                return Ok(());
+            }
+            if let Some(current) = current.filter(ReplicationState::is_final) {
+                return Err(CouchError::new(
+                    format!(
+                        "Replication {} is {} and will not become {}: {}",
+                        job_id,
+                        current,
+                        state,
+                        reason.unwrap_or_else(|| "no reason given".to_string())
+                    ),
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                ));
+            }
+
+            if Instant::now() >= deadline {
+                let seen = match current {
+                    Some(current) => format!("it is {}", current),
+                    None => "the scheduler does not know it".to_string(),
+                };
+                return Err(timeout_error(format!(
+                    "Timed out after {:?} waiting for replication {} to be {}; {}",
+                    timeout, job_id, state, seen
+                )));
+            }
+
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    // the state of the replication job `job_id` and the reason of its last failure, if the scheduler
+    // knows the job
+    async fn replication_state(
+        &self,
+        job_id: &str,
+    ) -> Result<(Option<ReplicationState>, Option<String>), CouchError> {
+        let jobs = self.get_json("_scheduler/jobs").await?;
+        let job = jobs["jobs"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|job| job["id"] == job_id || job["doc_id"] == job_id);
+        if let Some(job) = job {
+            // the most recent event comes first
+            let event = &job["history"][0];
+            let state = match event["type"].as_str() {
+                Some("started") => ReplicationState::Running,
+                Some("crashed") => ReplicationState::Crashing,
+                _ => ReplicationState::Pending,
+            };
+            return Ok((Some(state), event["reason"].as_str().map(str::to_string)));
+        }
+
+        let response = self
+            .client
+            .req(
+                Method::GET,
+                &format!(
+                    "_scheduler/docs/{}/{}",
+                    REPLICATOR_DB,
+                    encode_db_name(job_id)
+                ),
+                None,
+            )
+            .send()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok((None, None));
+        }
+        let doc: Value = response.error_for_status()?.json().await?;
+        let reason = match &doc["info"]["error"] {
+            Value::String(error) => Some(error.clone()),
+            Value::Null => None,
+            error => Some(error.to_string()),
+        };
+        Ok((
+            doc["state"].as_str().and_then(ReplicationState::parse),
+            reason,
+        ))
+    }
+
+    // a replication endpoint for a database url, or for the name of a database on the configured server
+    fn endpoint(&self, db: &str) -> Value {
+        if db.contains("://") {
+            return Value::from(db);
+        }
+        json!({
+            "url": format!(
+                "{}/{}",
+                self.cfg.uri.trim_end_matches('/'),
+                encode_db_name(&percent_decode(db))
+            ),
+            "auth": {
+                "basic": {
+                    "username": self.cfg.username,
+                    "password": self.cfg.password.expose(),
+                }
+            }
+        })
+    }
+}
+
+// deletes the _replicator document `id`, if it still exists
+async fn delete_replication(client: &Client, id: &str) -> Result<(), CouchError> {
+    let path = format!("{}/{}", REPLICATOR_DB, encode_db_name(id));
+    let response = client.req(Method::GET, &path, None).send().await?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(());
+    }
+    let doc: Value = response.error_for_status()?.json().await?;
+
+    let response = client
+        .req(Method::DELETE, &path, None)
+        .query(&[("rev", doc["_rev"].as_str().unwrap_or_default())])
+        .send()
+        .await?;
+    if response.status() != StatusCode::NOT_FOUND {
+        response.error_for_status()?;
+    }
+    log::info!("Cancelled replication {}", id);
+    Ok(())
+}
+
+/// Deletes the documents of the `_replicator` database whose source or target is `db_name`, cancelling
+/// their replication jobs, so the scheduler does not keep retrying against a destroyed test database.
+/// Returns the number of documents deleted; a server without a `_replicator` database has none.
+pub(crate) async fn remove_replications(
+    client: &Client,
+    db_name: &str,
+) -> Result<usize, CouchError> {
+    let response = client
+        .req(Method::GET, &format!("{}/_all_docs", REPLICATOR_DB), None)
+        .query(&[("include_docs", "true")])
+        .send()
+        .await?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(0);
+    }
+    let page: Value = response.error_for_status()?.json().await?;
+
+    let mut removed = 0;
+    let rows = page["rows"].as_array().cloned().unwrap_or_default();
+    for doc in rows.iter().filter_map(|row| row.get("doc")) {
+        let id = match doc["_id"].as_str() {
+            Some(id) if !id.starts_with("_design/") => id,
+            _ => continue,
+        };
+        if !refers_to(&doc["source"], db_name) && !refers_to(&doc["target"], db_name) {
+            continue;
+        }
+
+        client
+            .req(
+                Method::DELETE,
+                &format!("{}/{}", REPLICATOR_DB, encode_db_name(id)),
+                None,
+            )
+            .query(&[("rev", doc["_rev"].as_str().unwrap_or_default())])
+            .send()
+            .await?
+            .error_for_status()?;
+        log::info!("Removed replication {} of database {}", id, db_name);
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+// whether a replication endpoint, a database url or name or an object with a `url`, is `db_name`
+fn refers_to(endpoint: &Value, db_name: &str) -> bool {
+    let url = match endpoint {
+        Value::String(url) => url.as_str(),
+        Value::Object(endpoint) => match endpoint.get("url").and_then(Value::as_str) {
+            Some(url) => url,
+            None => return false,
+        },
+        _ => return false,
+    };
+
+    let url = url.trim_end_matches('/');
+    percent_decode(url.rsplit('/').next().unwrap_or(url)) == db_name
+}
@@ -0,0 +1,128 @@
+use std::{future::Future, time::Duration};
+
+use couch_rs::error::{CouchError, CouchResult};
+
+use crate::{
+    compat::{ReqwestError, StatusCode},
+    secret,
+};
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(200);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+// statuses a busy or restarting server answers with, after which the same request may well succeed
+const TRANSIENT_STATUSES: [StatusCode; 5] = [
+    StatusCode::REQUEST_TIMEOUT,
+    StatusCode::TOO_MANY_REQUESTS,
+    StatusCode::BAD_GATEWAY,
+    StatusCode::SERVICE_UNAVAILABLE,
+    StatusCode::GATEWAY_TIMEOUT,
+];
+
+/// How the operations of this crate itself, creating, seeding and destroying test databases, are
+/// retried after a transient failure: a `408`, `429`, `502`, `503` or `504` response, a timed out
+/// request or a failed connection. Shared CI servers routinely answer with such errors under load, and
+/// retrying keeps them from failing a whole suite. Set with
+/// [TestRepoConfig::with_retry](crate::TestRepoConfig::with_retry).
+///
+/// Attempts are spaced by an exponential backoff, starting at the initial backoff and doubling up to
+/// the maximum backoff. The default policy makes up to 3 attempts, waiting 200 milliseconds after the
+/// first.
+///
+/// ```rust
+/// use couch_rs_test::RetryPolicy;
+/// use std::time::Duration;
+///
+/// let policy = RetryPolicy::new(5).with_backoff(Duration::from_millis(500), Duration::from_secs(10));
+/// ```
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Create a policy making up to `max_attempts` attempts per operation, with the default backoff; 0
+    /// is treated as 1.
+    pub fn new(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: max_attempts.max(1),
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+        }
+    }
+
+    /// Create a policy that never retries.
+    pub fn none() -> RetryPolicy {
+        RetryPolicy::new(1)
+    }
+
+    /// Wait `initial` before the second attempt, doubling the wait for each further attempt up to `max`.
+    pub fn with_backoff(self, initial: Duration, max: Duration) -> RetryPolicy {
+        RetryPolicy {
+            initial_backoff: initial,
+            max_backoff: max,
+            ..self
+        }
+    }
+
+    /// Runs `op` until it succeeds, fails with an error that is not transient, or attempts run out. The
+    /// error returned has any credentials redacted.
+    pub(crate) async fn run<T, F, Fut>(&self, what: &str, mut op: F) -> CouchResult<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = CouchResult<T>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match op().await {
+                Err(e) if self.retries(attempt, &e) => {
+                    self.pause(attempt, what, &e).await;
+                    attempt += 1;
+                }
+                result => return result.map_err(secret::redact_error),
+            }
+        }
+    }
+
+    /// Whether an operation that failed with `error` on attempt number `attempt` is to be retried.
+    pub(crate) fn retries(&self, attempt: u32, error: &CouchError) -> bool {
+        attempt < self.max_attempts && is_transient(error)
+    }
+
+    /// Waits before the attempt following attempt number `attempt`, which failed with `error`.
+    pub(crate) async fn pause(&self, attempt: u32, what: &str, error: &CouchError) {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(self.max_backoff);
+        log::warn!(
+            "{} failed on attempt {} of {}, retrying in {:?}: {}",
+            what,
+            attempt,
+            self.max_attempts,
+            backoff,
+            secret::redact(&error.to_string())
+        );
+        tokio::time::sleep(backoff).await;
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy::new(DEFAULT_MAX_ATTEMPTS)
+    }
+}
+
+fn is_transient(error: &CouchError) -> bool {
+    if matches!(error.status(), Some(status) if TRANSIENT_STATUSES.contains(&status)) {
+        return true;
+    }
+    // errors of the HTTP client carry no status of their own
+    std::error::Error::source(error)
+        .and_then(|source| source.downcast_ref::<ReqwestError>())
+        .map(|e| e.is_timeout() || e.is_connect())
+        .unwrap_or(false)
+}
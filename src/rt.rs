@@ -0,0 +1,60 @@
+//! The tokio runtime the background work of this crate runs on.
+//!
+//! couch_rs talks to CouchDB through reqwest, which needs a tokio reactor. Within a tokio runtime, the
+//! current one is used. Tests run by another executor, such as async-std or smol, have none, so the
+//! work is handed to a runtime owned by this crate instead, whose join handles can be awaited by any
+//! executor. This covers the creation and teardown of test databases and the background tasks of the
+//! crate; the other helpers run on the calling task and so need its tokio context.
+
+use std::{future::Future, panic, sync::OnceLock};
+
+use tokio::{
+    runtime::{Builder, Handle, Runtime},
+    task::JoinHandle,
+};
+
+static FALLBACK: OnceLock<Runtime> = OnceLock::new();
+
+fn fallback() -> &'static Runtime {
+    FALLBACK.get_or_init(|| {
+        Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("couch_rs_test")
+            .enable_all()
+            .build()
+            .expect("failed to start the runtime of couch_rs_test")
+    })
+}
+
+/// Whether the calling thread runs within a tokio runtime.
+pub(crate) fn in_tokio() -> bool {
+    Handle::try_current().is_ok()
+}
+
+/// Spawns `future` on the current tokio runtime, or on the fallback runtime outside of one.
+pub(crate) fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    match Handle::try_current() {
+        Ok(handle) => handle.spawn(future),
+        Err(_) => fallback().spawn(future),
+    }
+}
+
+/// Runs `future` in place within a tokio runtime, or to completion on the fallback runtime outside of
+/// one. Panics of `future` are propagated.
+pub(crate) async fn run<F>(future: F) -> F::Output
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    if in_tokio() {
+        return future.await;
+    }
+    match fallback().spawn(future).await {
+        Ok(output) => output,
+        Err(e) => panic::resume_unwind(e.into_panic()),
+    }
+}
@@ -0,0 +1,73 @@
+//! Validation of fixture documents against JSON Schemas registered per document type.
+
+use std::path::Path;
+
+use couch_rs::error::CouchError;
+use serde_json::Value;
+
+use crate::{compat::StatusCode, fixtures::locate_docs, TestRepoConfig};
+
+/// Field naming the type of a document unless configured otherwise with
+/// [TestRepoConfig::with_schema_type_field].
+pub(crate) const DEFAULT_TYPE_FIELD: &str = "type";
+
+/// Validates the documents of the fixture `text`, read from `file`, against the schemas of `cfg`.
+/// Documents of types without a schema are accepted. The error lists every violation with the line of
+/// the offending document.
+pub(crate) fn validate_fixture(
+    cfg: &TestRepoConfig,
+    file: &Path,
+    text: &str,
+) -> Result<(), CouchError> {
+    if cfg.schemas.is_empty() {
+        return Ok(());
+    }
+
+    let mut validators = Vec::with_capacity(cfg.schemas.len());
+    for (doc_type, schema) in cfg.schemas.iter() {
+        let validator = jsonschema::validator_for(schema).map_err(|e| {
+            CouchError::new(
+                format!("Invalid JSON Schema for document type {}: {}", doc_type, e),
+                StatusCode::BAD_REQUEST,
+            )
+        })?;
+        validators.push((doc_type, validator));
+    }
+
+    let mut violations = vec![];
+    for (line, doc) in locate_docs(text) {
+        let doc_type = match doc.get(&cfg.schema_type_field).and_then(Value::as_str) {
+            Some(doc_type) => doc_type,
+            None => continue,
+        };
+        let validator = match validators.iter().find(|(t, _)| t.as_str() == doc_type) {
+            Some((_, validator)) => validator,
+            None => continue,
+        };
+        for error in validator.iter_errors(&doc) {
+            violations.push(format!(
+                "\n  {}:{}: {} document {}: {}: {}",
+                file.display(),
+                line,
+                doc_type,
+                doc.get("_id").unwrap_or(&Value::Null),
+                match error.instance_path.as_str() {
+                    "" => "/",
+                    path => path,
+                },
+                error
+            ));
+        }
+    }
+
+    match violations.is_empty() {
+        true => Ok(()),
+        false => Err(CouchError::new(
+            format!(
+                "Fixture documents do not match their schema:{}",
+                violations.concat()
+            ),
+            StatusCode::BAD_REQUEST,
+        )),
+    }
+}
@@ -0,0 +1,192 @@
+use couch_rs::{error::CouchError, Client};
+use serde_json::{json, Value};
+use tokio::runtime::{Handle, RuntimeFlavor};
+
+use crate::{
+    compat::{Method, StatusCode},
+    naming::encode_db_name,
+    registry, TestRepo, TestRepoConfig,
+};
+
+/// A section of a test whose writes are undone when it ends, returned by [TestRepo::scope]. Every
+/// document created or modified since the scope began is deleted when the scope is closed or dropped,
+/// so that several test cases can share one database without seeing each other's documents.
+///
+/// ```rust
+/// # use serde_json::json;
+/// # async fn example(repo: &couch_rs_test::TestRepo) -> Result<(), couch_rs::error::CouchError> {
+/// let scope = repo.scope().await?;
+/// repo.db.create(&mut json!({"name": "temporary"})).await?;
+/// let deleted = scope.close().await?;
+/// assert_eq!(deleted.len(), 1);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Documents are found through the changes feed, starting at the update sequence recorded when the
+/// scope began. Modified documents are deleted rather than restored, as their earlier revisions may have
+/// been compacted away; documents deleted within the scope stay deleted. In a shared database, only
+/// documents of the repository's [namespace](TestRepo::namespace) are deleted.
+///
+/// Dropping a scope blocks until its documents are deleted. Within a current-thread runtime, such as
+/// that of `#[tokio::test]`, this requires a second connection to the server, which the in-process
+/// [mock](crate::mock) cannot serve; close such scopes with [Scope::close] instead.
+#[must_use = "dropping a scope deletes the documents written within it right away"]
+pub struct Scope<'a> {
+    repo: &'a TestRepo,
+    since: String,
+    closed: bool,
+}
+
+impl TestRepo {
+    /// Begins a [Scope] recording the current update sequence of the database associated with this
+    /// instance, whose writes are undone when it is closed or dropped.
+    pub async fn scope(&self) -> Result<Scope<'_>, CouchError> {
+        Ok(Scope {
+            repo: self,
+            since: self.update_seq().await?,
+            closed: false,
+        })
+    }
+}
+
+impl Scope<'_> {
+    /// The update sequence of the database when the scope began.
+    pub fn since(&self) -> &str {
+        &self.since
+    }
+
+    /// Ends the scope, deleting every document created or modified since it began, and returns the ids
+    /// of the deleted documents.
+    pub async fn close(mut self) -> Result<Vec<String>, CouchError> {
+        self.closed = true;
+        delete_changed(&self.repo.client, &self.repo.cfg, &self.since).await
+    }
+}
+
+impl Drop for Scope<'_> {
+    fn drop(&mut self) {
+        if self.closed {
+            return;
+        }
+        let db_name = self.repo.cfg.db_name.clone();
+
+        let deleted = match Handle::try_current() {
+            Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+                tokio::task::block_in_place(|| {
+                    handle.block_on(delete_changed(
+                        &self.repo.client,
+                        &self.repo.cfg,
+                        &self.since,
+                    ))
+                })
+            }
+            // a current-thread runtime cannot make progress while this thread waits
+            _ => match self.repo.detached_cfg() {
+                Some(cfg) => {
+                    let since = self.since.clone();
+                    let (sender, receiver) = std::sync::mpsc::channel();
+                    let cleanup = registry::run_on_thread(async move {
+                        let deleted = match cfg.client() {
+                            Ok(client) => delete_changed(&client, &cfg, &since).await,
+                            Err(e) => Err(e),
+                        };
+                        let _ = sender.send(deleted);
+                    });
+                    let _ = cleanup.join();
+                    receiver.recv().unwrap_or_else(|_| {
+                        Err(CouchError::new(
+                            "cleanup thread failed".to_string(),
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                        ))
+                    })
+                }
+                None => {
+                    log::warn!(
+                        "Cannot delete the documents written within a scope of {} from a \
+                         current-thread runtime; close the scope with Scope::close instead",
+                        db_name
+                    );
+                    return;
+                }
+            },
+        };
+
+        match deleted {
+            Ok(ids) => log::debug!(
+                "Deleted {} documents written within a scope of {}",
+                ids.len(),
+                db_name
+            ),
+            Err(e) => log::error!(
+                "Failed to delete the documents written within a scope of {}: {}",
+                db_name,
+                e
+            ),
+        }
+    }
+}
+
+// deletes the documents of the database of `cfg` changed since `since`, unless already deleted or
+// outside the namespace of `cfg`, returning their ids
+async fn delete_changed(
+    client: &Client,
+    cfg: &TestRepoConfig,
+    since: &str,
+) -> Result<Vec<String>, CouchError> {
+    let db_path = encode_db_name(&cfg.db_name);
+    let namespace = cfg.namespace.as_deref().unwrap_or_default();
+    let changes: Value = client
+        .req(Method::GET, &format!("{}/_changes", db_path), None)
+        .query(&[("since", since)])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    let deletions: Vec<Value> = changes["results"]
+        .as_array()
+        .map(|results| {
+            results
+                .iter()
+                .filter(|change| change["deleted"] != Value::Bool(true))
+                .filter(|change| {
+                    change["id"]
+                        .as_str()
+                        .is_some_and(|id| id.starts_with(namespace))
+                })
+                .map(|change| {
+                    json!({
+                        "_id": change["id"],
+                        "_rev": change["changes"][0]["rev"],
+                        "_deleted": true,
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    if deletions.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let results: Value = client
+        .req(Method::POST, &format!("{}/_bulk_docs", db_path), None)
+        .body(json!({ "docs": deletions }).to_string())
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(results
+        .as_array()
+        .map(|results| {
+            results
+                .iter()
+                .filter(|result| result["ok"] == Value::Bool(true))
+                .filter_map(|result| result["id"].as_str())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default())
+}
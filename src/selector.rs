@@ -0,0 +1,140 @@
+//! A subset of Mango selector matching, for the in-memory backends.
+
+use std::cmp::Ordering;
+
+use serde_json::Value;
+
+/// Returns whether `doc` matches `selector`. Supported are implicit equality, nested fields in object
+/// or dotted form, the combination operators `$and`, `$or`, `$nor` and `$not`, and the condition
+/// operators `$eq`, `$ne`, `$gt`, `$gte`, `$lt`, `$lte`, `$in`, `$nin`, `$exists`, `$type` and
+/// `$size`. Anything else never matches.
+pub(crate) fn matches(doc: &Value, selector: &Value) -> bool {
+    match selector.as_object() {
+        Some(fields) => fields
+            .iter()
+            .all(|(field, condition)| match field.as_str() {
+                "$and" => all_match(doc, condition),
+                "$or" => any_match(doc, condition),
+                "$nor" => !any_match(doc, condition),
+                "$not" => !matches(doc, condition),
+                _ => field_matches(lookup(doc, field), condition),
+            }),
+        None => false,
+    }
+}
+
+/// Returns the fields every document matching `selector` is constrained on, in dotted form and in
+/// order of first appearance: those of its top level and of its `$and` clauses. Fields appearing only
+/// under `$or`, `$nor` or `$not` are left out, as an index on them cannot serve the query.
+pub(crate) fn fields(selector: &Value) -> Vec<String> {
+    let mut fields = vec![];
+    collect_fields("", selector, &mut fields);
+    fields
+}
+
+fn collect_fields(prefix: &str, selector: &Value, fields: &mut Vec<String>) {
+    let conditions = match selector.as_object() {
+        Some(conditions) => conditions,
+        None => return,
+    };
+    for (field, condition) in conditions {
+        match field.as_str() {
+            "$and" => {
+                for clause in condition.as_array().into_iter().flatten() {
+                    collect_fields(prefix, clause, fields);
+                }
+            }
+            _ if field.starts_with('$') => {}
+            _ => {
+                let path = format!("{}{}", prefix, field);
+                match condition {
+                    // a nested selector such as {"address": {"city": "Paris"}}
+                    Value::Object(nested) if !nested.keys().all(|k| k.starts_with('$')) => {
+                        collect_fields(&format!("{}.", path), condition, fields)
+                    }
+                    _ if !fields.contains(&path) => fields.push(path),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+fn all_match(doc: &Value, selectors: &Value) -> bool {
+    selectors
+        .as_array()
+        .map(|s| s.iter().all(|s| matches(doc, s)))
+        .unwrap_or(false)
+}
+
+fn any_match(doc: &Value, selectors: &Value) -> bool {
+    selectors
+        .as_array()
+        .map(|s| s.iter().any(|s| matches(doc, s)))
+        .unwrap_or(false)
+}
+
+fn lookup<'a>(doc: &'a Value, field: &str) -> Option<&'a Value> {
+    field
+        .split('.')
+        .try_fold(doc, |value, segment| value.get(segment))
+}
+
+fn field_matches(value: Option<&Value>, condition: &Value) -> bool {
+    match condition {
+        Value::Object(operators) if operators.keys().all(|k| k.starts_with('$')) => operators
+            .iter()
+            .all(|(operator, argument)| operator_matches(value, operator, argument)),
+        // a nested selector such as {"address": {"city": "Paris"}}
+        Value::Object(_) => value.map(|v| matches(v, condition)).unwrap_or(false),
+        _ => value == Some(condition),
+    }
+}
+
+fn operator_matches(value: Option<&Value>, operator: &str, argument: &Value) -> bool {
+    match (operator, value) {
+        ("$exists", _) => argument.as_bool() == Some(value.is_some()),
+        ("$ne", None) => true,
+        ("$nin", None) => true,
+        ("$not", _) => !field_matches(value, argument),
+        (_, None) => false,
+        ("$eq", Some(v)) => v == argument,
+        ("$ne", Some(v)) => v != argument,
+        ("$gt", Some(v)) => compare(v, argument) == Some(Ordering::Greater),
+        ("$gte", Some(v)) => matches!(
+            compare(v, argument),
+            Some(Ordering::Greater | Ordering::Equal)
+        ),
+        ("$lt", Some(v)) => compare(v, argument) == Some(Ordering::Less),
+        ("$lte", Some(v)) => matches!(compare(v, argument), Some(Ordering::Less | Ordering::Equal)),
+        ("$in", Some(v)) => argument.as_array().map(|a| a.contains(v)).unwrap_or(false),
+        ("$nin", Some(v)) => argument.as_array().map(|a| !a.contains(v)).unwrap_or(false),
+        ("$type", Some(v)) => argument.as_str() == Some(type_name(v)),
+        ("$size", Some(v)) => match (v.as_array(), argument.as_u64()) {
+            (Some(a), Some(n)) => a.len() as u64 == n,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+// orders values of the same kind; values of different kinds are not comparable
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
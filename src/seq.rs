@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+use couch_rs::{error::CouchError, types::changes::ChangeEvent};
+use serde_json::Value;
+use tokio::time::Instant;
+
+use crate::{
+    compat::Method, naming::encode_db_name, trace::trace_event, wait::timeout_error, TestRepo,
+};
+
+// time left to the server for answering a long-poll request before the request timeout of the client
+const LONGPOLL_MARGIN: Duration = Duration::from_secs(1);
+
+impl TestRepo {
+    /// Returns the current update sequence of the database associated with this instance. Capture it
+    /// before an action and pass it to [TestRepo::await_seq_advance] to see which changes the action made.
+    ///
+    /// CouchDB 2 and later use opaque string sequences; numeric sequences of older servers are returned in
+    /// their decimal form.
+    pub async fn update_seq(&self) -> Result<String, CouchError> {
+        let info = self.get_json(&encode_db_name(&self.cfg.db_name)).await?;
+        Ok(seq_to_string(&info["update_seq"]))
+    }
+
+    /// Waits until the database associated with this instance has changed since the sequence `from`, as
+    /// returned by [TestRepo::update_seq], and returns the changes made since then, one event per changed
+    /// document.
+    ///
+    /// If nothing changes before `timeout` elapses, an error with status `REQUEST_TIMEOUT` is returned.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "couch_rs_test::await_seq_advance", skip(self), fields(db = %self.db.name()))
+    )]
+    pub async fn await_seq_advance(
+        &self,
+        from: &str,
+        timeout: Duration,
+    ) -> Result<Vec<ChangeEvent>, CouchError> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let wait = match self.cfg.request_timeout {
+                Some(limit) => remaining.min(longpoll_limit(limit)),
+                None => remaining,
+            };
+            let response: Value = self
+                .client
+                .req(
+                    Method::GET,
+                    &format!("{}/_changes", encode_db_name(&self.cfg.db_name)),
+                    None,
+                )
+                .query(&[
+                    ("since", from.to_string()),
+                    ("feed", "longpoll".to_string()),
+                    ("timeout", wait.as_millis().to_string()),
+                ])
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+
+            let changes: Vec<ChangeEvent> = serde_json::from_value(response["results"].clone())?;
+            if !changes.is_empty() {
+                trace_event!(changes = changes.len(), "sequence advanced");
+                return Ok(changes);
+            }
+
+            if Instant::now() >= deadline {
+                return Err(timeout_error(format!(
+                    "Timed out after {:?} waiting for database {} to change since sequence {}",
+                    timeout,
+                    self.db.name(),
+                    from
+                )));
+            }
+        }
+    }
+}
+
+// the longest a single long-poll request may wait for changes within the request timeout `limit`,
+// shortened by the margin, or halved for timeouts too short for it
+fn longpoll_limit(limit: Duration) -> Duration {
+    limit.saturating_sub(LONGPOLL_MARGIN).max(limit / 2)
+}
+
+fn seq_to_string(seq: &Value) -> String {
+    match seq {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn longpoll_limit_leaves_a_margin() {
+        assert_eq!(
+            longpoll_limit(Duration::from_secs(10)),
+            Duration::from_secs(9)
+        );
+        assert_eq!(
+            longpoll_limit(Duration::from_secs(1)),
+            Duration::from_millis(500)
+        );
+    }
+}
@@ -0,0 +1,320 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    sync::{atomic::AtomicBool, Arc, OnceLock},
+    time::Instant,
+};
+
+use couch_rs::{error::CouchError, Client};
+use serde_json::{json, Value};
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    compat::{Method, StatusCode},
+    hooks, marker,
+    naming::{self, encode_db_name},
+    registry, summary, Siblings, TestRepo, TestRepoConfig,
+};
+
+// the databases shared by the tests of this process, keyed by server and name, with their unique names
+static SHARED: OnceLock<Mutex<HashMap<String, String>>> = OnceLock::new();
+
+// sorts after every character used in document ids, closing the id range of a namespace
+const RANGE_END: char = '\u{fff0}';
+
+impl TestRepo {
+    // joins the database shared by all instances with the same server and configured name, creating it
+    // on first use, under a namespace of its own
+    pub(crate) async fn join_shared(arg_cfg: TestRepoConfig) -> Result<TestRepo, Box<dyn Error>> {
+        #[cfg(feature = "mock")]
+        if arg_cfg.mock {
+            return Err(
+                "A shared database cannot be emulated, as each emulator serves one instance".into(),
+            );
+        }
+        #[cfg(feature = "proxy")]
+        if arg_cfg.proxy.is_some() || arg_cfg.http_log {
+            return Err("A shared database cannot be reached through a proxy".into());
+        }
+
+        let db_name = shared_database(&arg_cfg).await?;
+        // the test harness names each test's thread after the test
+        let test_name = arg_cfg
+            .test_name
+            .clone()
+            .or_else(|| std::thread::current().name().map(str::to_string))
+            .unwrap_or_else(|| "test".to_string());
+        let namespace = format!(
+            "{}-{}:",
+            naming::sanitize(&test_name),
+            naming::random_chars(&mut rand::thread_rng(), 6)
+        );
+        log::info!(
+            "Using namespace {} of shared database {} for testing",
+            namespace,
+            db_name
+        );
+
+        let cfg = TestRepoConfig {
+            namespace: Some(namespace),
+            ..arg_cfg.with_name(db_name)
+        };
+        if cfg.cleanup_on_signal {
+            registry::install_signal_handler();
+        }
+        let client = cfg.client()?;
+        let db = client.db(&cfg.db_name).await?;
+
+        let drop_token = CancellationToken::new();
+        let dump_on_drop = Arc::new(AtomicBool::new(false));
+        let torn_down = Arc::new(AtomicBool::new(false));
+        let siblings = Siblings::default();
+        let dropped_token = TestRepo::start_drop_watcher(
+            &drop_token,
+            cfg.clone(),
+            dump_on_drop.clone(),
+            torn_down.clone(),
+            siblings.clone(),
+        )
+        .await;
+
+        Ok(TestRepo {
+            db,
+            client,
+            cfg,
+            drop_token,
+            dropped_token,
+            dump_on_drop,
+            torn_down,
+            siblings,
+            _slot: None,
+            #[cfg(feature = "proxy")]
+            proxy: None,
+            #[cfg(feature = "mock")]
+            mock: None,
+            #[cfg(feature = "wiremock")]
+            stubs: None,
+        })
+    }
+
+    /// Returns the namespace prefixed to the ids of the documents of this instance, if it shares its
+    /// database with other instances; see
+    /// [TestRepoConfig::with_shared_database](crate::TestRepoConfig::with_shared_database).
+    pub fn namespace(&self) -> Option<&str> {
+        self.cfg.namespace.as_deref()
+    }
+
+    /// Returns `id` prefixed with the [namespace](TestRepo::namespace) of this instance, for documents
+    /// written to a shared database through [TestRepo::db](TestRepo#structfield.db) rather than the
+    /// seeding helpers. Ids of design and local documents, ids already carrying the namespace, and all
+    /// ids of an instance with a database of its own are returned unchanged.
+    ///
+    /// ```rust
+    /// # use serde_json::json;
+    /// # async fn example(repo: &couch_rs_test::TestRepo) -> Result<(), couch_rs::error::CouchError> {
+    /// repo.db
+    ///     .create(&mut json!({"_id": repo.namespaced_id("alice")}))
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn namespaced_id(&self, id: &str) -> String {
+        match &self.cfg.namespace {
+            Some(namespace)
+                if !id.starts_with(namespace.as_str())
+                    && !id.starts_with("_design/")
+                    && !id.starts_with("_local/") =>
+            {
+                format!("{}{}", namespace, id)
+            }
+            _ => id.to_string(),
+        }
+    }
+
+    // removes the namespace of this instance from the `_id` of `doc`, so that it reads as written
+    pub(crate) fn strip_namespace(&self, doc: &mut Value) {
+        let namespace = match self.namespace() {
+            Some(namespace) => namespace,
+            None => return,
+        };
+        if let Some(fields) = doc.as_object_mut() {
+            let id = fields.get("_id").and_then(Value::as_str);
+            if let Some(id) = id.and_then(|id| id.strip_prefix(namespace)) {
+                fields.insert("_id".to_string(), Value::from(id.to_string()));
+            }
+        }
+    }
+
+    // the start and end keys of the ids of the namespace of this instance, if any
+    pub(crate) fn namespace_range(&self) -> Option<(String, String)> {
+        self.cfg
+            .namespace
+            .as_ref()
+            .map(|namespace| (namespace.clone(), format!("{}{}", namespace, RANGE_END)))
+    }
+
+    // restricts the Mango `selector` to the documents of the namespace of this instance, if any
+    pub(crate) fn namespaced_selector(&self, selector: Value) -> Value {
+        match self.namespace_range() {
+            Some((start, end)) => json!({
+                "$and": [selector, {"_id": {"$gte": start, "$lt": end}}],
+            }),
+            None => selector,
+        }
+    }
+
+    // counts the documents of the namespace `namespace` of the database associated with this instance
+    pub(crate) async fn namespace_doc_count(&self, namespace: &str) -> Result<u64, CouchError> {
+        Ok(namespace_rows(&self.client, &self.cfg.db_name, namespace)
+            .await?
+            .len() as u64)
+    }
+}
+
+/// Deletes the documents of `namespace` from the shared database of `cfg`, in place of destroying the
+/// database, which is left to the other instances sharing it and destroyed when the process exits.
+pub(crate) async fn clear_namespace(cfg: &TestRepoConfig, namespace: &str) {
+    let cleared = match cfg.client() {
+        Ok(client) => delete_namespace(&client, &cfg.db_name, namespace).await,
+        Err(e) => Err(e),
+    };
+    match cleared {
+        Ok(n) => log::info!(
+            "Deleted {} documents of namespace {} of shared database {}",
+            n,
+            namespace,
+            cfg.db_name
+        ),
+        Err(e) => log::error!(
+            "Error while cleaning up namespace {} of {}: {}",
+            namespace,
+            cfg.db_name,
+            e
+        ),
+    }
+}
+
+// returns the unique name of the database shared under the configured name, creating it on first use
+async fn shared_database(cfg: &TestRepoConfig) -> Result<String, Box<dyn Error>> {
+    let name = match cfg.resolved_shard() {
+        Some(shard) => format!("{}-{}-shared", cfg.db_name, shard),
+        None => format!("{}-shared", cfg.db_name),
+    };
+    let key = format!("{} {}", cfg.uri.trim_end_matches('/'), name);
+
+    // held while creating, so that tests starting together create the database once
+    let mut shared = SHARED.get_or_init(Default::default).lock().await;
+    if let Some(db_name) = shared.get(&key) {
+        return Ok(db_name.clone());
+    }
+
+    let start = Instant::now();
+    let client = cfg.client()?;
+    let prefixed_name = format!("{}{}", naming::env_prefix(), name);
+    let mut rng = naming::name_rng(&name, naming::resolve_seed(cfg.seed));
+    let mut attempt: u32 = 0;
+    let (db, db_name) = loop {
+        attempt += 1;
+        let db_name = cfg.name_strategy.unique_name(&prefixed_name, &mut rng);
+        naming::validate_db_name(&db_name)?;
+
+        log::info!("Creating shared database {} for testing", db_name);
+        let created = cfg
+            .retry
+            .run("Creating a shared test database", || {
+                client.make_db(&db_name)
+            })
+            .await;
+        match created {
+            Ok(db) => break (db, db_name),
+            Err(e)
+                if e.status() == Some(StatusCode::PRECONDITION_FAILED)
+                    && attempt <= cfg.collision_retries =>
+            {
+                log::warn!(
+                    "Database {} already exists; retrying with a new name",
+                    db_name
+                );
+            }
+            Err(e) => return Err(e.into()),
+        }
+    };
+
+    // destroyed when the process exits, once every test sharing it is done
+    let db_cfg = cfg.clone().with_name(db_name.clone());
+    registry::register_shared(&db_cfg, cfg.uri.clone());
+    summary::record_created(start.elapsed());
+
+    let marker = marker::Marker::new(&cfg.db_name, cfg.resolved_shard().as_deref());
+    if let Err(e) = marker::write_marker(&client, &db_name, &marker).await {
+        log::warn!("Failed to write marker document to {}: {}", db_name, e);
+    }
+    hooks::run(&cfg.on_created, &db).await;
+
+    shared.insert(key, db_name.clone());
+    Ok(db_name)
+}
+
+// the rows of `_all_docs` within `namespace`
+async fn namespace_rows(
+    client: &Client,
+    db_name: &str,
+    namespace: &str,
+) -> Result<Vec<Value>, CouchError> {
+    let end = format!("{}{}", namespace, RANGE_END);
+    let all_docs: Value = client
+        .req(
+            Method::GET,
+            &format!("{}/_all_docs", encode_db_name(db_name)),
+            None,
+        )
+        .query(&[
+            ("start_key", serde_json::to_string(namespace)?),
+            ("end_key", serde_json::to_string(&end)?),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(all_docs["rows"].as_array().cloned().unwrap_or_default())
+}
+
+// deletes every document of `namespace`, returning the number deleted
+async fn delete_namespace(
+    client: &Client,
+    db_name: &str,
+    namespace: &str,
+) -> Result<usize, CouchError> {
+    let deletions: Vec<Value> = namespace_rows(client, db_name, namespace)
+        .await?
+        .iter()
+        .map(|row| json!({"_id": row["id"], "_rev": row["value"]["rev"], "_deleted": true}))
+        .collect();
+    if deletions.is_empty() {
+        return Ok(0);
+    }
+
+    let results: Value = client
+        .req(
+            Method::POST,
+            &format!("{}/_bulk_docs", encode_db_name(db_name)),
+            None,
+        )
+        .body(json!({ "docs": deletions }).to_string())
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(results
+        .as_array()
+        .map(|results| {
+            results
+                .iter()
+                .filter(|r| r["ok"] == Value::Bool(true))
+                .count()
+        })
+        .unwrap_or_default())
+}
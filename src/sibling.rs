@@ -0,0 +1,49 @@
+use std::time::Instant;
+
+use couch_rs::{database::Database, error::CouchError};
+
+use crate::{marker, naming, registry, summary, TestRepo};
+
+impl TestRepo {
+    /// Creates another database next to the database associated with this instance, for tests of code
+    /// that writes to a secondary database, such as an audit log, alongside the primary one. The
+    /// database is named after the primary database and `name`, for example `users-a1b2c3-audit`, so it
+    /// is as unique as the primary database, and it is destroyed along with it when this instance is
+    /// dropped. Characters of `name` that CouchDB does not allow in database names are replaced by
+    /// underscores.
+    ///
+    /// ```rust
+    /// # async fn example(repo: &couch_rs_test::TestRepo) -> Result<(), couch_rs::error::CouchError> {
+    /// let audit = repo.create_sibling("audit").await?;
+    /// let mut entry = serde_json::json!({"action": "login"});
+    /// audit.create(&mut entry).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn create_sibling(&self, name: &str) -> Result<Database, CouchError> {
+        let start = Instant::now();
+        let db_name = format!("{}-{}", self.cfg.db_name, naming::sanitize(name));
+        naming::validate_db_name(&db_name)?;
+
+        log::info!("Creating database {} for testing", db_name);
+        let db = self
+            .cfg
+            .retry
+            .run("Creating a sibling database", || self.client.make_db(&db_name))
+            .await?;
+        self.siblings
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(db_name.clone());
+        if registry::register_sibling(&self.cfg.db_name, &db_name) {
+            summary::record_created(start.elapsed());
+        }
+
+        // the marker names the primary database, as the sibling has no configured name of its own
+        let marker = marker::Marker::new(&self.cfg.db_name, self.cfg.shard.as_deref());
+        if let Err(e) = marker::write_marker(&self.client, &db_name, &marker).await {
+            log::warn!("Failed to write marker document to {}: {}", db_name, e);
+        }
+        Ok(db)
+    }
+}
@@ -0,0 +1,87 @@
+use std::{
+    error::Error,
+    fs::{self, File, OpenOptions, TryLockError},
+    path::PathBuf,
+    time::Duration,
+};
+
+use crate::{naming::fnv1a, trace::trace_event};
+
+/// Environment variable capping the number of test databases that may exist at once on a server, across
+/// all processes, when none is set with
+/// [TestRepoConfig::with_global_db_limit](crate::TestRepoConfig::with_global_db_limit).
+pub(crate) const MAX_DATABASES_ENV_VAR: &str = "COUCH_TEST_MAX_DATABASES";
+
+/// Environment variable naming the directory holding the lock files of the database slots.
+pub(crate) const LOCK_DIR_ENV_VAR: &str = "COUCH_TEST_LOCK_DIR";
+
+// how often a process waiting for a slot checks whether one has been freed
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// One of a limited number of slots for test databases on a server, shared by all processes on this
+/// machine. A slot is a lock file held with an exclusive lock, which the operating system releases when
+/// the slot is dropped or the process exits, however abruptly.
+#[derive(Debug)]
+pub(crate) struct Slot {
+    // released when the file is closed
+    _file: File,
+}
+
+/// Returns the limit on concurrent test databases set in the environment, if any.
+pub(crate) fn env_limit() -> Option<usize> {
+    let value = std::env::var(MAX_DATABASES_ENV_VAR).ok()?;
+    match value.trim().parse() {
+        Ok(0) | Err(_) => {
+            log::warn!(
+                "Ignoring {}={}; the limit must be a positive integer",
+                MAX_DATABASES_ENV_VAR,
+                value
+            );
+            None
+        }
+        Ok(limit) => Some(limit),
+    }
+}
+
+/// Waits until one of the `limit` slots for test databases on the server at `uri` is free and takes it.
+pub(crate) async fn acquire(uri: &str, limit: usize) -> Result<Slot, Box<dyn Error>> {
+    let dir = std::env::var_os(LOCK_DIR_ENV_VAR)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| std::env::temp_dir().join("couch_rs_test"));
+    fs::create_dir_all(&dir)?;
+    // slots are counted per server, so suites against different servers do not hold each other up
+    let server = format!("{:016x}", fnv1a(uri.trim_end_matches('/')));
+
+    let mut waiting = false;
+    loop {
+        for i in 0..limit {
+            let path = dir.join(format!("{}-{}.lock", server, i));
+            let file = OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(&path)?;
+            match file.try_lock() {
+                Ok(()) => {
+                    trace_event!(slot = i, limit, "acquired database slot");
+                    return Ok(Slot { _file: file });
+                }
+                Err(TryLockError::WouldBlock) => {}
+                Err(TryLockError::Error(e)) => {
+                    return Err(
+                        format!("Failed to lock database slot {}: {}", path.display(), e).into(),
+                    )
+                }
+            }
+        }
+
+        if !waiting {
+            log::info!(
+                "All {} test database slots are taken; waiting for one to be freed",
+                limit
+            );
+            waiting = true;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
@@ -0,0 +1,178 @@
+use std::{collections::BTreeMap, error::Error, fs, path::Path};
+
+use serde_json::{json, Value};
+
+use crate::{dump, secret::REDACTED, TestRepo};
+
+/// Environment variable that, when set to `1`, makes [TestRepo::assert_snapshot] rewrite snapshot files
+/// instead of comparing against them.
+const UPDATE_ENV_VAR: &str = "COUCH_TEST_UPDATE_SNAPSHOTS";
+
+impl TestRepo {
+    /// Reads all documents of the database associated with this instance, including design documents,
+    /// in id order, with the fields at the JSON pointers in `redactions` (such as `/_rev` or
+    /// `/updated_at`) masked. Fields missing from a document are left missing. In a shared database,
+    /// only the documents of this instance's [namespace](TestRepo::namespace) and the design documents
+    /// are read, with the namespace removed from their ids, so that snapshots match across runs.
+    pub async fn snapshot(&self, redactions: &[&str]) -> Result<Vec<Value>, Box<dyn Error>> {
+        let mut docs = dump::all_docs(&self.client, &self.cfg.db_name).await?;
+        if let Some(namespace) = self.namespace() {
+            docs.retain(|doc| {
+                let id = doc["_id"].as_str().unwrap_or_default();
+                id.starts_with(namespace) || id.starts_with("_design/")
+            });
+            for doc in docs.iter_mut() {
+                self.strip_namespace(doc);
+            }
+            docs.sort_by(|a, b| a["_id"].as_str().cmp(&b["_id"].as_str()));
+        }
+        for doc in docs.iter_mut() {
+            redact(doc, redactions);
+        }
+        Ok(docs)
+    }
+
+    /// Asserts that the documents of the database associated with this instance match the snapshot file
+    /// at `path`, after masking the fields at the JSON pointers in `redactions` on both sides, so that
+    /// volatile fields such as revisions, timestamps or tokens do not cause false differences.
+    ///
+    /// The snapshot file uses the format of [TestRepo::dump_to]. If it does not exist yet, or the
+    /// `COUCH_TEST_UPDATE_SNAPSHOTS` environment variable is set to `1`, it is written from the current
+    /// documents instead and the assertion passes.
+    ///
+    /// ```rust
+    /// # async fn example(repo: &couch_rs_test::TestRepo) {
+    /// repo.assert_snapshot("tests/snapshots/checkout.json", &["/_rev", "/updated_at"])
+    ///     .await;
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the documents differ from the snapshot, listing missing (`-`), unexpected (`+`) and
+    /// changed (`~`) documents, or if the database or the snapshot file cannot be read.
+    pub async fn assert_snapshot<P: AsRef<Path>>(&self, path: P, redactions: &[&str]) {
+        let path = path.as_ref();
+        let actual = self
+            .snapshot(redactions)
+            .await
+            .unwrap_or_else(|e| panic!("Failed to read documents of {}: {}", self.cfg.db_name, e));
+
+        if !path.exists() || std::env::var(UPDATE_ENV_VAR).as_deref() == Ok("1") {
+            write_snapshot(path, &actual)
+                .unwrap_or_else(|e| panic!("Failed to write snapshot {}: {}", path.display(), e));
+            log::info!(
+                "Wrote snapshot of {} documents to {}",
+                actual.len(),
+                path.display()
+            );
+            return;
+        }
+
+        let mut expected = read_snapshot(path)
+            .unwrap_or_else(|e| panic!("Failed to read snapshot {}: {}", path.display(), e));
+        for doc in expected.iter_mut() {
+            redact(doc, redactions);
+        }
+
+        let diff = diff_docs(&expected, &actual);
+        if !diff.is_empty() {
+            panic!(
+                "Documents of {} differ from snapshot {}:{}",
+                self.cfg.db_name,
+                path.display(),
+                diff
+            );
+        }
+    }
+}
+
+/// Replaces the fields at `pointers` in `doc` by a placeholder.
+fn redact(doc: &mut Value, pointers: &[&str]) {
+    for pointer in pointers {
+        if let Some(value) = doc.pointer_mut(pointer) {
+            *value = Value::from(REDACTED);
+        }
+    }
+}
+
+fn write_snapshot(path: &Path, docs: &[Value]) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(
+        path,
+        serde_json::to_string_pretty(&json!({ "docs": docs }))?,
+    )?;
+    Ok(())
+}
+
+fn read_snapshot(path: &Path) -> Result<Vec<Value>, Box<dyn Error>> {
+    let snapshot: Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+    match snapshot["docs"].as_array() {
+        Some(docs) => Ok(docs.clone()),
+        None => Err(format!("{} has no `docs` array", path.display()).into()),
+    }
+}
+
+// lists the documents missing from or unexpected in `actual`, and the fields of documents present in
+// both that differ, by id
+pub(crate) fn diff_docs(expected: &[Value], actual: &[Value]) -> String {
+    let by_id = |docs: &[Value]| -> BTreeMap<String, Value> {
+        docs.iter()
+            .map(|doc| {
+                (
+                    doc["_id"].as_str().unwrap_or_default().to_string(),
+                    doc.clone(),
+                )
+            })
+            .collect()
+    };
+    let expected = by_id(expected);
+    let actual = by_id(actual);
+
+    let mut diff = String::new();
+    for (id, doc) in expected.iter() {
+        match actual.get(id) {
+            None => diff.push_str(&format!("\n  - {}", doc)),
+            Some(other) if other != doc => {
+                let mut fields = vec![];
+                diff_fields("", doc, other, &mut fields);
+                diff.push_str(&format!("\n  ~ {}", id));
+                for field in fields {
+                    diff.push_str(&format!("\n      {}", field));
+                }
+            }
+            Some(_) => {}
+        }
+    }
+    for (id, doc) in actual.iter() {
+        if !expected.contains_key(id) {
+            diff.push_str(&format!("\n  + {}", doc));
+        }
+    }
+    diff
+}
+
+// describes the differences between `expected` and `actual` by JSON pointer, descending into objects
+pub(crate) fn diff_fields(pointer: &str, expected: &Value, actual: &Value, out: &mut Vec<String>) {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            for (key, value) in expected.iter() {
+                let field = format!("{}/{}", pointer, key.replace('~', "~0").replace('/', "~1"));
+                match actual.get(key) {
+                    Some(other) => diff_fields(&field, value, other, out),
+                    None => out.push(format!("{}: {} => (missing)", field, value)),
+                }
+            }
+            for (key, value) in actual.iter().filter(|(k, _)| !expected.contains_key(*k)) {
+                let field = format!("{}/{}", pointer, key.replace('~', "~0").replace('/', "~1"));
+                out.push(format!("{}: (missing) => {}", field, value));
+            }
+        }
+        (expected, actual) if expected != actual => {
+            out.push(format!("{}: {} => {}", pointer, expected, actual))
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,136 @@
+//! Revision-tracking document storage shared by the in-memory backends.
+
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+use crate::naming::fnv1a;
+
+/// Why a write to a [DocStore] was refused, named after the CouchDB error it mirrors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum WriteError {
+    /// The document exists and the write did not name its current revision.
+    Conflict,
+    /// The document is not a JSON object.
+    BadRequest,
+}
+
+impl WriteError {
+    /// The `error` and `reason` CouchDB responds with.
+    #[cfg(feature = "mock")]
+    pub(crate) fn describe(&self) -> (&'static str, &'static str) {
+        match self {
+            WriteError::Conflict => ("conflict", "Document update conflict."),
+            WriteError::BadRequest => ("bad_request", "Document must be a JSON object"),
+        }
+    }
+}
+
+/// Documents by id, including the tombstones of deleted documents.
+#[derive(Debug, Default)]
+pub(crate) struct DocStore {
+    docs: BTreeMap<String, Value>,
+    update_seq: u64,
+    // the update sequence of the latest write to each document
+    seqs: BTreeMap<String, u64>,
+}
+
+impl DocStore {
+    /// Writes `doc`, creating, updating or (with `_deleted`) deleting a document, and sets `_id` and the
+    /// new `_rev` on it. Returns the id and new revision.
+    pub(crate) fn write(&mut self, doc: &mut Value) -> Result<(String, String), WriteError> {
+        let fields = doc.as_object_mut().ok_or(WriteError::BadRequest)?;
+        let id = match fields.get("_id").and_then(Value::as_str) {
+            Some(id) => id.to_string(),
+            None => uuid::Builder::from_random_bytes(rand::random())
+                .into_uuid()
+                .simple()
+                .to_string(),
+        };
+
+        // a write must name the current revision of an existing document; a deleted document may be
+        // recreated without one
+        let current = self.docs.get(&id);
+        let current_rev = current.map(|d| d["_rev"].clone());
+        let deleted = current.map(is_deleted).unwrap_or(true);
+        let given_rev = fields.get("_rev").cloned();
+        if !deleted && current_rev != given_rev {
+            return Err(WriteError::Conflict);
+        }
+        if deleted && given_rev.is_some() && given_rev != current_rev {
+            return Err(WriteError::Conflict);
+        }
+        let generation = current_rev
+            .as_ref()
+            .and_then(Value::as_str)
+            .and_then(|rev| rev.split('-').next())
+            .and_then(|n| n.parse::<u64>().ok())
+            .unwrap_or(0)
+            + 1;
+
+        fields.insert("_id".to_string(), Value::from(id.clone()));
+        fields.remove("_rev");
+        let rev = format!(
+            "{}-{:016x}",
+            generation,
+            fnv1a(&Value::Object(fields.clone()).to_string())
+        );
+        fields.insert("_rev".to_string(), Value::from(rev.clone()));
+
+        let stored = if fields.get("_deleted") == Some(&Value::Bool(true)) {
+            serde_json::json!({"_id": id, "_rev": rev, "_deleted": true})
+        } else {
+            doc.clone()
+        };
+        self.docs.insert(id.clone(), stored);
+        self.update_seq += 1;
+        self.seqs.insert(id.clone(), self.update_seq);
+        Ok((id, rev))
+    }
+
+    /// The document with the given id, unless it does not exist or has been deleted.
+    pub(crate) fn get(&self, id: &str) -> Option<&Value> {
+        self.docs.get(id).filter(|doc| !is_deleted(doc))
+    }
+
+    /// Whether a document with the given id existed and has been deleted.
+    #[cfg(feature = "mock")]
+    pub(crate) fn is_deleted(&self, id: &str) -> bool {
+        self.docs.get(id).map(is_deleted).unwrap_or(false)
+    }
+
+    /// All documents that have not been deleted, in id order.
+    pub(crate) fn live(&self) -> impl DoubleEndedIterator<Item = (&String, &Value)> {
+        self.docs.iter().filter(|(_, doc)| !is_deleted(doc))
+    }
+
+    /// Number of documents that have been deleted.
+    #[cfg(feature = "mock")]
+    pub(crate) fn deleted_count(&self) -> usize {
+        self.docs.values().filter(|doc| is_deleted(doc)).count()
+    }
+
+    /// Number of writes made to the store.
+    #[cfg(feature = "mock")]
+    pub(crate) fn update_seq(&self) -> u64 {
+        self.update_seq
+    }
+
+    /// The documents written after the update sequence `since`, deleted ones included, with the sequence
+    /// of their latest write, in the order of those writes.
+    #[cfg(feature = "mock")]
+    pub(crate) fn changes_since(&self, since: u64) -> Vec<(u64, &Value)> {
+        let mut changes: Vec<(u64, &Value)> = self
+            .seqs
+            .iter()
+            .filter(|(_, seq)| **seq > since)
+            .filter_map(|(id, seq)| self.docs.get(id).map(|doc| (*seq, doc)))
+            .collect();
+        changes.sort_by_key(|(seq, _)| *seq);
+        changes
+    }
+}
+
+fn is_deleted(doc: &Value) -> bool {
+    doc.get("_deleted") == Some(&Value::Bool(true))
+}
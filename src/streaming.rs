@@ -0,0 +1,152 @@
+use std::time::Instant;
+
+use couch_rs::{document::TypedCouchDocument, error::CouchError};
+use futures_util::{stream, Stream, StreamExt, TryStreamExt};
+use serde_json::Value;
+
+use crate::{
+    compat::Method, hooks, naming::encode_db_name, trace::trace_event, SeedProgress, TestRepo,
+};
+
+const PAGE_SIZE: usize = 1000;
+
+impl TestRepo {
+    /// Pushes documents produced by `data` to the database associated with this instance, like
+    /// [TestRepo::with_data] but without materializing the full data set: documents are collected into
+    /// batches of [TestRepoConfig::with_batch_size](crate::TestRepoConfig::with_batch_size) as the stream
+    /// yields them, and each batch is dropped once written. Use this to generate large fixtures lazily on
+    /// memory-constrained CI runners.
+    ///
+    /// Returns the number of documents pushed. An iterator can be seeded by wrapping it in
+    /// `futures::stream::iter`.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "couch_rs_test::seed", skip_all, fields(db = %self.db.name()))
+    )]
+    pub async fn with_data_stream<S, St>(&self, data: St) -> Result<usize, CouchError>
+    where
+        S: TypedCouchDocument,
+        St: Stream<Item = S>,
+    {
+        let start = Instant::now();
+        let mut progress = SeedProgress {
+            batches_done: 0,
+            batches_total: None,
+            docs_done: 0,
+            docs_total: None,
+        };
+        let (written, failed) = data
+            .chunks(self.cfg.batch_size)
+            .map(|mut batch| async move {
+                let result = self.bulk_docs(&mut batch).await?;
+                self.resolve_collisions(&mut batch, &result).await?;
+                Ok::<_, CouchError>(result)
+            })
+            .buffered(self.cfg.parallel_batches)
+            .try_fold((0, 0), |(written, failed), result| {
+                let ok = result.iter().filter(|r| r.is_ok()).count();
+                progress.batches_done += 1;
+                progress.docs_done += result.len();
+                if let Some(report) = &self.cfg.progress {
+                    report(&progress);
+                }
+                async move { Ok((written + ok, failed + result.len() - ok)) }
+            })
+            .await?;
+
+        self.record_seeded(written, start.elapsed());
+        trace_event!(written, failed, "seeded test database");
+        hooks::run(&self.cfg.after_seed, &self.db).await;
+        Ok(written + failed)
+    }
+
+    /// Streams all documents of the database associated with this instance, deserialized into `T`, in
+    /// document id order. Documents are fetched in pages of 1000 as the stream is consumed, so assertions
+    /// over large result sets do not need to hold every document in memory. Design documents are skipped,
+    /// and in a shared database only the documents of this instance's [namespace](TestRepo::namespace)
+    /// are read.
+    ///
+    /// ```rust
+    /// # use futures_util::TryStreamExt;
+    /// # use serde_json::Value;
+    /// # async fn example(repo: &couch_rs_test::TestRepo) -> Result<(), couch_rs::error::CouchError> {
+    /// let mut docs = std::pin::pin!(repo.all_docs_stream::<Value>());
+    /// while let Some(doc) = docs.try_next().await? {
+    ///     assert!(doc.get("name").is_some());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn all_docs_stream<T: TypedCouchDocument>(
+        &self,
+    ) -> impl Stream<Item = Result<T, CouchError>> + '_ {
+        // the state is the key to continue after, or None once the last page has been read
+        stream::try_unfold(Some(None), move |next: Option<Option<String>>| async move {
+            let after = match next {
+                Some(after) => after,
+                None => return Ok(None),
+            };
+            let (docs, next) = self.all_docs_page::<T>(after.as_deref()).await?;
+            Ok::<_, CouchError>(Some((
+                stream::iter(docs.into_iter().map(Ok)),
+                next.map(Some),
+            )))
+        })
+        .try_flatten()
+    }
+
+    // reads the page of documents following the id `after`, returning the id to continue after if more
+    // pages may follow
+    async fn all_docs_page<T: TypedCouchDocument>(
+        &self,
+        after: Option<&str>,
+    ) -> Result<(Vec<T>, Option<String>), CouchError> {
+        let mut query = vec![
+            ("include_docs".to_string(), "true".to_string()),
+            ("limit".to_string(), PAGE_SIZE.to_string()),
+        ];
+        if let Some(key) = after {
+            query.push(("start_key".to_string(), serde_json::to_string(key)?));
+            query.push(("skip".to_string(), "1".to_string()));
+        }
+        if let Some((start, end)) = self.namespace_range() {
+            if after.is_none() {
+                query.push(("start_key".to_string(), serde_json::to_string(&start)?));
+            }
+            query.push(("end_key".to_string(), serde_json::to_string(&end)?));
+        }
+
+        let page: Value = self
+            .client
+            .req(
+                Method::GET,
+                &format!("{}/_all_docs", encode_db_name(&self.cfg.db_name)),
+                None,
+            )
+            .query(&query)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let rows = page["rows"].as_array().cloned().unwrap_or_default();
+
+        let docs = rows
+            .iter()
+            .filter(|row| {
+                !row["id"]
+                    .as_str()
+                    .map(|id| id.starts_with("_design/"))
+                    .unwrap_or(false)
+            })
+            .filter_map(|row| row.get("doc").cloned())
+            .map(serde_json::from_value)
+            .collect::<Result<Vec<T>, _>>()?;
+
+        let next = match rows.last().and_then(|row| row["id"].as_str()) {
+            Some(id) if rows.len() == PAGE_SIZE => Some(id.to_string()),
+            _ => None,
+        };
+        Ok((docs, next))
+    }
+}
@@ -0,0 +1,170 @@
+//! A [wiremock](https://docs.rs/wiremock) server standing in for CouchDB, for testing how repository code
+//! handles failures that a real server rarely produces on demand.
+//!
+//! [TestRepo::stubbed](crate::TestRepo::stubbed) starts a server that answers the requests needed to
+//! create, open and destroy a test database, and returns the repo together with a [Stubs] handle. Every
+//! other request is answered with status 404 until a response for it is stubbed:
+//!
+//! ```rust
+//! # use couch_rs_test::{TestRepo, TestRepoConfig};
+//! # use serde_json::json;
+//! # async fn example() {
+//! let (repo, stubs) = TestRepo::stubbed(TestRepoConfig::new("", "", "", "orders"))
+//!     .await
+//!     .unwrap();
+//! stubs.fail_bulk_docs(500).await;
+//!
+//! assert!(repo.with_data(&mut [json!({"total": 3})]).await.is_err());
+//! # }
+//! ```
+//!
+//! Stubs take precedence over the built-in responses, so even database creation and destruction can be
+//! made to fail. For anything the helpers do not cover, mount a [wiremock::Mock] on [Stubs::server].
+//!
+//! Only available with the `wiremock` feature.
+
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use wiremock::{
+    matchers::{method, path, path_regex},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use crate::{
+    compat::{Method, StatusCode},
+    naming::encode_db_name,
+};
+
+// lower than the default priority of wiremock, so that any stub overrides the built-in responses
+const BUILT_IN_PRIORITY: u8 = 10;
+
+/// Handle to the stub server of a [TestRepo](crate::TestRepo) created with
+/// [TestRepo::stubbed](crate::TestRepo::stubbed), used to stub responses of endpoints of its database.
+#[derive(Clone, Debug)]
+pub struct Stubs {
+    server: Arc<MockServer>,
+    db_name: String,
+}
+
+impl Stubs {
+    /// Starts a stub server answering the requests that create, open and destroy any database.
+    pub(crate) async fn start() -> Stubs {
+        let server = MockServer::start().await;
+        let db = r"^/[^/_][^/]*/?$";
+
+        let built_in = [
+            Mock::given(method("GET")).and(path("/")).respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"couchdb": "Welcome", "version": "3.3.3"})),
+            ),
+            Mock::given(method("HEAD"))
+                .and(path_regex(db))
+                .respond_with(ResponseTemplate::new(200)),
+            Mock::given(method("GET"))
+                .and(path_regex(db))
+                .respond_with(ResponseTemplate::new(200).set_body_json(db_info())),
+            Mock::given(method("PUT"))
+                .and(path_regex(db))
+                .respond_with(ResponseTemplate::new(201).set_body_json(json!({"ok": true}))),
+            Mock::given(method("DELETE"))
+                .and(path_regex(db))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({"ok": true}))),
+            // the marker document written into every test database
+            Mock::given(method("PUT"))
+                .and(path_regex(r"^/[^/]+/_local/.+$"))
+                .respond_with(
+                    ResponseTemplate::new(201).set_body_json(json!({"ok": true, "rev": "0-1"})),
+                ),
+        ];
+        for mock in built_in {
+            mock.with_priority(BUILT_IN_PRIORITY).mount(&server).await;
+        }
+
+        Stubs {
+            server: Arc::new(server),
+            db_name: String::new(),
+        }
+    }
+
+    pub(crate) fn for_db(self, db_name: &str) -> Stubs {
+        Stubs {
+            db_name: db_name.to_string(),
+            ..self
+        }
+    }
+
+    /// The uri of the stub server.
+    pub fn uri(&self) -> String {
+        self.server.uri()
+    }
+
+    /// The underlying wiremock server, for mounting mocks the helpers do not cover and for inspecting the
+    /// requests received.
+    pub fn server(&self) -> &MockServer {
+        &self.server
+    }
+
+    /// Answer `method` requests to `endpoint` of the test database with `status` and the JSON `body`.
+    /// `endpoint` is the path below the database, such as `_find` or a document id; an empty `endpoint`
+    /// addresses the database itself.
+    pub async fn respond(&self, method: Method, endpoint: &str, status: u16, body: Value) {
+        Mock::given(wiremock::matchers::method(method.as_str()))
+            .and(path(self.path(endpoint)))
+            .respond_with(ResponseTemplate::new(status).set_body_json(body))
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Answer `method` requests to `endpoint` of the test database with `status` and an error body in the
+    /// form CouchDB uses, whose `error` is derived from the status, for example `internal_server_error`.
+    pub async fn fail(&self, method: Method, endpoint: &str, status: u16) {
+        let error = StatusCode::from_u16(status)
+            .ok()
+            .and_then(|s| s.canonical_reason())
+            .unwrap_or("unknown_error")
+            .to_lowercase()
+            .replace(' ', "_");
+        let body = json!({"error": error, "reason": "stubbed failure"});
+        self.respond(method, endpoint, status, body).await;
+    }
+
+    /// Fail every bulk write to the test database with `status`, including the writes of
+    /// [TestRepo::with_data](crate::TestRepo::with_data).
+    pub async fn fail_bulk_docs(&self, status: u16) {
+        self.fail(Method::POST, "_bulk_docs", status).await;
+    }
+
+    /// Fail every Mango query of the test database with `status`.
+    pub async fn fail_find(&self, status: u16) {
+        self.fail(Method::POST, "_find", status).await;
+    }
+
+    /// Fail every read of the document with the given id with `status`.
+    pub async fn fail_get(&self, id: &str, status: u16) {
+        self.fail(Method::GET, id, status).await;
+    }
+
+    fn path(&self, endpoint: &str) -> String {
+        match endpoint.trim_start_matches('/') {
+            "" => format!("/{}", encode_db_name(&self.db_name)),
+            endpoint => format!("/{}/{}", encode_db_name(&self.db_name), endpoint),
+        }
+    }
+}
+
+fn db_info() -> Value {
+    json!({
+        "db_name": "stubbed",
+        "doc_count": 0,
+        "doc_del_count": 0,
+        "update_seq": "0-stubbed",
+        "purge_seq": "0-stubbed",
+        "compact_running": false,
+        "disk_format_version": 8,
+        "instance_start_time": "0",
+        "sizes": {"active": 0, "external": 0, "file": 0},
+        "cluster": {"q": 1, "n": 1, "w": 1, "r": 1},
+        "props": {},
+    })
+}
@@ -0,0 +1,152 @@
+use std::{
+    fs,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use crate::{leak_report, marker::now_secs, LeakedDatabase};
+
+/// Environment variable selecting where the [RunSummary] is written when the process exits: `stderr`,
+/// or the path of a JSON file.
+const SUMMARY_ENV_VAR: &str = "COUCH_TEST_SUMMARY";
+
+// when the first test database of this process was created, as an instant and in seconds since the epoch
+static STARTED: OnceLock<(Instant, u64)> = OnceLock::new();
+static DOCS_SEEDED: AtomicU64 = AtomicU64::new(0);
+static CREATE_MICROS: AtomicU64 = AtomicU64::new(0);
+static SEED_MICROS: AtomicU64 = AtomicU64::new(0);
+static DESTROY_MICROS: AtomicU64 = AtomicU64::new(0);
+
+/// What the test databases of this process cost, as returned by [run_summary]: how many were created
+/// and destroyed, how many documents were seeded, the time spent on each, and the databases leaked.
+/// Archiving it from each CI run allows tracking the health of the test infrastructure over time, such
+/// as seeding slowing down as fixtures grow.
+///
+/// Durations are summed over all test databases, so they exceed the wall time when tests run in
+/// parallel. Databases of the in-process mock server are not counted.
+#[derive(Serialize, Clone, Debug)]
+pub struct RunSummary {
+    /// When the first test database of this process was created, in seconds since the UNIX epoch, or
+    /// `None` if none was.
+    pub started_at: Option<u64>,
+    /// Time since the first test database was created, in milliseconds.
+    pub elapsed_ms: u64,
+    /// Number of test databases created by this process.
+    pub databases_created: usize,
+    /// Number of test databases destroyed by this process.
+    pub databases_destroyed: usize,
+    /// Number of documents written by [TestRepo::with_data](crate::TestRepo::with_data) and the other
+    /// fixture loaders.
+    pub docs_seeded: u64,
+    /// Time spent creating test databases, in milliseconds.
+    pub create_ms: u64,
+    /// Time spent seeding test databases, in milliseconds.
+    pub seed_ms: u64,
+    /// Time spent destroying test databases, in milliseconds.
+    pub destroy_ms: u64,
+    /// The databases created but not destroyed, oldest first. In the summary written at exit, these
+    /// are the databases left when the process exited, before [teardown_all](crate::teardown_all)
+    /// destroyed them.
+    pub leaked: Vec<LeakedDatabase>,
+}
+
+/// Summarizes the test databases created by this process so far; see [RunSummary].
+///
+/// Setting the `COUCH_TEST_SUMMARY` environment variable writes this summary as JSON when the process
+/// exits: to standard error when set to `stderr`, and to the file it names otherwise.
+pub fn run_summary() -> RunSummary {
+    let report = leak_report();
+    let started = STARTED.get();
+    RunSummary {
+        started_at: started.map(|(_, secs)| *secs),
+        elapsed_ms: started
+            .map(|(instant, _)| instant.elapsed().as_millis() as u64)
+            .unwrap_or(0),
+        databases_created: report.created,
+        databases_destroyed: report.destroyed,
+        docs_seeded: DOCS_SEEDED.load(Ordering::SeqCst),
+        create_ms: CREATE_MICROS.load(Ordering::SeqCst) / 1000,
+        seed_ms: SEED_MICROS.load(Ordering::SeqCst) / 1000,
+        destroy_ms: DESTROY_MICROS.load(Ordering::SeqCst) / 1000,
+        leaked: report.leaked,
+    }
+}
+
+/// Records the creation of a test database, which took `elapsed`.
+pub(crate) fn record_created(elapsed: Duration) {
+    STARTED.get_or_init(|| {
+        let now = Instant::now();
+        (
+            now.checked_sub(elapsed).unwrap_or(now),
+            now_secs().saturating_sub(elapsed.as_secs()),
+        )
+    });
+    add(&CREATE_MICROS, elapsed);
+}
+
+/// Records the seeding of `docs` documents, which took `elapsed`.
+pub(crate) fn record_seeded(docs: usize, elapsed: Duration) {
+    DOCS_SEEDED.fetch_add(docs as u64, Ordering::SeqCst);
+    add(&SEED_MICROS, elapsed);
+}
+
+/// Records the destruction of a test database, which took `elapsed`.
+pub(crate) fn record_destroyed(elapsed: Duration) {
+    add(&DESTROY_MICROS, elapsed);
+}
+
+fn add(total: &AtomicU64, elapsed: Duration) {
+    total.fetch_add(elapsed.as_micros() as u64, Ordering::SeqCst);
+}
+
+/// Writes the summary where `COUCH_TEST_SUMMARY` selects, if anywhere, reporting `leaked` as the leaked
+/// databases.
+pub(crate) fn write_summary(leaked: Vec<LeakedDatabase>) {
+    let target = match std::env::var(SUMMARY_ENV_VAR) {
+        Ok(target) if !target.is_empty() => target,
+        _ => return,
+    };
+    let summary = RunSummary {
+        leaked,
+        ..run_summary()
+    };
+
+    let written = serde_json::to_string_pretty(&summary)
+        .map_err(|e| e.to_string())
+        .and_then(|json| match target.as_str() {
+            "stderr" => {
+                eprintln!("{}", json);
+                Ok(())
+            }
+            path => fs::write(path, json).map_err(|e| e.to_string()),
+        });
+    if let Err(e) = written {
+        log::error!("Failed to write run summary to {}: {}", target, e);
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{TestRepo, TestRepoConfig};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn emulated_databases_are_not_counted() {
+        let cfg = TestRepoConfig::new("", "", "", "users")
+            .named("summary::tests::emulated_databases_are_not_counted")
+            .with_mock();
+        let repo = TestRepo::new(cfg).await.unwrap();
+        let before = run_summary();
+        repo.with_data(&mut [json!({"name": "alice"}), json!({"name": "bob"})])
+            .await
+            .unwrap();
+        assert_eq!(run_summary().docs_seeded, before.docs_seeded);
+    }
+}
@@ -0,0 +1,205 @@
+use std::time::Duration;
+
+use couch_rs::error::CouchError;
+use serde_json::Value;
+use tokio::time::Instant;
+
+use crate::{
+    naming::percent_decode,
+    trace::trace_event,
+    wait::{timeout_error, POLL_INTERVAL},
+    TestRepo,
+};
+
+/// A background task running on the CouchDB server, as listed by `_active_tasks` and returned by
+/// [TestRepo::active_tasks]. Database names are given without the shard prefix and creation suffix of
+/// the shard files CouchDB reports.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ActiveTask {
+    /// Building or updating the view indexes of a design document.
+    Indexer {
+        /// Name of the database.
+        database: String,
+        /// Id of the design document, such as `_design/orders`.
+        design_document: String,
+        /// Percentage of the changes processed, if reported.
+        progress: Option<u64>,
+    },
+    /// Compacting a database.
+    DatabaseCompaction {
+        /// Name of the database.
+        database: String,
+        /// Percentage of the compaction done, if reported.
+        progress: Option<u64>,
+    },
+    /// Compacting the view indexes of a design document.
+    ViewCompaction {
+        /// Name of the database.
+        database: String,
+        /// Id of the design document.
+        design_document: String,
+        /// Percentage of the compaction done, if reported.
+        progress: Option<u64>,
+    },
+    /// Replicating documents between two databases.
+    Replication {
+        /// Url of the source database, with credentials redacted by CouchDB.
+        source: String,
+        /// Url of the target database, with credentials redacted by CouchDB.
+        target: String,
+        /// Id of the `_replicator` document defining the replication, unless started through
+        /// `_replicate`.
+        doc_id: Option<String>,
+        /// Number of documents written to the target so far.
+        docs_written: u64,
+        /// Whether the replication is continuous.
+        continuous: bool,
+    },
+    /// A task of another type, such as a search indexer, as reported by CouchDB.
+    Other(Value),
+}
+
+impl ActiveTask {
+    // reads a task as listed by _active_tasks
+    fn from_json(task: Value) -> ActiveTask {
+        let database = short_db_name(task["database"].as_str().unwrap_or_default());
+        let design_document = task["design_document"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        let progress = task["progress"].as_u64();
+        match task["type"].as_str() {
+            Some("indexer") => ActiveTask::Indexer {
+                database,
+                design_document,
+                progress,
+            },
+            Some("database_compaction") => ActiveTask::DatabaseCompaction { database, progress },
+            Some("view_compaction") => ActiveTask::ViewCompaction {
+                database,
+                design_document,
+                progress,
+            },
+            Some("replication") => ActiveTask::Replication {
+                source: url(&task["source"]),
+                target: url(&task["target"]),
+                doc_id: task["doc_id"].as_str().map(str::to_string),
+                docs_written: task["docs_written"].as_u64().unwrap_or(0),
+                continuous: task["continuous"].as_bool().unwrap_or(false),
+            },
+            _ => ActiveTask::Other(task),
+        }
+    }
+
+    // whether the task works on the database named `db_name`
+    fn concerns(&self, db_name: &str) -> bool {
+        match self {
+            ActiveTask::Indexer { database, .. }
+            | ActiveTask::DatabaseCompaction { database, .. }
+            | ActiveTask::ViewCompaction { database, .. } => database == db_name,
+            ActiveTask::Replication { source, target, .. } => {
+                url_db_name(source) == db_name || url_db_name(target) == db_name
+            }
+            ActiveTask::Other(task) => {
+                short_db_name(task["database"].as_str().unwrap_or_default()) == db_name
+            }
+        }
+    }
+}
+
+impl TestRepo {
+    /// Lists the background tasks the CouchDB server is running on the database associated with this
+    /// instance: view indexing, compaction of the database or its views, and replications from or to
+    /// it. Listing tasks requires the configured user to be a server admin.
+    ///
+    /// ```rust
+    /// use couch_rs_test::ActiveTask;
+    ///
+    /// # async fn example(repo: &couch_rs_test::TestRepo) -> Result<(), couch_rs::error::CouchError> {
+    /// for task in repo.active_tasks().await? {
+    ///     if let ActiveTask::Indexer { design_document, progress, .. } = task {
+    ///         println!("{} indexed to {:?}%", design_document, progress);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn active_tasks(&self) -> Result<Vec<ActiveTask>, CouchError> {
+        let tasks = self.get_json("_active_tasks").await?;
+        Ok(tasks
+            .as_array()
+            .into_iter()
+            .flatten()
+            .cloned()
+            .map(ActiveTask::from_json)
+            .filter(|task| task.concerns(&self.cfg.db_name))
+            .collect())
+    }
+
+    /// Polls [TestRepo::active_tasks] until the server runs no background task on the database
+    /// associated with this instance, so that assertions on timings or sizes are not skewed by indexing
+    /// or compaction still in progress. Continuous replications never finish, and so must be cancelled
+    /// first.
+    ///
+    /// If tasks are still running when `timeout` elapses, an error with status `REQUEST_TIMEOUT` is
+    /// returned listing them.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "couch_rs_test::await_no_active_tasks", skip(self), fields(db = %self.db.name()))
+    )]
+    pub async fn await_no_active_tasks(&self, timeout: Duration) -> Result<(), CouchError> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let tasks = self.active_tasks().await?;
+            if tasks.is_empty() {
+                trace_event!("no active tasks");
+                return Ok(());
+            }
+
+            if Instant::now() >= deadline {
+                return Err(timeout_error(format!(
+                    "Timed out after {:?} waiting for the active tasks of database {} to finish: {:?}",
+                    timeout, self.cfg.db_name, tasks
+                )));
+            }
+
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+}
+
+// the name of the database of a shard file such as `shards/00000000-7fffffff/orders.1712345678`, or
+// of a database file of a single node such as `orders`
+fn short_db_name(database: &str) -> String {
+    match database.strip_prefix("shards/") {
+        Some(shard) => {
+            let file = shard.split_once('/').map(|(_, file)| file).unwrap_or(shard);
+            file.rsplit_once('.')
+                .map(|(name, _)| name)
+                .unwrap_or(file)
+                .to_string()
+        }
+        None => database.to_string(),
+    }
+}
+
+// the url of a replication endpoint, which CouchDB reports as a string or, in older versions, as an
+// object with a `url` field
+fn url(endpoint: &Value) -> String {
+    endpoint
+        .as_str()
+        .or_else(|| endpoint["url"].as_str())
+        .unwrap_or_default()
+        .to_string()
+}
+
+// the name of the database at the end of the path of `url`
+fn url_db_name(url: &str) -> String {
+    percent_decode(
+        url.trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .unwrap_or_default(),
+    )
+}
@@ -0,0 +1,14 @@
+//! Internal helpers for the optional `tracing` feature. Without the feature, the macros expand to
+//! nothing so that call sites do not need their own `cfg` attributes.
+
+/// Emits a `tracing` event at info level when the `tracing` feature is enabled.
+macro_rules! trace_event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        {
+            tracing::info!($($arg)*);
+        }
+    };
+}
+
+pub(crate) use trace_event;
@@ -0,0 +1,143 @@
+use couch_rs::error::CouchError;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::{
+    compat::{Method, StatusCode},
+    docs::encode_doc_id,
+    naming::encode_db_name,
+    TestRepo,
+};
+
+/// The ways a `validate_doc_update` function can reject a write, matching the error it throws.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rejection {
+    /// `throw({forbidden: reason})`; CouchDB responds with status 403.
+    Forbidden,
+    /// `throw({unauthorized: reason})`; CouchDB responds with status 401.
+    Unauthorized,
+}
+
+impl Rejection {
+    fn error(&self) -> &'static str {
+        match self {
+            Rejection::Forbidden => "forbidden",
+            Rejection::Unauthorized => "unauthorized",
+        }
+    }
+}
+
+impl TestRepo {
+    /// Installs `function`, the JavaScript source of a `validate_doc_update` function, in the design
+    /// document `_design/{ddoc}` of the database associated with this instance, replacing an existing
+    /// design document of that name.
+    ///
+    /// ```rust
+    /// # async fn example(repo: &couch_rs_test::TestRepo) -> Result<(), couch_rs::error::CouchError> {
+    /// repo.install_validate_doc_update(
+    ///     "validation",
+    ///     "function(newDoc) { if (!newDoc.owner) { throw({forbidden: 'owner is required'}); } }",
+    /// )
+    /// .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn install_validate_doc_update(
+        &self,
+        ddoc: &str,
+        function: &str,
+    ) -> Result<(), CouchError> {
+        let mut design_doc = json!({
+            "_id": format!("_design/{}", ddoc),
+            "validate_doc_update": function,
+        });
+        if let Ok(existing) = self.db.get_raw(&format!("_design/{}", ddoc)).await {
+            design_doc["_rev"] = existing["_rev"].clone();
+        }
+
+        match self.write_raw(&design_doc).await? {
+            (status, _) if status.is_success() => Ok(()),
+            (status, error) => Err(CouchError::new(
+                format!(
+                    "Failed to install validate_doc_update in _design/{}: {}",
+                    ddoc, error
+                ),
+                status,
+            )),
+        }
+    }
+
+    /// Asserts that writing `doc` to the database associated with this instance is rejected by a
+    /// `validate_doc_update` function with the given kind of rejection and a reason containing `reason`.
+    /// To assert on an update, `doc` must carry the `_id` and current `_rev` of the document to update.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the write succeeds, or fails in any other way.
+    pub async fn assert_write_rejected<T: Serialize>(
+        &self,
+        doc: &T,
+        rejection: Rejection,
+        reason: &str,
+    ) {
+        let body = serde_json::to_value(doc).expect("document must serialize to JSON");
+        let (status, error) = self
+            .write_raw(&body)
+            .await
+            .unwrap_or_else(|e| panic!("Failed to write document: {}", e));
+
+        if status.is_success() {
+            panic!(
+                "Expected write to be rejected as {} ({}), but it was accepted: {}",
+                rejection.error(),
+                reason,
+                body
+            );
+        }
+        assert!(
+            error["error"] == rejection.error()
+                && error["reason"].as_str().unwrap_or_default().contains(reason),
+            "Expected write to be rejected as {} with a reason containing {:?}, but it failed with status {}: {}",
+            rejection.error(),
+            reason,
+            status,
+            error
+        );
+    }
+
+    /// Asserts that writing `doc` to the database associated with this instance is accepted, typically
+    /// alongside [TestRepo::assert_write_rejected] to cover both sides of a validation rule.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the write fails.
+    pub async fn assert_write_accepted<T: Serialize>(&self, doc: &T) {
+        let body = serde_json::to_value(doc).expect("document must serialize to JSON");
+        let (status, error) = self
+            .write_raw(&body)
+            .await
+            .unwrap_or_else(|e| panic!("Failed to write document: {}", e));
+        if !status.is_success() {
+            panic!(
+                "Expected write to be accepted, but it failed with status {}: {}",
+                status, error
+            );
+        }
+    }
+
+    // writes a document without interpreting the response, so validation errors can be inspected;
+    // returns the status and body of the response
+    pub(crate) async fn write_raw(&self, doc: &Value) -> Result<(StatusCode, Value), CouchError> {
+        let db_name = encode_db_name(&self.cfg.db_name);
+        let request = match doc["_id"].as_str() {
+            Some(id) => {
+                let path = format!("{}/{}", db_name, encode_doc_id(id));
+                self.client.req(Method::PUT, &path, None)
+            }
+            None => self.client.req(Method::POST, &db_name, None),
+        };
+        let response = request.body(doc.to_string()).send().await?;
+        let status = response.status();
+        Ok((status, response.json().await.unwrap_or_default()))
+    }
+}
@@ -0,0 +1,106 @@
+use couch_rs::error::CouchError;
+use serde_json::Value;
+
+use crate::{
+    compat::{Method, StatusCode},
+    docs::encode_doc_id,
+    naming::encode_db_name,
+    TestRepo,
+};
+
+/// Id of the document holding the schema version of a database, unless configured otherwise with
+/// [TestRepoConfig::with_schema_version_doc](crate::TestRepoConfig::with_schema_version_doc).
+pub(crate) const DEFAULT_SCHEMA_VERSION_ID: &str = "_local/schema_version";
+
+/// Field of the schema version document holding the version, unless configured otherwise.
+pub(crate) const DEFAULT_SCHEMA_VERSION_FIELD: &str = "version";
+
+impl TestRepo {
+    /// Reads the schema version recorded in the database associated with this instance, returning
+    /// `None` if the schema version document does not exist. The version is read from the `version`
+    /// field of the `_local/schema_version` document, or from the document and field set with
+    /// [TestRepoConfig::with_schema_version_doc](crate::TestRepoConfig::with_schema_version_doc).
+    ///
+    /// An error with status `INTERNAL_SERVER_ERROR` is returned if the document exists but its version
+    /// field does not hold a non-negative integer.
+    pub async fn schema_version(&self) -> Result<Option<u64>, CouchError> {
+        let (id, field) = &self.cfg.schema_version_doc;
+        let doc = match self.get_typed::<Value>(id).await? {
+            Some(doc) => doc,
+            None => return Ok(None),
+        };
+        match doc[field.as_str()].as_u64() {
+            Some(version) => Ok(Some(version)),
+            None => Err(CouchError::new(
+                format!(
+                    "Schema version document {} has no integer `{}` field: {}",
+                    id, field, doc
+                ),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )),
+        }
+    }
+
+    /// Records `version` as the schema version of the database associated with this instance, creating
+    /// the schema version document or updating its version field, for tests starting from a database
+    /// that is already at some version, such as tests of a single migration step.
+    pub async fn set_schema_version(&self, version: u64) -> Result<(), CouchError> {
+        let (id, field) = &self.cfg.schema_version_doc;
+        let mut doc = self
+            .get_typed::<Value>(id)
+            .await?
+            .unwrap_or_else(|| Value::Object(Default::default()));
+        doc[field.as_str()] = Value::from(version);
+
+        self.client
+            .req(
+                Method::PUT,
+                &format!(
+                    "{}/{}",
+                    encode_db_name(&self.cfg.db_name),
+                    encode_doc_id(&self.namespaced_id(id))
+                ),
+                None,
+            )
+            .body(doc.to_string())
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Asserts that the database associated with this instance records the schema version `expected`,
+    /// typically after the application under test has bootstrapped against the fresh database, to
+    /// catch bootstrapping that forgot to run the migrations.
+    ///
+    /// ```rust
+    /// # async fn example(repo: &couch_rs_test::TestRepo) {
+    /// // app::bootstrap(&repo.db).await;
+    /// repo.assert_schema_version(3).await;
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the recorded version differs, the schema version document does not exist, or it
+    /// cannot be read.
+    pub async fn assert_schema_version(&self, expected: u64) {
+        let version = self.schema_version().await.unwrap_or_else(|e| {
+            panic!(
+                "Failed to read the schema version of {}: {}",
+                self.cfg.db_name, e
+            )
+        });
+        match version {
+            Some(version) => assert_eq!(
+                version, expected,
+                "Database {} is at schema version {}, expected {}",
+                self.cfg.db_name, version, expected
+            ),
+            None => panic!(
+                "Database {} records no schema version, expected {}; were the migrations run?",
+                self.cfg.db_name, expected
+            ),
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use std::{future::Future, time::Duration};
+
+use couch_rs::{database::Database, document::TypedCouchDocument, error::CouchError};
+use tokio::time::Instant;
+
+use crate::{compat::StatusCode, trace::trace_event, TestRepo};
+
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+impl TestRepo {
+    /// Polls the database associated with this instance until a document with the given id exists,
+    /// returning the document. This is intended for tests where the code under test writes asynchronously,
+    /// for example through a queue or a background task.
+    ///
+    /// If the document does not appear before `timeout` elapses, an error with status
+    /// `REQUEST_TIMEOUT` is returned.
+    pub async fn wait_for_doc<T: TypedCouchDocument>(
+        &self,
+        id: &str,
+        timeout: Duration,
+    ) -> Result<T, CouchError> {
+        self.wait_for_doc_matching(id, timeout, |_: &T| true).await
+    }
+
+    /// Polls the database associated with this instance until a document with the given id exists and
+    /// satisfies `predicate`, returning the document. Use this instead of [TestRepo::wait_for_doc] when the
+    /// document already exists and the test is waiting on an update to it.
+    ///
+    /// If no matching document is found before `timeout` elapses, an error with status `REQUEST_TIMEOUT`
+    /// is returned. In a shared database, `id` is looked up within this instance's
+    /// [namespace](TestRepo::namespace).
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "couch_rs_test::wait_for_doc", skip(self, predicate), fields(db = %self.db.name()))
+    )]
+    pub async fn wait_for_doc_matching<T, F>(
+        &self,
+        id: &str,
+        timeout: Duration,
+        predicate: F,
+    ) -> Result<T, CouchError>
+    where
+        T: TypedCouchDocument,
+        F: Fn(&T) -> bool,
+    {
+        let deadline = Instant::now() + timeout;
+        let id = self.namespaced_id(id);
+
+        loop {
+            match self.db.get::<T>(&id).await {
+                Ok(doc) if predicate(&doc) => {
+                    trace_event!("document found");
+                    return Ok(doc);
+                }
+                Ok(_) => {}
+                Err(e) if e.is_not_found() => {}
+                Err(e) => return Err(e),
+            }
+
+            if Instant::now() >= deadline {
+                return Err(timeout_error(format!(
+                    "Timed out after {:?} waiting for document {} in database {}",
+                    timeout,
+                    id,
+                    self.db.name()
+                )));
+            }
+
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Polls the database information of the database associated with this instance until its
+    /// `doc_count` equals `expected`. This is intended for tests where the code under test writes a known
+    /// number of documents asynchronously. Design documents count towards `doc_count`; deleted and local
+    /// documents do not. In a shared database, documents are counted as by [TestRepo::assert_doc_count].
+    ///
+    /// If the count does not reach `expected` before `timeout` elapses, an error with status
+    /// `REQUEST_TIMEOUT` is returned naming the last count seen.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "couch_rs_test::await_doc_count", skip(self), fields(db = %self.db.name()))
+    )]
+    pub async fn await_doc_count(&self, expected: u64, timeout: Duration) -> Result<(), CouchError> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let count = self.doc_count().await?;
+            if count == expected {
+                trace_event!(count, "document count reached");
+                return Ok(());
+            }
+
+            if Instant::now() >= deadline {
+                return Err(timeout_error(format!(
+                    "Timed out after {:?} waiting for database {} to hold {} documents; it holds {}",
+                    timeout,
+                    self.db.name(),
+                    expected,
+                    count
+                )));
+            }
+
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Retries an asynchronous predicate against the database associated with this instance until it
+    /// returns `true`, sleeping `interval` between attempts. This standardizes the retry loop needed for
+    /// assertions against eventually-consistent state, such as view indexes or data written by background
+    /// tasks.
+    ///
+    /// The predicate receives a clone of [TestRepo::db](TestRepo#structfield.db). If the predicate has not
+    /// passed before `timeout` elapses, an error with status `REQUEST_TIMEOUT` is returned describing the
+    /// number of attempts made.
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # async fn example(repo: &couch_rs_test::TestRepo) {
+    /// repo.eventually(
+    ///     |db| async move { db.exists("written-by-worker").await },
+    ///     Duration::from_secs(5),
+    ///     Duration::from_millis(250),
+    /// )
+    /// .await
+    /// .expect("worker never wrote its document");
+    /// # }
+    /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "couch_rs_test::eventually", skip_all, fields(db = %self.db.name()))
+    )]
+    pub async fn eventually<F, Fut>(
+        &self,
+        mut predicate: F,
+        timeout: Duration,
+        interval: Duration,
+    ) -> Result<(), CouchError>
+    where
+        F: FnMut(Database) -> Fut,
+        Fut: Future<Output = bool>,
+    {
+        let start = Instant::now();
+        let mut attempts: usize = 0;
+
+        loop {
+            attempts += 1;
+            if predicate(self.db.clone()).await {
+                trace_event!(attempts, "condition passed");
+                return Ok(());
+            }
+
+            if start.elapsed() >= timeout {
+                return Err(timeout_error(format!(
+                    "Condition on database {} did not pass after {} attempts over {:?}",
+                    self.db.name(),
+                    attempts,
+                    start.elapsed()
+                )));
+            }
+
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
+
+pub(crate) fn timeout_error(message: String) -> CouchError {
+    CouchError::new(message, StatusCode::REQUEST_TIMEOUT)
+}